@@ -1,33 +1,46 @@
 use alloy_primitives::U256;
-use anyhow::{bail, Result};
-use hdp_primitives::datalake::{
-    block_sampled::{AccountField, BlockSampledCollectionType, BlockSampledDatalake, HeaderField},
-    datalake_type::DatalakeType,
-    envelope::DatalakeEnvelope,
-    transactions::{
-        TransactionField, TransactionReceiptField, TransactionsCollectionType,
-        TransactionsInBlockDatalake,
+use anyhow::{bail, Context, Result};
+use hdp_primitives::{
+    block::tag::BlockNumberOrTag,
+    datalake::{
+        block_sampled::{
+            AccountField, BlockSampledCollectionType, BlockSampledDatalake, HeaderField,
+        },
+        datalake_type::DatalakeType,
+        envelope::DatalakeEnvelope,
+        output::ProcessedResult,
+        time_range::{ResolvedTimeRange, TimeRange},
+        transactions::{
+            TransactionField, TransactionReceiptField, TransactionsCollectionType,
+            TransactionsInBlockDatalake,
+        },
     },
+    mmr::HashingFunction,
 };
 use inquire::{error::InquireError, Select};
-use std::{str::FromStr, sync::Arc, vec};
+use std::{collections::HashMap, str::FromStr, vec};
 use tracing_subscriber::FmtSubscriber;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use hdp_core::{
     aggregate_fn::{integer::Operator, FunctionContext},
     codec::{
         datalake_decoder, datalakes_decoder, datalakes_encoder, task_decoder, tasks_decoder,
         tasks_encoder,
     },
-    config::Config,
-    evaluator::evaluator,
+    config::{indexer_page_size, Config, EvaluatorOptions, FinalityOptions, IndexerOptions},
+    estimator::{estimate, EstimatorConfig},
+    evaluator::{
+        evaluator,
+        finality::FinalityPolicy,
+        progress::{CancellationToken, EvaluationHooks},
+        EvaluationResult,
+    },
     task::ComputationalTask,
 };
 
 use hdp_provider::evm::AbstractProvider;
 
-use tokio::sync::RwLock;
 use tracing::{debug, error, info, Level};
 
 /// Simple Herodotus Data Processor CLI to handle tasks and datalakes
@@ -39,6 +52,58 @@ struct Cli {
     command: Commands,
 }
 
+/// Run settings that can also be given in the environment or in the config file
+#[derive(Args, Clone, Debug, Default)]
+struct RunConfigArgs {
+    /// Path to the toml config file (default: `HDP_CONFIG` or ./hdp.toml if it exists)
+    #[arg(long)]
+    config: Option<String>,
+    /// Url of the MMR indexer to fetch headers and MMR proofs from
+    #[arg(long)]
+    indexer_url: Option<String>,
+    /// Hashing function of the MMR to fetch proofs from: "poseidon" or "keccak"
+    #[arg(long)]
+    hashing_function: Option<HashingFunction>,
+    /// Type of the contract the MMR is accumulated in, e.g. "AGGREGATOR"
+    #[arg(long)]
+    contract_type: Option<String>,
+    /// Build the header MMR locally in this file from RPC headers, instead of using the indexer
+    #[arg(long)]
+    local_mmr: Option<String>,
+    /// What to do when sampled blocks are not final yet: "refuse", "warn" or "wait"
+    #[arg(long)]
+    finality_policy: Option<FinalityPolicy>,
+    /// Blocks this deep below the chain head are final (default: the chain's finalized block)
+    #[arg(long)]
+    confirmation_depth: Option<u64>,
+    /// Maximum number of datalakes compiled at the same time (default: 4)
+    #[arg(long)]
+    max_concurrent_datalakes: Option<usize>,
+    /// Directory of the evaluation checkpoints (default: .hdp/checkpoints)
+    #[arg(long)]
+    checkpoint_dir: Option<String>,
+    /// Resume the evaluation of the same batch from its checkpoint, after a failed run
+    #[arg(long)]
+    resume: bool,
+    /// Stop the evaluation if it takes longer than this many seconds
+    #[arg(long)]
+    evaluation_timeout_secs: Option<u64>,
+}
+
+/// Files the results of a run are saved to
+#[derive(Args, Clone, Debug, Default)]
+struct OutputArgs {
+    /// Path to the file to save the output result
+    #[arg(short, long)]
+    output_file: Option<String>,
+    /// Path to the file to save the input.json in cairo format
+    #[arg(short, long)]
+    cairo_input: Option<String>,
+    /// Path to the file to save the full evaluation result, that `hdp merge` can load back
+    #[arg(short, long)]
+    evaluation_file: Option<String>,
+}
+
 #[derive(Debug, Subcommand)]
 enum Commands {
     /// New to the HDP CLI? Start here!
@@ -60,18 +125,17 @@ enum Commands {
         #[command(subcommand)]
         command: DataLakeCommands,
 
-        /// The RPC URL to fetch the data
+        /// The RPC URL to fetch the data, several comma separated URLs are used for failover
         rpc_url: Option<String>,
 
         /// The chain id to fetch the data
         chain_id: Option<u64>,
 
-        /// Path to the file to save the output result
-        #[arg(short, long)]
-        output_file: Option<String>,
-        /// Path to the file to save the input.json in cairo format
-        #[arg(short, long)]
-        cairo_input: Option<String>,
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[command(flatten)]
+        run_config: RunConfigArgs,
     },
     /// Decode batch tasks and datalakes
     ///
@@ -93,17 +157,70 @@ enum Commands {
         tasks: Option<String>,
         /// Batched datalakes bytes
         datalakes: Option<String>,
-        /// The RPC URL to fetch the data
+        /// The RPC URL to fetch the data, several comma separated URLs are used for failover
         rpc_url: Option<String>,
         /// The chain id to fetch the data
         chain_id: Option<u64>,
-        /// Path to the file to save the output result
-        #[arg(short, long)]
-        output_file: Option<String>,
 
-        /// Path to the file to save the input.json in cairo format
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[command(flatten)]
+        run_config: RunConfigArgs,
+    },
+    /// Merge evaluation results saved by separate runs into one batch, and export it
+    /// without fetching chain data, unless the headers are proven again with `--rpc-url`
+    #[command(arg_required_else_help = true)]
+    Merge {
+        /// Evaluation result files saved with `--evaluation-file`, merged in this order
+        #[arg(required = true)]
+        evaluation_files: Vec<String>,
+
+        /// The RPC URL to prove the headers of the runs again against the latest states of
+        /// their MMRs, to merge runs proven against different states of an MMR
+        #[arg(long)]
+        rpc_url: Option<String>,
+
+        /// The chain id to fetch the data (default: the chain of the merged runs)
+        #[arg(long)]
+        chain_id: Option<u64>,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[command(flatten)]
+        run_config: RunConfigArgs,
+    },
+    /// Extract the proof that the result of a task is in the results root of an output file,
+    /// as JSON with the ABI encoded calldata for a Solidity verifier
+    #[command(arg_required_else_help = true)]
+    ProveResult {
+        /// Output file saved with `--output-file`
+        output_file: String,
+        /// Commitment of the task to prove the result of
+        task_commitment: String,
+        /// Path to the file to save the proof bundle (printed if not set)
         #[arg(short, long)]
-        cairo_input: Option<String>,
+        proof_file: Option<String>,
+    },
+    /// Estimate the headers, proofs, RPC calls, output size and Cairo steps of a batch,
+    /// without fetching chain data
+    #[command(arg_required_else_help = true)]
+    Estimate {
+        /// Batched tasks bytes
+        tasks: String,
+        /// Batched datalakes bytes
+        datalakes: String,
+        /// Transactions assumed in the blocks of transactions datalakes (default: 150)
+        #[arg(long)]
+        transactions_per_block: Option<u64>,
+        /// Print the estimate as JSON
+        #[arg(long)]
+        json: bool,
+        /// Path to the toml config file the indexer page size is read from
+        /// (default: `HDP_CONFIG` or ./hdp.toml if it exists)
+        #[arg(long)]
+        config: Option<String>,
     },
 }
 
@@ -113,10 +230,25 @@ enum DataLakeCommands {
     #[command(arg_required_else_help = true)]
     #[command(short_flag = 'b')]
     BlockSampled {
-        /// Block number range start
-        block_range_start: u64,
-        /// Block number range end
-        block_range_end: u64,
+        /// Block number range start, or a block tag: "latest" (or "head"), "safe", "finalized", e.g. "finalized-1000"
+        block_range_start: BlockNumberOrTag,
+        /// Block number range end, or a block tag
+        block_range_end: BlockNumberOrTag,
+        /// Sampled property e.g. "header.number", "account.0xaccount.balance", "storage.0xcontract.0xstoragekey"
+        sampled_property: String,
+        /// Increment number of given range blocks
+        #[arg(default_value_t = 1)]
+        increment: u64,
+    },
+
+    ///  Encode the block sampled data lake over the blocks of a time range, resolved with the RPC
+    #[command(arg_required_else_help = true)]
+    #[command(short_flag = 'd')]
+    BlockSampledInTime {
+        /// Start of the time range: unix timestamp, "YYYY-MM-DD" or "YYYY-MM-DDTHH:MM:SSZ" in UTC
+        start_time: String,
+        /// End of the time range, included. A date includes the whole day
+        end_time: String,
         /// Sampled property e.g. "header.number", "account.0xaccount.balance", "storage.0xcontract.0xstoragekey"
         sampled_property: String,
         /// Increment number of given range blocks
@@ -128,8 +260,8 @@ enum DataLakeCommands {
     #[command(arg_required_else_help = true)]
     #[command(short_flag = 't')]
     TransactionsInBlock {
-        /// target block number
-        target_block: u64,
+        /// target block number, or a block tag: "latest" (or "head"), "safe", "finalized", e.g. "finalized-1000"
+        target_block: BlockNumberOrTag,
         /// Sampled property
         /// Fields from transaction: "chain_id", "gas_price"... etc
         /// Fields from transaction receipt: "cumulative_gas_used".. etc
@@ -181,39 +313,180 @@ async fn handle_encode_multiple(
     })
 }
 
+fn load_config(
+    tasks: Option<String>,
+    datalakes: Option<String>,
+    rpc_url: Option<String>,
+    chain_id: Option<u64>,
+    run_config: RunConfigArgs,
+) -> Result<Config> {
+    Config::init(
+        rpc_url,
+        datalakes,
+        tasks,
+        chain_id,
+        run_config.config,
+        IndexerOptions {
+            url: run_config.indexer_url,
+            hashing_function: run_config.hashing_function,
+            contract_type: run_config.contract_type,
+            local_mmr_path: run_config.local_mmr,
+        },
+        EvaluatorOptions {
+            finality: FinalityOptions {
+                policy: run_config.finality_policy,
+                confirmation_depth: run_config.confirmation_depth,
+            },
+            max_concurrent_datalakes: run_config.max_concurrent_datalakes,
+            checkpoint_dir: run_config.checkpoint_dir,
+            resume: run_config.resume,
+            timeout_secs: run_config.evaluation_timeout_secs,
+        },
+    )
+}
+
+/// Provider of the run, to resolve the blocks of a datalake while encoding it
+fn encode_provider(
+    rpc_url: Option<String>,
+    chain_id: Option<u64>,
+    run_config: RunConfigArgs,
+) -> Result<AbstractProvider> {
+    let (provider, _) = load_provider(
+        rpc_url,
+        chain_id,
+        run_config,
+        "to resolve the blocks of the datalake",
+    )?;
+    Ok(provider)
+}
+
+/// Provider and config of a run without a batch to evaluate, `purpose` tells what the
+/// RPC is needed for when it is not set
+fn load_provider(
+    rpc_url: Option<String>,
+    chain_id: Option<u64>,
+    run_config: RunConfigArgs,
+    purpose: &str,
+) -> Result<(AbstractProvider, Config)> {
+    let rpc_url = rpc_url
+        .or_else(|| std::env::var("RPC_URL").ok())
+        .with_context(|| format!("An RPC URL is needed {}, set RPC_URL", purpose))?;
+    let chain_id = match chain_id {
+        Some(chain_id) => chain_id,
+        None => std::env::var("CHAIN_ID")
+            .with_context(|| format!("A chain id is needed {}, set CHAIN_ID", purpose))?
+            .parse()?,
+    };
+    // there is no batch to evaluate, only the provider settings are used
+    let config = load_config(
+        Some(String::new()),
+        Some(String::new()),
+        Some(rpc_url),
+        Some(chain_id),
+        run_config,
+    )?;
+    let provider = AbstractProvider::new_with_config(
+        config.rpc_endpoints.clone(),
+        config.chain_id,
+        config.provider_config.clone(),
+    )?;
+    Ok((provider, config))
+}
+
+/// Resolve blocks given by number or tag to block numbers, the RPC is only used for tags
+async fn resolve_blocks(
+    blocks: &[BlockNumberOrTag],
+    rpc_url: Option<String>,
+    chain_id: Option<u64>,
+    run_config: RunConfigArgs,
+) -> Result<Vec<u64>> {
+    if blocks.iter().all(|block| block.tag().is_none()) {
+        return blocks
+            .iter()
+            .map(|block| block.resolve(&HashMap::new()))
+            .collect();
+    }
+    encode_provider(rpc_url, chain_id, run_config)?
+        .resolve_blocks(blocks)
+        .await
+}
+
+/// Resolve a time range to the blocks inside it, with the provider of the run
+async fn resolve_time_range(
+    start_time: &str,
+    end_time: &str,
+    rpc_url: Option<String>,
+    chain_id: Option<u64>,
+    run_config: RunConfigArgs,
+) -> Result<ResolvedTimeRange> {
+    let time_range = TimeRange::parse(start_time, end_time)?;
+    let provider = encode_provider(rpc_url, chain_id, run_config)?;
+    let resolved = provider.resolve_time_range(time_range).await?;
+    info!(
+        "Time range {}..={} resolved to blocks {} (timestamp {}) to {} (timestamp {})",
+        resolved.start_timestamp,
+        resolved.end_timestamp,
+        resolved.first_block,
+        resolved.first_block_timestamp,
+        resolved.last_block,
+        resolved.last_block_timestamp
+    );
+    Ok(resolved)
+}
+
+/// Run the evaluator, recording the time ranges the block ranges of datalakes were resolved from
 async fn handle_run(
     tasks: Option<String>,
     datalakes: Option<String>,
     rpc_url: Option<String>,
     chain_id: Option<u64>,
-    output_file: Option<String>,
-    cairo_input: Option<String>,
+    output: OutputArgs,
+    run_config: RunConfigArgs,
+    time_ranges: Vec<(DatalakeEnvelope, ResolvedTimeRange)>,
 ) -> Result<()> {
-    let config = Config::init(rpc_url, datalakes, tasks, chain_id).await;
-    let provider = AbstractProvider::new(&config.rpc_url, config.chain_id);
+    let config = load_config(tasks, datalakes, rpc_url, chain_id, run_config)?;
+    let provider = AbstractProvider::new_with_config(
+        config.rpc_endpoints.clone(),
+        config.chain_id,
+        config.provider_config.clone(),
+    )?;
 
     let decoded_result =
         handle_decode_multiple(config.datalakes.clone(), config.tasks.clone()).await?;
 
+    // Stop the evaluation cleanly on Ctrl-C, so that it is checkpointed
+    let cancellation = CancellationToken::new();
+    tokio::spawn({
+        let cancellation = cancellation.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                info!("Cancelling the evaluation");
+                cancellation.cancel();
+            }
+        }
+    });
+    let hooks = EvaluationHooks {
+        cancellation: Some(cancellation),
+        ..Default::default()
+    };
+
     match evaluator(
         decoded_result.tasks,
         decoded_result.datalakes,
-        Arc::new(RwLock::new(provider)),
+        &provider,
+        &config.evaluator_config,
+        &hooks,
     )
     .await
     {
-        Ok(res) => {
-            debug!("Result: {:#?}", res);
-
-            if let Some(output_file) = output_file {
-                res.save_to_file(&output_file, false)?;
-                info!("Output file saved to: {}", output_file);
-            }
-            if let Some(cairo_input) = cairo_input {
-                res.save_to_file(&cairo_input, true)?;
-                info!("Cairo input file saved to: {}", cairo_input);
+        Ok(mut evaluation) => {
+            for (datalake, time_range) in &time_ranges {
+                evaluation.result.record_time_range(datalake, *time_range)?;
             }
-
+            debug!("Result: {:#?}", evaluation.result);
+            save_output(&evaluation.result, output)?;
+            // the batch can be resumed until its results are exported
+            evaluation.complete();
             Ok(())
         }
         Err(e) => {
@@ -223,6 +496,110 @@ async fn handle_run(
     }
 }
 
+/// Merge the evaluation results, proving their headers again if an RPC URL is given
+async fn handle_merge(
+    evaluation_files: Vec<String>,
+    rpc_url: Option<String>,
+    chain_id: Option<u64>,
+    output: OutputArgs,
+    run_config: RunConfigArgs,
+) -> Result<()> {
+    let mut evaluation_files = evaluation_files.into_iter();
+    let Some(first_file) = evaluation_files.next() else {
+        bail!("No evaluation result to merge");
+    };
+    let mut merged_result = EvaluationResult::load_from_file(&first_file)?;
+    match rpc_url {
+        Some(rpc_url) => {
+            let evaluation_results = evaluation_files
+                .map(|evaluation_file| EvaluationResult::load_from_file(&evaluation_file))
+                .collect::<Result<Vec<_>>>()?;
+            let (provider, config) = load_provider(
+                Some(rpc_url),
+                chain_id.or(merged_result.chain_id),
+                run_config,
+                "to prove the headers of the merged runs again",
+            )?;
+            merged_result
+                .merge_and_reprove(evaluation_results, &provider, &config.evaluator_config)
+                .await?;
+        }
+        None => {
+            for evaluation_file in evaluation_files {
+                let evaluation_result = EvaluationResult::load_from_file(&evaluation_file)?;
+                merged_result
+                    .merge(evaluation_result)
+                    .with_context(|| format!("Failed to merge {}", evaluation_file))?;
+            }
+        }
+    }
+    info!("Merged {} tasks", merged_result.ordered_tasks.len());
+    save_output(&merged_result, output)
+}
+
+fn handle_prove_result(
+    output_file: String,
+    task_commitment: String,
+    proof_file: Option<String>,
+) -> Result<()> {
+    let json = std::fs::read_to_string(&output_file)
+        .with_context(|| format!("Failed to read output file {}", output_file))?;
+    let processed_result: ProcessedResult = serde_json::from_str(&json)
+        .with_context(|| format!("Invalid output file {}", output_file))?;
+    let result_proof = processed_result.get_result_proof(&task_commitment)?;
+
+    let mut proof_bundle = serde_json::to_value(&result_proof)?;
+    proof_bundle["calldata"] = result_proof.to_calldata().into();
+    let proof_bundle = serde_json::to_string_pretty(&proof_bundle)?;
+    match proof_file {
+        Some(proof_file) => {
+            std::fs::write(&proof_file, proof_bundle)?;
+            info!("Result proof saved to: {}", proof_file);
+        }
+        None => println!("{}", proof_bundle),
+    }
+    Ok(())
+}
+
+async fn handle_estimate(
+    tasks: String,
+    datalakes: String,
+    transactions_per_block: Option<u64>,
+    json: bool,
+    config_file: Option<String>,
+) -> Result<()> {
+    let decoded_result = handle_decode_multiple(datalakes, tasks).await?;
+    let config = EstimatorConfig {
+        indexer_page_size: indexer_page_size(config_file)?,
+        transactions_per_block: transactions_per_block
+            .unwrap_or(EstimatorConfig::default().transactions_per_block),
+    };
+    let batch_estimate = estimate(decoded_result.tasks, decoded_result.datalakes, &config);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&batch_estimate)?);
+    } else {
+        print!("{}", batch_estimate);
+    }
+    Ok(())
+}
+
+fn save_output(res: &EvaluationResult, output: OutputArgs) -> Result<()> {
+    if let Some(output_file) = output.output_file {
+        res.save_to_file(&output_file, false)?;
+        info!("Output file saved to: {}", output_file);
+    }
+    if let Some(cairo_input) = output.cairo_input {
+        res.save_to_file(&cairo_input, true)?;
+        info!("Cairo input file saved to: {}", cairo_input);
+    }
+    if let Some(evaluation_file) = output.evaluation_file {
+        res.save_evaluation_to_file(&evaluation_file)?;
+        info!("Evaluation result saved to: {}", evaluation_file);
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let start_run = std::time::Instant::now();
@@ -246,6 +623,28 @@ async fn main() -> Result<()> {
     "
             );
 
+            // the chain is needed to resolve tagged blocks, so it is asked first
+            let rpc_url: Option<String> = match inquire::Text::new("Enter RPC URL: ")
+                .with_help_message("Skip if you have it in your .env file")
+                .prompt()
+            {
+                Ok(url) => match url.as_str() {
+                    "" => None,
+                    _ => Some(url),
+                },
+                Err(_) => None,
+            };
+            let chain_id: Option<u64> = match inquire::Text::new("Enter Chain ID: ")
+                .with_help_message("Skip if you have it in your .env file")
+                .prompt()
+            {
+                Ok(chain_id) => match chain_id.as_str() {
+                    "" => None,
+                    _ => Some(chain_id.parse()?),
+                },
+                Err(_) => None,
+            };
+
             let variants = DatalakeType::variants();
             let datalake_opts: Vec<&str> = variants.iter().map(AsRef::as_ref).collect();
 
@@ -259,19 +658,19 @@ async fn main() -> Result<()> {
                         DatalakeType::BlockSampled => {
                             // ================== Block Sampled Datalake Fields ==================
                             // 1. Block range start
-                            let block_range_start: u64 = inquire::Text::new("Block range start")
+                            let block_range_start: BlockNumberOrTag = inquire::Text::new("Block range start")
                                 .with_help_message(
-                                    "What is the block range start? (Enter to set default)",
+                                    "What is the block range start? A number, latest (or head), safe or finalized, e.g. finalized-1000 (Enter to set default)",
                                 )
-                                .with_default("4952200")
+                                .with_default("finalized-29")
                                 .prompt()?
                                 .parse()?;
                             // 2. Block range end
-                            let block_range_end: u64 = inquire::Text::new("Block range end")
+                            let block_range_end: BlockNumberOrTag = inquire::Text::new("Block range end")
                                 .with_help_message(
-                                    "What is the block range end? (Enter to set default)",
+                                    "What is the block range end? A number, latest (or head), safe or finalized, e.g. finalized-1000 (Enter to set default)",
                                 )
-                                .with_default("4952229")
+                                .with_default("finalized")
                                 .prompt()?
                                 .parse()?;
                             // 3. Increment
@@ -330,20 +729,27 @@ async fn main() -> Result<()> {
                                     format!("storage.{}.{}", address, storage_key)
                                 }
                             };
+                            let blocks = resolve_blocks(
+                                &[block_range_start, block_range_end],
+                                rpc_url.clone(),
+                                chain_id,
+                                RunConfigArgs::default(),
+                            )
+                            .await?;
                             let block_sampled_datalake = BlockSampledDatalake::new(
-                                block_range_start,
-                                block_range_end,
+                                blocks[0],
+                                blocks[1],
                                 sampled_property,
                                 increment,
                             )?;
                             DatalakeEnvelope::BlockSampled(block_sampled_datalake)
                         }
                         DatalakeType::TransactionsInBlock => {
-                            let target_block: u64 = inquire::Text::new("Enter target block number")
+                            let target_block: BlockNumberOrTag = inquire::Text::new("Enter target block number")
                             .with_help_message(
-                                "What block you target to get transactions? (Enter to set default)",
+                                "What block you target to get transactions? A number, latest (or head), safe or finalized, e.g. finalized-1000 (Enter to set default)",
                             )
-                            .with_default("4952200")
+                            .with_default("finalized")
                             .prompt()?
                             .parse()?;
                             let increment: u64 = inquire::Text::new("Increment")
@@ -386,8 +792,15 @@ async fn main() -> Result<()> {
                                     format!("tx_receipt.{}", transaction_receipt_ans)
                                 }
                             };
+                            let blocks = resolve_blocks(
+                                &[target_block],
+                                rpc_url.clone(),
+                                chain_id,
+                                RunConfigArgs::default(),
+                            )
+                            .await?;
                             let transactions_datalake = TransactionsInBlockDatalake::new(
-                                target_block,
+                                blocks[0],
                                 sampled_property,
                                 increment,
                             )?;
@@ -439,26 +852,6 @@ async fn main() -> Result<()> {
                 .with_default(true)
                 .prompt()?;
             if allow_run {
-                let rpc_url: Option<String> = match inquire::Text::new("Enter RPC URL: ")
-                    .with_help_message("Skip if you have it in your .env file")
-                    .prompt()
-                {
-                    Ok(url) => match url.as_str() {
-                        "" => None,
-                        _ => Some(url),
-                    },
-                    Err(_) => None,
-                };
-                let chain_id: Option<u64> = match inquire::Text::new("Enter Chain ID: ")
-                    .with_help_message("Skip if you have it in your .env file")
-                    .prompt()
-                {
-                    Ok(chain_id) => match chain_id.as_str() {
-                        "" => None,
-                        _ => Some(chain_id.parse()?),
-                    },
-                    Err(_) => None,
-                };
                 let output_file: String = inquire::Text::new("Enter Output file path: ")
                     .with_default("output.json")
                     .prompt()?;
@@ -471,8 +864,13 @@ async fn main() -> Result<()> {
                     Some(encoded_result.datalakes),
                     rpc_url,
                     chain_id,
-                    Some(output_file),
-                    Some(cairo_input),
+                    OutputArgs {
+                        output_file: Some(output_file),
+                        cairo_input: Some(cairo_input),
+                        evaluation_file: None,
+                    },
+                    RunConfigArgs::default(),
+                    vec![],
                 )
                 .await?
             }
@@ -481,12 +879,13 @@ async fn main() -> Result<()> {
             allow_run,
             rpc_url,
             chain_id,
-            output_file,
-            cairo_input,
+            output,
             aggregate_fn_id,
             aggregate_fn_ctx,
             command,
+            run_config,
         } => {
+            let mut time_ranges = vec![];
             let datalake = match command {
                 DataLakeCommands::BlockSampled {
                     block_range_start,
@@ -494,24 +893,58 @@ async fn main() -> Result<()> {
                     sampled_property,
                     increment,
                 } => {
+                    let blocks = resolve_blocks(
+                        &[block_range_start, block_range_end],
+                        rpc_url.clone(),
+                        chain_id,
+                        run_config.clone(),
+                    )
+                    .await?;
                     let block_sampled_datalake = BlockSampledDatalake::new(
-                        block_range_start,
-                        block_range_end,
+                        blocks[0],
+                        blocks[1],
                         sampled_property,
                         increment,
                     )?;
                     DatalakeEnvelope::BlockSampled(block_sampled_datalake)
                 }
-                DataLakeCommands::TransactionsInBlock {
-                    target_block,
+                DataLakeCommands::BlockSampledInTime {
+                    start_time,
+                    end_time,
                     sampled_property,
                     increment,
                 } => {
-                    let transactions_datalake = TransactionsInBlockDatalake::new(
-                        target_block,
+                    let time_range = resolve_time_range(
+                        &start_time,
+                        &end_time,
+                        rpc_url.clone(),
+                        chain_id,
+                        run_config.clone(),
+                    )
+                    .await?;
+                    let block_sampled_datalake = BlockSampledDatalake::new_in_time_range(
+                        &time_range,
                         sampled_property,
                         increment,
                     )?;
+                    let datalake = DatalakeEnvelope::BlockSampled(block_sampled_datalake);
+                    time_ranges.push((datalake.clone(), time_range));
+                    datalake
+                }
+                DataLakeCommands::TransactionsInBlock {
+                    target_block,
+                    sampled_property,
+                    increment,
+                } => {
+                    let blocks = resolve_blocks(
+                        &[target_block],
+                        rpc_url.clone(),
+                        chain_id,
+                        run_config.clone(),
+                    )
+                    .await?;
+                    let transactions_datalake =
+                        TransactionsInBlockDatalake::new(blocks[0], sampled_property, increment)?;
                     DatalakeEnvelope::Transactions(transactions_datalake)
                 }
             };
@@ -528,8 +961,9 @@ async fn main() -> Result<()> {
                     Some(encoded_result.datalakes),
                     rpc_url,
                     chain_id,
-                    output_file,
-                    cairo_input,
+                    output,
+                    run_config,
+                    time_ranges,
                 )
                 .await?
             }
@@ -549,19 +983,39 @@ async fn main() -> Result<()> {
             datalakes,
             rpc_url,
             chain_id,
-            output_file,
-            cairo_input,
+            output,
+            run_config,
         } => {
             handle_run(
                 tasks,
                 datalakes,
                 rpc_url,
                 chain_id,
-                output_file,
-                cairo_input,
+                output,
+                run_config,
+                vec![],
             )
             .await?
         }
+        Commands::Merge {
+            evaluation_files,
+            rpc_url,
+            chain_id,
+            output,
+            run_config,
+        } => handle_merge(evaluation_files, rpc_url, chain_id, output, run_config).await?,
+        Commands::ProveResult {
+            output_file,
+            task_commitment,
+            proof_file,
+        } => handle_prove_result(output_file, task_commitment, proof_file)?,
+        Commands::Estimate {
+            tasks,
+            datalakes,
+            transactions_per_block,
+            json,
+            config,
+        } => handle_estimate(tasks, datalakes, transactions_per_block, json, config).await?,
     }
     let duration_run = start_run.elapsed();
     info!("HDP Cli Finished in: {:?}", duration_run);
@@ -2,6 +2,7 @@ use std::str::FromStr;
 
 use alloy_primitives::U256;
 use anyhow::{bail, Result};
+use hdp_primitives::task_result::TaskResultType;
 
 use self::integer::Operator;
 
@@ -103,6 +104,14 @@ impl AggregationFunction {
         }
     }
 
+    /// Type of the results of the function
+    pub fn result_type(&self) -> TaskResultType {
+        match self {
+            AggregationFunction::MERKLE => TaskResultType::Bytes32,
+            _ => TaskResultType::Uint256,
+        }
+    }
+
     pub fn operation(&self, values: &[String], ctx: Option<FunctionContext>) -> Result<String> {
         match self {
             // Aggregation functions for integer values
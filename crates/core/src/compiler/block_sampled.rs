@@ -1,21 +1,27 @@
-use hdp_primitives::datalake::{
-    block_sampled::{BlockSampledCollection, BlockSampledDatalake},
-    output::{Header, HeaderProof, MMRMeta, MPTProof},
-    DatalakeField,
+use hdp_primitives::{
+    block::{
+        account::{verify_storage_proof, Account as AccountInTrie},
+        header::Header as BlockHeader,
+    },
+    datalake::{
+        block_sampled::{BlockSampledCollection, BlockSampledDatalake},
+        output::{Header, HeaderProof, MMRMeta, MPTProof},
+        DatalakeField,
+    },
+    mpt::EMPTY_ROOT,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet};
 
-use alloy_primitives::keccak256;
-use anyhow::Result;
+use alloy_primitives::{keccak256, Address, StorageKey};
+use anyhow::{Context, Result};
 
 use hdp_primitives::datalake::block_sampled::output::{Account, Storage};
 use hdp_provider::evm::AbstractProvider;
-use tokio::sync::RwLock;
 
 /// [`CompiledBlockSampledDatalake`] is a unified structure that contains all the required data to verify the datalake
 ///
-/// Contains compiled results, headers, accounts, storages, and mmr_meta data.
+/// Contains compiled results, headers, accounts, storages, and mmr_metas data.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CompiledBlockSampledDatalake {
     /// Targeted datalake's compiled results
@@ -26,22 +32,20 @@ pub struct CompiledBlockSampledDatalake {
     pub accounts: Vec<Account>,
     /// Storages related to the datalake
     pub storages: Vec<Storage>,
-    /// MMR meta data related to the headers
-    pub mmr_meta: MMRMeta,
+    /// MMR meta data of the MMRs the headers are proven against
+    pub mmr_metas: Vec<MMRMeta>,
 }
 
 pub async fn compile_block_sampled_datalake(
     datalake: BlockSampledDatalake,
-    provider: &Arc<RwLock<AbstractProvider>>,
+    provider: &AbstractProvider,
 ) -> Result<CompiledBlockSampledDatalake> {
-    let mut abstract_provider = provider.write().await;
-
     let mut aggregation_set: Vec<String> = Vec::new();
 
-    let full_header_and_proof_result = abstract_provider
+    let full_header_and_proof_result = provider
         .get_sequencial_full_header_with_proof(datalake.block_range_start, datalake.block_range_end)
         .await?;
-    let mmr_meta = full_header_and_proof_result.1;
+    let mmr_metas = full_header_and_proof_result.1;
     let mut headers: Vec<Header> = vec![];
     let mut accounts: Vec<Account> = vec![];
     let mut storages: Vec<Storage> = vec![];
@@ -58,6 +62,7 @@ pub async fn compile_block_sampled_datalake(
                 headers.push(Header {
                     rlp: fetched_block.0,
                     proof: HeaderProof {
+                        mmr_id: fetched_block.3,
                         leaf_idx: fetched_block.2,
                         mmr_path: fetched_block.1,
                     },
@@ -67,7 +72,7 @@ pub async fn compile_block_sampled_datalake(
             }
         }
         BlockSampledCollection::Account(address, property) => {
-            let accounts_and_proofs_result = abstract_provider
+            let accounts_and_proofs_result = provider
                 .get_range_account_with_proof(
                     datalake.block_range_start,
                     datalake.block_range_end,
@@ -77,7 +82,6 @@ pub async fn compile_block_sampled_datalake(
                 .await?;
 
             let mut account_proofs: Vec<MPTProof> = vec![];
-            // let mut encoded_account = "".to_string();
 
             for block in datalake.block_range_start..=datalake.block_range_end {
                 if block % datalake.increment != 0 {
@@ -85,12 +89,16 @@ pub async fn compile_block_sampled_datalake(
                 }
                 let fetched_block = full_header_and_proof_result.0.get(&block).unwrap().clone();
                 let acc = accounts_and_proofs_result.get(&block).unwrap().clone();
-                // encoded_account = acc.0.clone();
-                let value = property.decode_field_from_rlp(&acc.0);
+                let account = verify_account(&fetched_block.0, &address, block, &acc.1)?;
+                let is_exclusion = account.is_none();
+                // every field of an account that doesn't exist is 0
+                let value =
+                    property.decode_field_from_rlp(&account.unwrap_or_default().rlp_encode());
 
                 headers.push(Header {
                     rlp: fetched_block.0,
                     proof: HeaderProof {
+                        mmr_id: fetched_block.3,
                         leaf_idx: fetched_block.2,
                         mmr_path: fetched_block.1,
                     },
@@ -99,6 +107,7 @@ pub async fn compile_block_sampled_datalake(
                 let account_proof = MPTProof {
                     block_number: block,
                     proof: acc.1,
+                    is_exclusion,
                 };
 
                 account_proofs.push(account_proof);
@@ -113,7 +122,7 @@ pub async fn compile_block_sampled_datalake(
             });
         }
         BlockSampledCollection::Storage(address, slot) => {
-            let storages_and_proofs_result = abstract_provider
+            let storages_and_proofs_result = provider
                 .get_range_storage_with_proof(
                     datalake.block_range_start,
                     datalake.block_range_end,
@@ -132,10 +141,23 @@ pub async fn compile_block_sampled_datalake(
                 }
                 let fetched_block = full_header_and_proof_result.0.get(&i).unwrap().clone();
                 let acc_and_storage = storages_and_proofs_result.get(&i).unwrap().clone();
+                let account = verify_account(&fetched_block.0, &address, i, &acc_and_storage.1)?;
+                // an account that doesn't exist has an empty storage trie
+                let storage_root = account
+                    .as_ref()
+                    .map_or(EMPTY_ROOT, |account| account.storage_root);
+                let storage_value = verify_storage_proof(storage_root, &slot, &acc_and_storage.3)
+                    .with_context(|| {
+                    format!(
+                        "Invalid proof of storage slot {} of account {} in block {}",
+                        slot, address, i
+                    )
+                })?;
 
                 headers.push(Header {
                     rlp: fetched_block.0,
                     proof: HeaderProof {
+                        mmr_id: fetched_block.3,
                         leaf_idx: fetched_block.2,
                         mmr_path: fetched_block.1,
                     },
@@ -144,14 +166,16 @@ pub async fn compile_block_sampled_datalake(
                 account_proofs.push(MPTProof {
                     block_number: i,
                     proof: acc_and_storage.1,
+                    is_exclusion: account.is_none(),
                 });
 
                 storage_proofs.push(MPTProof {
                     block_number: i,
                     proof: acc_and_storage.3,
+                    is_exclusion: storage_value.is_none(),
                 });
 
-                aggregation_set.push(acc_and_storage.2);
+                aggregation_set.push(format!("{:#x}", storage_value.unwrap_or_default()));
             }
 
             let storage_key = keccak256(slot).to_string();
@@ -176,6 +200,180 @@ pub async fn compile_block_sampled_datalake(
         headers,
         accounts,
         storages,
-        mmr_meta,
+        mmr_metas,
+    })
+}
+
+/// Fetch the storage slots that the datalakes sample from a same contract with one
+/// `eth_getProof` request per block, so that the shared account proof is fetched once.
+pub async fn prefetch_storages(
+    datalakes: &[&BlockSampledDatalake],
+    provider: &AbstractProvider,
+) -> Result<()> {
+    for (address, slots_by_block) in storage_slots_by_address(datalakes) {
+        // a contract sampled at a single slot is fetched when its datalake is compiled
+        if slots_by_block.values().all(|slots| slots.len() < 2) {
+            continue;
+        }
+        let slots_by_block = slots_by_block
+            .into_iter()
+            .map(|(block_number, slots)| {
+                let slots = slots.iter().map(|slot| slot.to_string()).collect();
+                (block_number, slots)
+            })
+            .collect();
+        provider
+            .fetch_storages_with_proof(&address.to_string(), slots_by_block)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Storage slots sampled by the datalakes, by contract and block
+fn storage_slots_by_address(
+    datalakes: &[&BlockSampledDatalake],
+) -> BTreeMap<Address, BTreeMap<u64, BTreeSet<StorageKey>>> {
+    let mut slots_by_address: BTreeMap<Address, BTreeMap<u64, BTreeSet<StorageKey>>> =
+        BTreeMap::new();
+    for datalake in datalakes {
+        if let BlockSampledCollection::Storage(address, slot) = &datalake.sampled_property {
+            let slots_by_block = slots_by_address.entry(*address).or_default();
+            for block_number in (datalake.block_range_start..=datalake.block_range_end)
+                .filter(|block| block % datalake.increment == 0)
+            {
+                slots_by_block
+                    .entry(block_number)
+                    .or_default()
+                    .insert(*slot);
+            }
+        }
+    }
+    slots_by_address
+}
+
+/// Verify the account proof returned by the RPC against the state root of the block header.
+/// Returns `None` for an exclusion proof of an account that doesn't exist in the block.
+fn verify_account(
+    header_rlp: &str,
+    address: &Address,
+    block_number: u64,
+    account_proof: &[String],
+) -> Result<Option<AccountInTrie>> {
+    let state_root = BlockHeader::rlp_decode(header_rlp).state_root;
+    AccountInTrie::verify_proof(state_root, address, account_proof).with_context(|| {
+        format!(
+            "Invalid proof of account {} in block {}",
+            address, block_number
+        )
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{StorageKey, U256};
+    use serde_json::Value;
+    use std::{collections::HashMap, str::FromStr};
+
+    fn proofs_by_block(proofs: &Value) -> HashMap<u64, Vec<String>> {
+        proofs
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|proof| {
+                (
+                    proof["block_number"].as_u64().unwrap(),
+                    serde_json::from_value(proof["proof"].clone()).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_storage_slots_by_address() {
+        let storage_datalake = |address: &str, slot: &str, start: u64, end: u64| {
+            BlockSampledDatalake::new(start, end, format!("storage.{}.{}", address, slot), 1)
+                .unwrap()
+        };
+        let contract = "0x75CeC1db9dCeb703200EAa6595f66885C962B920";
+        let other_contract = "0x7f2C6f930306D3AA736B3A6C6A98f512F74036D4";
+        let slot_0 = format!("0x{:064x}", 0);
+        let slot_1 = format!("0x{:064x}", 1);
+        let datalakes = [
+            storage_datalake(contract, &slot_0, 10, 12),
+            storage_datalake(contract, &slot_1, 11, 13),
+            storage_datalake(other_contract, &slot_0, 10, 10),
+            BlockSampledDatalake::new(10, 12, "header.number".to_string(), 1).unwrap(),
+        ];
+        let slots_by_address = storage_slots_by_address(&datalakes.iter().collect::<Vec<_>>());
+
+        assert_eq!(slots_by_address.len(), 2);
+        let contract_slots = &slots_by_address[&Address::from_str(contract).unwrap()];
+        assert_eq!(
+            contract_slots.keys().copied().collect::<Vec<_>>(),
+            vec![10, 11, 12, 13]
+        );
+        assert_eq!(contract_slots[&10].len(), 1);
+        assert_eq!(contract_slots[&11].len(), 2);
+        assert_eq!(contract_slots[&12].len(), 2);
+        assert_eq!(
+            slots_by_address[&Address::from_str(other_contract).unwrap()][&10].len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_verify_storage_proofs_of_example() {
+        let output: Value = serde_json::from_str(include_str!(
+            "../../../../example/storage/sum_storage/output.json"
+        ))
+        .unwrap();
+        let headers = output["headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|header| {
+                let rlp = header["rlp"].as_str().unwrap().to_string();
+                (BlockHeader::rlp_decode(&rlp).number, rlp)
+            })
+            .collect::<HashMap<_, _>>();
+        let address =
+            Address::from_str(output["accounts"][0]["address"].as_str().unwrap()).unwrap();
+        let slot = StorageKey::from_str(output["storages"][0]["slot"].as_str().unwrap()).unwrap();
+        let account_proofs = proofs_by_block(&output["accounts"][0]["proofs"]);
+        let storage_proofs = proofs_by_block(&output["storages"][0]["proofs"]);
+
+        let mut sum = U256::ZERO;
+        for (block_number, storage_proof) in &storage_proofs {
+            let account = verify_account(
+                &headers[block_number],
+                &address,
+                *block_number,
+                &account_proofs[block_number],
+            )
+            .unwrap()
+            .unwrap();
+            sum += verify_storage_proof(account.storage_root, &slot, storage_proof)
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(
+            sum.to_string(),
+            output["tasks"][0]["compiled_result"].as_str().unwrap()
+        );
+
+        // a slot of an account without storage is proven unset by an empty proof
+        assert_eq!(verify_storage_proof(EMPTY_ROOT, &slot, &[]).unwrap(), None);
+
+        // the proof of another block doesn't verify against this header
+        let mut block_numbers = storage_proofs.keys().copied().collect::<Vec<_>>();
+        block_numbers.sort();
+        assert!(verify_account(
+            &headers[&block_numbers[0]],
+            &address,
+            block_numbers[0],
+            &account_proofs[&block_numbers[1]],
+        )
+        .is_err());
+    }
+}
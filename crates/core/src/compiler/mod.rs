@@ -1,15 +1,17 @@
-use std::{fmt, sync::Arc};
+use std::fmt;
 
 use anyhow::{bail, Result};
-use hdp_primitives::datalake::{
-    block_sampled::output::{Account, Storage},
-    envelope::DatalakeEnvelope,
-    output::{Header, MMRMeta},
-    transactions::output::{Transaction, TransactionReceipt},
+use hdp_primitives::{
+    block::header::Header as BlockHeader,
+    datalake::{
+        block_sampled::output::{Account, Storage},
+        envelope::DatalakeEnvelope,
+        output::{Header, HeaderProof, MMRMeta},
+        transactions::output::{Transaction, TransactionReceipt},
+    },
 };
 use hdp_provider::evm::AbstractProvider;
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
 
 use self::{
     block_sampled::{compile_block_sampled_datalake, CompiledBlockSampledDatalake},
@@ -100,17 +102,67 @@ impl CompiledDatalakeEnvelope {
         }
     }
 
-    /// Get mmr_meta from compiled datalake
-    pub fn get_mmr_meta(&self) -> Result<MMRMeta> {
+    /// Get mmr_metas from compiled datalake
+    pub fn get_mmr_metas(&self) -> Result<Vec<MMRMeta>> {
         match self {
             CompiledDatalakeEnvelope::BlockSampled(compiled_block_sampled_datalake) => {
-                Ok(compiled_block_sampled_datalake.mmr_meta.clone())
+                Ok(compiled_block_sampled_datalake.mmr_metas.clone())
             }
             CompiledDatalakeEnvelope::Transactions(compiled_transactions_datalake) => {
-                Ok(compiled_transactions_datalake.mmr_meta.clone())
+                Ok(compiled_transactions_datalake.mmr_metas.clone())
             }
         }
     }
+
+    /// Prove the headers again against the state of their MMRs cached by the provider,
+    /// from the headers of the blocks from `start_block` to `end_block`.
+    ///
+    /// The values, accounts, storages and transactions are kept, a header that changed
+    /// since it was compiled is refused.
+    pub async fn reprove_headers(
+        &mut self,
+        start_block: u64,
+        end_block: u64,
+        provider: &AbstractProvider,
+    ) -> Result<()> {
+        let (fetched_headers, fetched_mmr_metas) = provider
+            .get_sequencial_full_header_with_proof(start_block, end_block)
+            .await?;
+        let (headers, mmr_metas) = match self {
+            CompiledDatalakeEnvelope::BlockSampled(compiled_block_sampled_datalake) => (
+                &mut compiled_block_sampled_datalake.headers,
+                &mut compiled_block_sampled_datalake.mmr_metas,
+            ),
+            CompiledDatalakeEnvelope::Transactions(compiled_transactions_datalake) => (
+                &mut compiled_transactions_datalake.headers,
+                &mut compiled_transactions_datalake.mmr_metas,
+            ),
+        };
+        for header in headers.iter_mut() {
+            let block_number = BlockHeader::rlp_decode(&header.rlp).number;
+            let Some((rlp, mmr_path, leaf_idx, mmr_id)) = fetched_headers.get(&block_number) else {
+                bail!(
+                    "Block {} is not in blocks {} - {}",
+                    block_number,
+                    start_block,
+                    end_block
+                );
+            };
+            if *rlp != header.rlp {
+                bail!(
+                    "Header of block {} changed since it was compiled, the chain was reorganized",
+                    block_number
+                );
+            }
+            header.proof = HeaderProof {
+                mmr_id: *mmr_id,
+                leaf_idx: *leaf_idx,
+                mmr_path: mmr_path.clone(),
+            };
+        }
+        *mmr_metas = fetched_mmr_metas;
+        Ok(())
+    }
 }
 
 pub struct DatalakeCompiler {
@@ -138,13 +190,10 @@ impl DatalakeCompiler {
         }
     }
 
-    /// Compile the datalake meaning, fetching relevant headers, accounts, storages, and mmr_meta data.
+    /// Compile the datalake meaning, fetching relevant headers, accounts, storages, and mmr_metas data.
     ///
     /// Plus, it will combine target datalake's datapoints in compiled_results.
-    pub async fn compile(
-        &self,
-        provider: &Arc<RwLock<AbstractProvider>>,
-    ) -> Result<CompiledDatalakeEnvelope> {
+    pub async fn compile(&self, provider: &AbstractProvider) -> Result<CompiledDatalakeEnvelope> {
         let result_datapoints = match &self.datalake {
             DatalakeEnvelope::BlockSampled(datalake) => CompiledDatalakeEnvelope::BlockSampled(
                 compile_block_sampled_datalake(datalake.clone(), provider).await?,
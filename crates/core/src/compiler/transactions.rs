@@ -1,6 +1,7 @@
 use alloy_primitives::U256;
 use anyhow::Result;
 use hdp_primitives::{
+    block::header::Header as BlockHeader,
     datalake::{
         output::{Header, HeaderProof, MMRMeta},
         transactions::{
@@ -13,8 +14,6 @@ use hdp_primitives::{
 };
 use hdp_provider::evm::AbstractProvider;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio::sync::RwLock;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CompiledTransactionsDatalake {
@@ -26,29 +25,39 @@ pub struct CompiledTransactionsDatalake {
     pub transactions: Vec<Transaction>,
     /// Transaction receipts related to the datalake
     pub transaction_receipts: Vec<TransactionReceipt>,
-    /// MMR meta data related to the headers
-    pub mmr_meta: MMRMeta,
+    /// MMR meta data of the MMRs the headers are proven against
+    pub mmr_metas: Vec<MMRMeta>,
 }
 
 pub async fn compile_tx_datalake(
     datalake: TransactionsInBlockDatalake,
-    provider: &Arc<RwLock<AbstractProvider>>,
+    provider: &AbstractProvider,
 ) -> Result<CompiledTransactionsDatalake> {
-    let abstract_provider = provider.write().await;
     let mut aggregation_set: Vec<String> = Vec::new();
 
-    let full_header_and_proof_result = abstract_provider
+    let full_header_and_proof_result = provider
         .get_sequencial_full_header_with_proof(datalake.target_block, datalake.target_block)
         .await?;
-    let mmr_meta = full_header_and_proof_result.1;
+    let mmr_metas = full_header_and_proof_result.1;
+    let target_header = BlockHeader::rlp_decode(
+        &full_header_and_proof_result
+            .0
+            .get(&datalake.target_block)
+            .unwrap()
+            .0,
+    );
     let mut headers: Vec<Header> = vec![];
     let mut transactions: Vec<Transaction> = vec![];
     let mut transaction_receipts: Vec<TransactionReceipt> = vec![];
 
     match datalake.sampled_property {
         TransactionsCollection::Transactions(property) => {
-            let full_tx_and_proof_result = abstract_provider
-                .get_tx_with_proof_from_block(datalake.target_block, datalake.increment)
+            let full_tx_and_proof_result = provider
+                .get_tx_with_proof_from_block(
+                    datalake.target_block,
+                    datalake.increment,
+                    target_header.transactions_root,
+                )
                 .await?;
 
             for (block_number, tx_index, rlp_encoded_tx, proof) in full_tx_and_proof_result {
@@ -70,6 +79,7 @@ pub async fn compile_tx_datalake(
                         .0
                         .clone(),
                     proof: HeaderProof {
+                        mmr_id: full_header_and_proof_result.0.get(&block_number).unwrap().3,
                         leaf_idx: full_header_and_proof_result.0.get(&block_number).unwrap().2,
                         mmr_path: full_header_and_proof_result
                             .0
@@ -84,8 +94,12 @@ pub async fn compile_tx_datalake(
             }
         }
         TransactionsCollection::TranasactionReceipts(property) => {
-            let full_tx_receipt_and_proof_result = abstract_provider
-                .get_tx_receipt_with_proof_from_block(datalake.target_block, datalake.increment)
+            let full_tx_receipt_and_proof_result = provider
+                .get_tx_receipt_with_proof_from_block(
+                    datalake.target_block,
+                    datalake.increment,
+                    target_header.receipts_root,
+                )
                 .await?;
 
             for (block_number, tx_index, rlp_encoded_tx_receipt, proof) in
@@ -109,6 +123,7 @@ pub async fn compile_tx_datalake(
                         .0
                         .clone(),
                     proof: HeaderProof {
+                        mmr_id: full_header_and_proof_result.0.get(&block_number).unwrap().3,
                         leaf_idx: full_header_and_proof_result.0.get(&block_number).unwrap().2,
                         mmr_path: full_header_and_proof_result
                             .0
@@ -129,6 +144,6 @@ pub async fn compile_tx_datalake(
         headers,
         transactions,
         transaction_receipts,
-        mmr_meta,
+        mmr_metas,
     })
 }
@@ -1,37 +1,546 @@
-use std::env;
-use tokio::sync::OnceCell;
+use anyhow::{bail, Context, Result};
+use hdp_primitives::mmr::HashingFunction;
+use hdp_provider::evm::config::{
+    Endpoint, IndexerConfig, LocalMmrConfig, ProviderConfig, TlsConfig,
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
-pub static CONFIG: OnceCell<Config> = OnceCell::const_new();
+use crate::evaluator::{
+    checkpoint::{CheckpointConfig, DEFAULT_CHECKPOINT_DIR},
+    finality::{FinalityConfig, FinalityPolicy},
+    EvaluatorConfig, DEFAULT_MAX_CONCURRENT_DATALAKES,
+};
 
+/// Config file read when no path is given with `--config` or `HDP_CONFIG`
+pub const DEFAULT_CONFIG_FILE: &str = "hdp.toml";
+
+/// Settings of one run, several configs can be used side by side in a process
+#[derive(Debug, Clone)]
 pub struct Config {
     pub rpc_url: String,
+    /// RPC endpoints in failover order, `rpc_url` split on commas
+    pub rpc_endpoints: Vec<Endpoint>,
     pub datalakes: String,
     pub tasks: String,
     pub chain_id: u64,
+    pub provider_config: ProviderConfig,
+    pub evaluator_config: EvaluatorConfig,
 }
 
 impl Config {
-    pub async fn init(
+    pub fn init(
         cli_rpc_url: Option<String>,
         cli_datalakes: Option<String>,
         cli_tasks: Option<String>,
         cli_chain_id: Option<u64>,
-    ) -> &'static Self {
+        cli_config_file: Option<String>,
+        cli_indexer: IndexerOptions,
+        cli_evaluator: EvaluatorOptions,
+    ) -> Result<Self> {
         let rpc_url = cli_rpc_url.unwrap_or_else(|| env::var("RPC_URL").unwrap());
         let datalakes = cli_datalakes.unwrap_or_else(|| env::var("DATALAKES").unwrap());
         let tasks = cli_tasks.unwrap_or_else(|| env::var("TASKS").unwrap());
         let chain_id =
             cli_chain_id.unwrap_or_else(|| env::var("CHAIN_ID").unwrap().parse().unwrap());
+        let config_file = ConfigFile::load(cli_config_file)?;
+        let rpc_endpoints = rpc_endpoints(&rpc_url, &config_file.rpc)?;
+        let mut provider_config = provider_config_from_env()?;
+        provider_config.tls = TlsConfig {
+            ca_certificates: config_file.tls.ca_certificates.clone(),
+            accept_invalid_certs: config_file.tls.accept_invalid_certs,
+        };
+        let local_mmr_path = cli_indexer
+            .local_mmr_path
+            .clone()
+            .or_else(|| env::var("LOCAL_MMR_PATH").ok())
+            .or(config_file.local_mmr.path.clone());
+        provider_config.indexer = indexer_config(cli_indexer, config_file.indexer)?;
+        provider_config.local_mmr = local_mmr_path.map(|path| LocalMmrConfig {
+            path: PathBuf::from(path),
+            hashing_function: provider_config.indexer.hashing_function,
+        });
+        let evaluator_config = EvaluatorConfig {
+            finality: finality_config(cli_evaluator.finality, config_file.finality)?,
+            max_concurrent_datalakes: cli_evaluator
+                .max_concurrent_datalakes
+                .or(env_var("EVALUATOR_MAX_CONCURRENT_DATALAKES")?)
+                .or(config_file.evaluator.max_concurrent_datalakes)
+                .unwrap_or(DEFAULT_MAX_CONCURRENT_DATALAKES),
+            checkpoint: Some(CheckpointConfig {
+                dir: cli_evaluator
+                    .checkpoint_dir
+                    .or_else(|| env::var("EVALUATOR_CHECKPOINT_DIR").ok())
+                    .or(config_file.evaluator.checkpoint_dir)
+                    .unwrap_or_else(|| DEFAULT_CHECKPOINT_DIR.to_string())
+                    .into(),
+                resume: cli_evaluator.resume,
+            }),
+            timeout: cli_evaluator
+                .timeout_secs
+                .or(env_var("EVALUATOR_TIMEOUT_SECS")?)
+                .or(config_file.evaluator.timeout_secs)
+                .map(Duration::from_secs),
+        };
+
+        Ok(Config {
+            rpc_url,
+            rpc_endpoints,
+            datalakes,
+            tasks,
+            chain_id,
+            provider_config,
+            evaluator_config,
+        })
+    }
+}
+
+/// Indexer settings given on the command line
+#[derive(Debug, Clone, Default)]
+pub struct IndexerOptions {
+    pub url: Option<String>,
+    pub hashing_function: Option<HashingFunction>,
+    pub contract_type: Option<String>,
+    /// Build the MMR locally in this file instead of querying the indexer
+    pub local_mmr_path: Option<String>,
+}
+
+/// Evaluator settings given on the command line
+#[derive(Debug, Clone, Default)]
+pub struct EvaluatorOptions {
+    pub finality: FinalityOptions,
+    pub max_concurrent_datalakes: Option<usize>,
+    /// Directory of the checkpoints of the evaluations
+    pub checkpoint_dir: Option<String>,
+    /// Resume the evaluation of the batch from its checkpoint
+    pub resume: bool,
+    /// Stop the evaluation if it takes longer
+    pub timeout_secs: Option<u64>,
+}
+
+/// Finality settings given on the command line
+#[derive(Debug, Clone, Default)]
+pub struct FinalityOptions {
+    pub policy: Option<FinalityPolicy>,
+    pub confirmation_depth: Option<u64>,
+}
+
+/// Settings read from the toml config file, e.g.
+///
+/// ```toml
+/// [rpc]
+/// headers = { "x-api-key" = "..." }
+///
+/// [indexer]
+/// url = "http://localhost:8080/accumulators"
+/// bearer_token = "..."
+/// hashing_function = "keccak"
+/// contract_type = "AGGREGATOR"
+/// page_size = 1000
+/// max_concurrent_pages = 4
+///
+/// [local_mmr]
+/// path = "local_mmr.json"
+///
+/// [finality]
+/// policy = "wait"
+/// confirmation_depth = 64
+/// poll_interval_secs = 12
+/// max_wait_secs = 1800
+///
+/// [evaluator]
+/// max_concurrent_datalakes = 8
+/// checkpoint_dir = ".hdp/checkpoints"
+/// timeout_secs = 3600
+///
+/// [tls]
+/// ca_certificates = ["ca.pem"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    indexer: IndexerOptionsFromFile,
+    #[serde(default)]
+    local_mmr: LocalMmrOptionsFromFile,
+    #[serde(default)]
+    finality: FinalityOptionsFromFile,
+    #[serde(default)]
+    evaluator: EvaluatorOptionsFromFile,
+    #[serde(default)]
+    rpc: RpcOptionsFromFile,
+    #[serde(default)]
+    tls: TlsOptionsFromFile,
+}
+
+/// Credentials sent to every RPC url
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RpcOptionsFromFile {
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    bearer_token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TlsOptionsFromFile {
+    #[serde(default)]
+    ca_certificates: Vec<PathBuf>,
+    #[serde(default)]
+    accept_invalid_certs: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LocalMmrOptionsFromFile {
+    path: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FinalityOptionsFromFile {
+    policy: Option<FinalityPolicy>,
+    confirmation_depth: Option<u64>,
+    poll_interval_secs: Option<u64>,
+    max_wait_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EvaluatorOptionsFromFile {
+    max_concurrent_datalakes: Option<usize>,
+    checkpoint_dir: Option<String>,
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IndexerOptionsFromFile {
+    url: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    bearer_token: Option<String>,
+    hashing_function: Option<HashingFunction>,
+    contract_type: Option<String>,
+    page_size: Option<u64>,
+    max_concurrent_pages: Option<usize>,
+}
+
+impl ConfigFile {
+    /// Load the config file given on the command line or with `HDP_CONFIG`.
+    /// Otherwise `hdp.toml` is read if it exists in the working directory.
+    fn load(cli_config_file: Option<String>) -> Result<Self> {
+        let path = match cli_config_file.or_else(|| env::var("HDP_CONFIG").ok()) {
+            Some(path) => path,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => DEFAULT_CONFIG_FILE.to_string(),
+            None => return Ok(Self::default()),
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path))?;
+        Self::parse(&content).with_context(|| format!("Invalid config file {}", path))
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+}
+
+/// Resolve the indexer settings, by order of precedence:
+/// command line, environment, config file and defaults.
+fn indexer_config(cli: IndexerOptions, file: IndexerOptionsFromFile) -> Result<IndexerConfig> {
+    let default = IndexerConfig::default();
+    let hashing_function = match env::var("INDEXER_HASHING_FUNCTION").ok() {
+        Some(value) => Some(HashingFunction::from_str(&value)?),
+        None => None,
+    };
+
+    let url = cli
+        .url
+        .or_else(|| env::var("INDEXER_URL").ok())
+        .or(file.url);
+    let endpoint = match url {
+        Some(url) => Endpoint::from_str(&url)?,
+        None => default.endpoint,
+    };
+    let bearer_token = env::var("INDEXER_BEARER_TOKEN").ok().or(file.bearer_token);
+
+    Ok(IndexerConfig {
+        endpoint: with_credentials(endpoint, &file.headers, bearer_token.as_deref()),
+        hashing_function: cli
+            .hashing_function
+            .or(hashing_function)
+            .or(file.hashing_function)
+            .unwrap_or(default.hashing_function),
+        contract_type: cli
+            .contract_type
+            .or_else(|| env::var("INDEXER_CONTRACT_TYPE").ok())
+            .or(file.contract_type)
+            .unwrap_or(default.contract_type),
+        page_size: page_size(file.page_size)?,
+        max_concurrent_pages: env_var("INDEXER_MAX_CONCURRENT_PAGES")?
+            .or(file.max_concurrent_pages)
+            .unwrap_or(default.max_concurrent_pages),
+    })
+}
+
+/// Blocks fetched from the indexer per request, resolved from the environment, the config
+/// file and the default like a run does, without the RPC settings a run needs
+pub fn indexer_page_size(cli_config_file: Option<String>) -> Result<u64> {
+    page_size(ConfigFile::load(cli_config_file)?.indexer.page_size)
+}
+
+fn page_size(file_page_size: Option<u64>) -> Result<u64> {
+    Ok(env_var("INDEXER_PAGE_SIZE")?
+        .or(file_page_size)
+        .unwrap_or(IndexerConfig::default().page_size))
+}
+
+/// Parse the comma separated RPC urls, with the credentials of the environment or config file
+fn rpc_endpoints(rpc_url: &str, file: &RpcOptionsFromFile) -> Result<Vec<Endpoint>> {
+    let bearer_token = env::var("RPC_BEARER_TOKEN")
+        .ok()
+        .or(file.bearer_token.clone());
+    rpc_url
+        .split(',')
+        .map(|url| url.trim())
+        .filter(|url| !url.is_empty())
+        .map(|url| {
+            let endpoint = Endpoint::from_str(url)?;
+            Ok(with_credentials(
+                endpoint,
+                &file.headers,
+                bearer_token.as_deref(),
+            ))
+        })
+        .collect()
+}
+
+fn with_credentials(
+    mut endpoint: Endpoint,
+    headers: &BTreeMap<String, String>,
+    bearer_token: Option<&str>,
+) -> Endpoint {
+    for (name, value) in headers {
+        endpoint = endpoint.with_header(name, value);
+    }
+    match bearer_token {
+        Some(bearer_token) => endpoint.with_bearer_auth(bearer_token),
+        None => endpoint,
+    }
+}
+
+/// Resolve the finality settings, by order of precedence:
+/// command line, environment, config file and defaults.
+fn finality_config(cli: FinalityOptions, file: FinalityOptionsFromFile) -> Result<FinalityConfig> {
+    let default = FinalityConfig::default();
+    let policy = match env::var("FINALITY_POLICY").ok() {
+        Some(value) => Some(FinalityPolicy::from_str(&value)?),
+        None => None,
+    };
+
+    Ok(FinalityConfig {
+        policy: cli
+            .policy
+            .or(policy)
+            .or(file.policy)
+            .unwrap_or(default.policy),
+        confirmation_depth: cli
+            .confirmation_depth
+            .or(env_var("FINALITY_CONFIRMATION_DEPTH")?)
+            .or(file.confirmation_depth)
+            .or(default.confirmation_depth),
+        poll_interval: env_var("FINALITY_POLL_INTERVAL_SECS")?
+            .or(file.poll_interval_secs)
+            .map(Duration::from_secs)
+            .unwrap_or(default.poll_interval),
+        max_wait: env_var("FINALITY_MAX_WAIT_SECS")?
+            .or(file.max_wait_secs)
+            .map(Duration::from_secs)
+            .unwrap_or(default.max_wait),
+    })
+}
+
+/// Read the optional provider settings from the environment, falling back to defaults
+fn provider_config_from_env() -> Result<ProviderConfig> {
+    let default = ProviderConfig::default();
+    Ok(ProviderConfig {
+        max_retries: env_var("RPC_MAX_RETRIES")?.unwrap_or(default.max_retries),
+        initial_backoff: env_var("RPC_INITIAL_BACKOFF_MS")?
+            .map(Duration::from_millis)
+            .unwrap_or(default.initial_backoff),
+        max_backoff: env_var("RPC_MAX_BACKOFF_MS")?
+            .map(Duration::from_millis)
+            .unwrap_or(default.max_backoff),
+        max_requests_per_second: env_var("RPC_MAX_REQUESTS_PER_SECOND")?
+            .or(default.max_requests_per_second),
+        max_concurrent_requests: env_var("RPC_MAX_CONCURRENT_REQUESTS")?
+            .unwrap_or(default.max_concurrent_requests),
+        request_timeout: env_var("RPC_REQUEST_TIMEOUT_SECS")?
+            .map(Duration::from_secs)
+            .unwrap_or(default.request_timeout),
+        quorum: env_var("RPC_QUORUM")?.unwrap_or(default.quorum),
+        indexer: default.indexer,
+        local_mmr: default.local_mmr,
+        tls: default.tls,
+    })
+}
+
+/// Parse an optional environment variable, an invalid value is an error rather than ignored
+fn env_var<T: FromStr>(key: &str) -> Result<Option<T>> {
+    match env::var(key) {
+        Ok(value) => match value.parse() {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(_) => bail!("Invalid value of {}: {}", key, value),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config_file() {
+        let config_file = ConfigFile::parse(
+            r#"
+            [indexer]
+            url = "http://localhost:8080/accumulators"
+            hashing_function = "keccak"
+            page_size = 500
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config_file.indexer.url.as_deref(),
+            Some("http://localhost:8080/accumulators")
+        );
+        assert_eq!(
+            config_file.indexer.hashing_function,
+            Some(HashingFunction::Keccak)
+        );
+        assert_eq!(config_file.indexer.contract_type, None);
+        assert_eq!(config_file.indexer.page_size, Some(500));
+        assert_eq!(config_file.local_mmr.path, None);
+
+        assert!(ConfigFile::parse("[indexer]\nhashing_function = \"sha256\"").is_err());
+        assert!(ConfigFile::parse("[indexer]\nurl_typo = \"\"").is_err());
+    }
+
+    #[test]
+    fn test_cli_indexer_options_take_precedence() {
+        let indexer = indexer_config(
+            IndexerOptions {
+                url: Some("http://cli".to_string()),
+                hashing_function: Some(HashingFunction::Keccak),
+                contract_type: None,
+                local_mmr_path: None,
+            },
+            IndexerOptionsFromFile {
+                url: Some("http://file".to_string()),
+                headers: BTreeMap::from([("x-api-key".to_string(), "key".to_string())]),
+                bearer_token: None,
+                hashing_function: Some(HashingFunction::Poseidon),
+                contract_type: Some("CUSTOM".to_string()),
+                page_size: Some(500),
+                max_concurrent_pages: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(indexer.endpoint.url.as_str(), "http://cli/");
+        assert_eq!(
+            indexer.endpoint.headers,
+            vec![("x-api-key".to_string(), "key".to_string())]
+        );
+        assert_eq!(indexer.hashing_function, HashingFunction::Keccak);
+        assert_eq!(indexer.contract_type, "CUSTOM");
+        assert_eq!(indexer.page_size, 500);
+    }
+
+    #[test]
+    fn test_rpc_endpoints() {
+        let config_file = ConfigFile::parse(
+            r#"
+            [rpc]
+            headers = { "x-api-key" = "key" }
+            bearer_token = "token"
+
+            [tls]
+            ca_certificates = ["ca.pem"]
+            "#,
+        )
+        .unwrap();
+        let endpoints = rpc_endpoints(
+            "https://a.example.com, https://b.example.com/v2",
+            &config_file.rpc,
+        )
+        .unwrap();
+        assert_eq!(endpoints.len(), 2);
+        assert_eq!(endpoints[1].url.as_str(), "https://b.example.com/v2");
+        assert_eq!(
+            endpoints[0].headers,
+            vec![
+                ("x-api-key".to_string(), "key".to_string()),
+                ("Authorization".to_string(), "Bearer token".to_string())
+            ]
+        );
+        assert_eq!(
+            config_file.tls.ca_certificates,
+            vec![PathBuf::from("ca.pem")]
+        );
+
+        assert!(rpc_endpoints("not a url", &config_file.rpc).is_err());
+    }
+
+    #[test]
+    fn test_invalid_env_var() {
+        // a key of its own, tests run in parallel in the same environment
+        env::set_var("HDP_TEST_ENV_VAR", "ten");
+        let error = env_var::<u32>("HDP_TEST_ENV_VAR").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid value of HDP_TEST_ENV_VAR: ten");
+        env::set_var("HDP_TEST_ENV_VAR", "10");
+        assert_eq!(env_var::<u32>("HDP_TEST_ENV_VAR").unwrap(), Some(10));
+        env::remove_var("HDP_TEST_ENV_VAR");
+        assert_eq!(env_var::<u32>("HDP_TEST_ENV_VAR").unwrap(), None);
+    }
+
+    #[test]
+    fn test_finality_options() {
+        let config_file = ConfigFile::parse(
+            r#"
+            [finality]
+            policy = "wait"
+            confirmation_depth = 64
+            max_wait_secs = 60
+            "#,
+        )
+        .unwrap();
+        let finality = finality_config(
+            FinalityOptions {
+                policy: Some(FinalityPolicy::Warn),
+                confirmation_depth: None,
+            },
+            config_file.finality,
+        )
+        .unwrap();
+        assert_eq!(finality.policy, FinalityPolicy::Warn);
+        assert_eq!(finality.confirmation_depth, Some(64));
+        assert_eq!(finality.max_wait, Duration::from_secs(60));
+        assert_eq!(
+            finality.poll_interval,
+            FinalityConfig::default().poll_interval
+        );
+
+        assert!(ConfigFile::parse("[finality]\npolicy = \"ignore\"").is_err());
 
-        CONFIG
-            .get_or_init(|| async {
-                Config {
-                    rpc_url,
-                    datalakes,
-                    tasks,
-                    chain_id,
-                }
-            })
-            .await
+        let config_file = ConfigFile::parse("[evaluator]\nmax_concurrent_datalakes = 8").unwrap();
+        assert_eq!(config_file.evaluator.max_concurrent_datalakes, Some(8));
     }
 }
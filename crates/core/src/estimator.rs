@@ -0,0 +1,368 @@
+//! Dry-run cost estimation of a batch of tasks, without fetching any chain data.
+//!
+//! The counts of headers, proofs and requests follow how the evaluator fetches the data of
+//! the batch: datalakes sharing blocks, accounts or slots fetch them once. Sizes and Cairo
+//! steps are rough averages, to compare batches and size a run, not to bill it.
+
+use std::{collections::BTreeSet, fmt::Display};
+
+use alloy_primitives::{Address, StorageKey};
+use serde::Serialize;
+
+use hdp_primitives::datalake::{
+    block_sampled::BlockSampledCollection, envelope::DatalakeEnvelope,
+    transactions::TransactionsCollection,
+};
+use hdp_provider::evm::config::DEFAULT_INDEXER_PAGE_SIZE;
+
+use crate::{
+    aggregate_fn::AggregationFunction,
+    task::{ComputationalTask, ComputationalTaskWithDatalake},
+};
+
+/// Transactions assumed in a block, as their count is not known without fetching the block
+pub const DEFAULT_TRANSACTIONS_PER_BLOCK: u64 = 150;
+
+/// Average sizes in bytes in the output file and in the Cairo input
+const HEADER_SIZE: (u64, u64) = (1_800, 2_200);
+const ACCOUNT_PROOF_SIZE: (u64, u64) = (6_600, 9_100);
+const STORAGE_PROOF_SIZE: (u64, u64) = (2_500, 3_500);
+const TRANSACTION_PROOF_SIZE: (u64, u64) = (2_100, 2_800);
+const RECEIPT_PROOF_SIZE: (u64, u64) = (2_500, 2_600);
+const TASK_SIZE: (u64, u64) = (1_100, 600);
+
+/// Cairo steps to verify a header against its MMR, and a proof against its trie root
+const HEADER_STEPS: u64 = 5_000;
+const ACCOUNT_PROOF_STEPS: u64 = 40_000;
+const STORAGE_PROOF_STEPS: u64 = 25_000;
+const TRANSACTION_PROOF_STEPS: u64 = 20_000;
+/// Cairo steps of a task besides its aggregation
+const TASK_STEPS: u64 = 5_000;
+const AGGREGATE_STEPS_PER_VALUE: u64 = 100;
+const MERKLE_STEPS_PER_VALUE: u64 = 1_000;
+
+/// RPC calls to build the transactions trie, and the receipts trie, of a block
+const TRANSACTIONS_TRIE_RPC_CALLS: u64 = 1;
+const RECEIPTS_TRIE_RPC_CALLS: u64 = 2;
+
+/// [`EstimatorConfig`] sets what the estimator can't know without fetching.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EstimatorConfig {
+    /// Blocks fetched from the indexer per request
+    pub indexer_page_size: u64,
+    /// Transactions assumed in the blocks targeted by transactions datalakes
+    pub transactions_per_block: u64,
+}
+
+impl Default for EstimatorConfig {
+    fn default() -> Self {
+        Self {
+            indexer_page_size: DEFAULT_INDEXER_PAGE_SIZE,
+            transactions_per_block: DEFAULT_TRANSACTIONS_PER_BLOCK,
+        }
+    }
+}
+
+/// Estimated cost of a batch of tasks
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BatchEstimate {
+    /// Headers proven in the output
+    pub headers: u64,
+    /// Requests to the MMR indexer
+    pub indexer_requests: u64,
+    pub account_proofs: u64,
+    pub storage_proofs: u64,
+    /// Transactions and receipts tries built from blocks
+    pub transactions_tries: u64,
+    /// Proofs of transactions and receipts
+    pub transaction_proofs: u64,
+    /// Requests to the RPC endpoints
+    pub rpc_calls: u64,
+    /// Size of the output file in bytes
+    pub output_size: u64,
+    /// Size of the Cairo input file in bytes
+    pub cairo_input_size: u64,
+    pub cairo_steps: u64,
+    pub tasks: Vec<TaskEstimate>,
+}
+
+/// Estimated cost of one task of a batch
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskEstimate {
+    pub task_commitment: String,
+    /// Sampled collection and aggregate function, e.g. `storage/SUM`
+    pub task_type: String,
+    pub sampled_values: u64,
+    /// Steps of the task run alone, verifying the data it shares with other tasks
+    pub cairo_steps: u64,
+}
+
+/// Data a datalake samples, the way it is fetched and proven
+#[derive(Default)]
+struct SampledData {
+    header_ranges: BTreeSet<(u64, u64)>,
+    headers: BTreeSet<u64>,
+    accounts: BTreeSet<(Address, u64)>,
+    storages: BTreeSet<(Address, StorageKey, u64)>,
+    /// Tries by block, `true` for the receipts trie
+    tries: BTreeSet<(u64, bool)>,
+    /// Transactions by block, trie and index
+    transactions: BTreeSet<(u64, bool, u64)>,
+}
+
+impl SampledData {
+    fn of(datalake: &DatalakeEnvelope, config: &EstimatorConfig) -> (Self, &'static str, u64) {
+        let mut data = SampledData::default();
+        match datalake {
+            DatalakeEnvelope::BlockSampled(datalake) => {
+                data.header_ranges
+                    .insert((datalake.block_range_start, datalake.block_range_end));
+                // sampled as the compiler keeps them, the blocks multiple of the increment
+                let increment = datalake.increment.max(1);
+                let blocks = (datalake.block_range_start..=datalake.block_range_end)
+                    .filter(|block| block % increment == 0)
+                    .collect::<Vec<_>>();
+                data.headers.extend(&blocks);
+                let collection = match &datalake.sampled_property {
+                    BlockSampledCollection::Header(_) => "header",
+                    BlockSampledCollection::Account(address, _) => {
+                        data.accounts
+                            .extend(blocks.iter().map(|block| (*address, *block)));
+                        "account"
+                    }
+                    BlockSampledCollection::Storage(address, slot) => {
+                        data.accounts
+                            .extend(blocks.iter().map(|block| (*address, *block)));
+                        data.storages
+                            .extend(blocks.iter().map(|block| (*address, *slot, *block)));
+                        "storage"
+                    }
+                };
+                (data, collection, blocks.len() as u64)
+            }
+            DatalakeEnvelope::Transactions(datalake) => {
+                let block = datalake.target_block;
+                let (collection, is_receipt) = match datalake.sampled_property {
+                    TransactionsCollection::Transactions(_) => ("transaction", false),
+                    TransactionsCollection::TranasactionReceipts(_) => ("receipt", true),
+                };
+                data.header_ranges.insert((block, block));
+                data.headers.insert(block);
+                data.tries.insert((block, is_receipt));
+                data.transactions.extend(
+                    (0..config.transactions_per_block)
+                        .step_by(datalake.increment.max(1) as usize)
+                        .map(|index| (block, is_receipt, index)),
+                );
+                let values = data.transactions.len() as u64;
+                (data, collection, values)
+            }
+        }
+    }
+
+    fn extend(&mut self, other: SampledData) {
+        self.header_ranges.extend(other.header_ranges);
+        self.headers.extend(other.headers);
+        self.accounts.extend(other.accounts);
+        self.storages.extend(other.storages);
+        self.tries.extend(other.tries);
+        self.transactions.extend(other.transactions);
+    }
+
+    fn cairo_steps(&self) -> u64 {
+        self.headers.len() as u64 * HEADER_STEPS
+            + self.accounts.len() as u64 * ACCOUNT_PROOF_STEPS
+            + self.storages.len() as u64 * STORAGE_PROOF_STEPS
+            + self.transactions.len() as u64 * TRANSACTION_PROOF_STEPS
+    }
+}
+
+/// Estimate what evaluating and proving the tasks over their datalakes will cost
+pub fn estimate(
+    computational_tasks: Vec<ComputationalTask>,
+    datalake_for_tasks: Vec<DatalakeEnvelope>,
+    config: &EstimatorConfig,
+) -> BatchEstimate {
+    let mut batch_data = SampledData::default();
+    let mut tasks = Vec::new();
+    let mut aggregation_steps = 0;
+    for (datalake, task) in datalake_for_tasks.into_iter().zip(computational_tasks) {
+        let (data, collection, sampled_values) = SampledData::of(&datalake, config);
+        let task_aggregation_steps = TASK_STEPS
+            + sampled_values
+                * match task.aggregate_fn_id {
+                    AggregationFunction::MERKLE => MERKLE_STEPS_PER_VALUE,
+                    _ => AGGREGATE_STEPS_PER_VALUE,
+                };
+        tasks.push(TaskEstimate {
+            task_type: format!("{}/{:?}", collection, task.aggregate_fn_id),
+            task_commitment: ComputationalTaskWithDatalake::new(datalake, task).commit(),
+            sampled_values,
+            cairo_steps: task_aggregation_steps + data.cairo_steps(),
+        });
+        aggregation_steps += task_aggregation_steps;
+        batch_data.extend(data);
+    }
+
+    let receipt_proofs = batch_data
+        .transactions
+        .iter()
+        .filter(|(_, is_receipt, _)| *is_receipt)
+        .count() as u64;
+    let transaction_proofs = batch_data.transactions.len() as u64 - receipt_proofs;
+    let size = |index: fn(&(u64, u64)) -> u64| {
+        batch_data.headers.len() as u64 * index(&HEADER_SIZE)
+            + batch_data.accounts.len() as u64 * index(&ACCOUNT_PROOF_SIZE)
+            + batch_data.storages.len() as u64 * index(&STORAGE_PROOF_SIZE)
+            + transaction_proofs * index(&TRANSACTION_PROOF_SIZE)
+            + receipt_proofs * index(&RECEIPT_PROOF_SIZE)
+            + tasks.len() as u64 * index(&TASK_SIZE)
+    };
+    let output_size = size(|size| size.0);
+    let cairo_input_size = size(|size| size.1);
+
+    // an `eth_getProof` per account and block, shared by its storage slots,
+    // and a request for the finality block
+    let tries_rpc_calls = batch_data
+        .tries
+        .iter()
+        .map(|(_, is_receipt)| match is_receipt {
+            true => RECEIPTS_TRIE_RPC_CALLS,
+            false => TRANSACTIONS_TRIE_RPC_CALLS,
+        })
+        .sum::<u64>();
+    let rpc_calls = batch_data.accounts.len() as u64 + tries_rpc_calls + 1;
+    let indexer_requests = batch_data
+        .header_ranges
+        .iter()
+        .map(|(start, end)| (end - start + 1).div_ceil(config.indexer_page_size.max(1)))
+        .sum();
+
+    BatchEstimate {
+        headers: batch_data.headers.len() as u64,
+        indexer_requests,
+        account_proofs: batch_data.accounts.len() as u64,
+        storage_proofs: batch_data.storages.len() as u64,
+        transactions_tries: batch_data.tries.len() as u64,
+        transaction_proofs: batch_data.transactions.len() as u64,
+        rpc_calls,
+        output_size,
+        cairo_input_size,
+        cairo_steps: batch_data.cairo_steps() + aggregation_steps,
+        tasks,
+    }
+}
+
+impl Display for BatchEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Headers: {}", self.headers)?;
+        writeln!(f, "Account proofs: {}", self.account_proofs)?;
+        writeln!(f, "Storage proofs: {}", self.storage_proofs)?;
+        writeln!(f, "Transactions tries: {}", self.transactions_tries)?;
+        writeln!(f, "Transaction proofs: {}", self.transaction_proofs)?;
+        writeln!(f, "RPC calls: {}", self.rpc_calls)?;
+        writeln!(f, "Indexer requests: {}", self.indexer_requests)?;
+        writeln!(f, "Output size: {} kB", self.output_size.div_ceil(1000))?;
+        writeln!(
+            f,
+            "Cairo input size: {} kB",
+            self.cairo_input_size.div_ceil(1000)
+        )?;
+        writeln!(f, "Cairo steps: {}", self.cairo_steps)?;
+        for task in &self.tasks {
+            writeln!(
+                f,
+                "Task {} ({}): {} values, {} Cairo steps",
+                task.task_commitment, task.task_type, task.sampled_values, task.cairo_steps
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdp_primitives::datalake::{
+        block_sampled::BlockSampledDatalake, transactions::TransactionsInBlockDatalake,
+    };
+
+    fn block_sampled(sampled_property: &str) -> DatalakeEnvelope {
+        DatalakeEnvelope::BlockSampled(
+            BlockSampledDatalake::new(100, 109, sampled_property.to_string(), 2).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_estimate_shares_fetched_data() {
+        let datalakes = vec![
+            block_sampled("header.base_fee_per_gas"),
+            block_sampled("account.0x7b2f05ce9ae365c3dbf30657e2dc6449989e83d6.nonce"),
+            block_sampled("storage.0x7b2f05ce9ae365c3dbf30657e2dc6449989e83d6.0x0000000000000000000000000000000000000000000000000000000000000002"),
+            DatalakeEnvelope::Transactions(
+                TransactionsInBlockDatalake::new(100, "tx_receipt.success".to_string(), 10)
+                    .unwrap(),
+            ),
+        ];
+        let tasks = vec![
+            ComputationalTask::new("avg", None),
+            ComputationalTask::new("sum", None),
+            ComputationalTask::new("merkle", None),
+            ComputationalTask::new("count", None),
+        ];
+        let config = EstimatorConfig {
+            indexer_page_size: 4,
+            transactions_per_block: 100,
+        };
+        let estimate = estimate(tasks, datalakes, &config);
+
+        // blocks 100, 102, ..., 108 are sampled, the account proofs are shared
+        assert_eq!(estimate.headers, 5);
+        assert_eq!(estimate.account_proofs, 5);
+        assert_eq!(estimate.storage_proofs, 5);
+        assert_eq!(estimate.transactions_tries, 1);
+        assert_eq!(estimate.transaction_proofs, 10);
+        // the range of the block sampled datalakes in 3 pages, and the target block
+        assert_eq!(estimate.indexer_requests, 4);
+        assert_eq!(estimate.rpc_calls, 5 + RECEIPTS_TRIE_RPC_CALLS + 1);
+
+        assert_eq!(estimate.tasks.len(), 4);
+        assert_eq!(estimate.tasks[2].task_type, "storage/MERKLE");
+        assert_eq!(estimate.tasks[3].task_type, "receipt/COUNT");
+        assert_eq!(estimate.tasks[3].sampled_values, 10);
+        assert_eq!(
+            estimate.tasks[0].cairo_steps,
+            TASK_STEPS + 5 * AGGREGATE_STEPS_PER_VALUE + 5 * HEADER_STEPS
+        );
+        // the shared data is proven once for the batch
+        let tasks_steps = estimate
+            .tasks
+            .iter()
+            .map(|task| task.cairo_steps)
+            .sum::<u64>();
+        assert!(estimate.cairo_steps < tasks_steps);
+        assert!(estimate.cairo_input_size > estimate.output_size);
+    }
+
+    #[test]
+    fn test_estimate_samples_multiples_of_the_increment() {
+        // blocks 102, 105 and 108 are sampled
+        let datalakes = vec![DatalakeEnvelope::BlockSampled(
+            BlockSampledDatalake::new(
+                101,
+                110,
+                "account.0x7b2f05ce9ae365c3dbf30657e2dc6449989e83d6.nonce".to_string(),
+                3,
+            )
+            .unwrap(),
+        )];
+        let estimate = estimate(
+            vec![ComputationalTask::new("sum", None)],
+            datalakes,
+            &EstimatorConfig::default(),
+        );
+        assert_eq!(estimate.headers, 3);
+        assert_eq!(estimate.account_proofs, 3);
+        assert_eq!(estimate.tasks[0].sampled_values, 3);
+    }
+}
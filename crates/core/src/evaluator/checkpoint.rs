@@ -0,0 +1,286 @@
+//! Checkpoints of long-running evaluations, to resume them after a failure.
+//!
+//! The checkpoint of a batch lives in its own directory, named after the chain and the
+//! commitments of its tasks. Every task is saved by task commitment with its compiled
+//! datalake once its datalake group is evaluated. The account and storage proofs are
+//! appended to the checkpoint as they are fetched, so that a killed run loses at most the
+//! proofs being written. The checkpoint is removed once the results are exported.
+
+use alloy_primitives::{hex, keccak256};
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tracing::{info, warn};
+
+use hdp_provider::evm::{FetchedProofs, ProofRecorder};
+
+use super::EvaluatedTask;
+
+/// Directory of the checkpoints when none is configured
+pub const DEFAULT_CHECKPOINT_DIR: &str = ".hdp/checkpoints";
+
+const TASKS_DIR: &str = "tasks";
+/// Fetched proofs, one JSON line per fetch
+const FETCHED_PROOFS_FILE: &str = "fetched_proofs.jsonl";
+
+/// [`CheckpointConfig`] sets where the evaluations are checkpointed, and whether to resume them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckpointConfig {
+    /// Directory holding the checkpoint of every batch
+    pub dir: PathBuf,
+    /// Resume from the checkpoint of the batch, instead of discarding it
+    pub resume: bool,
+}
+
+impl Default for CheckpointConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(DEFAULT_CHECKPOINT_DIR),
+            resume: false,
+        }
+    }
+}
+
+/// Checkpoint of one batch of tasks
+#[derive(Debug)]
+pub(super) struct Checkpoint {
+    dir: PathBuf,
+    /// Fetched proofs file, opened to append the proofs fetched by this run
+    fetched_proofs: Arc<Mutex<File>>,
+}
+
+impl Checkpoint {
+    /// Open the checkpoint of the batch of `task_commitments` on `chain_id`.
+    ///
+    /// Unless resuming, a checkpoint left by a previous run of the batch is discarded.
+    pub fn open(
+        config: &CheckpointConfig,
+        chain_id: u64,
+        task_commitments: &[String],
+    ) -> Result<Self> {
+        let mut batch = chain_id.to_be_bytes().to_vec();
+        for task_commitment in task_commitments {
+            batch.extend_from_slice(task_commitment.as_bytes());
+        }
+        let dir = config
+            .dir
+            .join(format!("{}-{}", chain_id, hex::encode(keccak256(batch))));
+
+        if dir.exists() {
+            if config.resume {
+                info!("Resuming the evaluation from {}", dir.display());
+            } else {
+                info!("Discarding the checkpoint {}", dir.display());
+                fs::remove_dir_all(&dir)
+                    .with_context(|| format!("Failed to discard checkpoint {}", dir.display()))?;
+            }
+        }
+        fs::create_dir_all(dir.join(TASKS_DIR))
+            .with_context(|| format!("Failed to create checkpoint {}", dir.display()))?;
+        let fetched_proofs = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(FETCHED_PROOFS_FILE))
+            .with_context(|| format!("Failed to create checkpoint {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            fetched_proofs: Arc::new(Mutex::new(fetched_proofs)),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Tasks evaluated by the previous runs, by task commitment
+    pub fn load_tasks(&self) -> Result<HashMap<String, EvaluatedTask>> {
+        let mut tasks = HashMap::new();
+        for entry in fs::read_dir(self.dir.join(TASKS_DIR))? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                let task: EvaluatedTask = read_json(&path)?;
+                tasks.insert(task.task_commitment.clone(), task);
+            }
+        }
+        Ok(tasks)
+    }
+
+    pub fn save_task(&self, task: &EvaluatedTask) -> Result<()> {
+        let path = self
+            .dir
+            .join(TASKS_DIR)
+            .join(format!("{}.json", task.task_commitment));
+        write_json(&path, task)
+    }
+
+    /// Proofs fetched by the previous runs, `None` if they fetched none.
+    ///
+    /// The last line is skipped if it is incomplete, a run killed while writing it
+    /// didn't save these proofs.
+    pub fn load_fetched_proofs(&self) -> Result<Option<FetchedProofs>> {
+        let path = self.dir.join(FETCHED_PROOFS_FILE);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read checkpoint {}", path.display()))?;
+        let mut lines = content.lines().peekable();
+        let mut loaded = FetchedProofs::default();
+        let mut is_empty = true;
+        while let Some(line) = lines.next() {
+            let fetched_proofs: FetchedProofs = match serde_json::from_str(line) {
+                Ok(fetched_proofs) => fetched_proofs,
+                Err(e) if lines.peek().is_none() && !content.ends_with('\n') => {
+                    warn!("Skipping the last fetched proofs of the checkpoint: {}", e);
+                    break;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Invalid checkpoint {}", path.display()))
+                }
+            };
+            loaded.accounts.extend(fetched_proofs.accounts);
+            loaded.storages.extend(fetched_proofs.storages);
+            is_empty = false;
+        }
+        Ok((!is_empty).then_some(loaded))
+    }
+
+    /// Recorder appending the proofs fetched by a provider to the checkpoint
+    pub fn proof_recorder(&self) -> ProofRecorder {
+        let file = self.fetched_proofs.clone();
+        let path = self.dir.join(FETCHED_PROOFS_FILE);
+        Arc::new(move |fetched_proofs| {
+            let appended = serde_json::to_string(&fetched_proofs)
+                .map_err(anyhow::Error::from)
+                .and_then(|line| {
+                    // lines are written under the lock, so that concurrent fetches don't interleave
+                    let mut file = file.lock().unwrap();
+                    Ok(file.write_all(format!("{}\n", line).as_bytes())?)
+                });
+            if let Err(e) = appended {
+                warn!(
+                    "Failed to checkpoint fetched proofs in {}: {}",
+                    path.display(),
+                    e
+                );
+            }
+        })
+    }
+
+    /// Remove the checkpoint once the results of the batch are exported
+    pub fn remove(self) {
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            warn!("Failed to remove checkpoint {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read checkpoint {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Invalid checkpoint {}", path.display()))
+}
+
+/// Write to a temporary file first, so that an interrupted run doesn't corrupt the checkpoint
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let temporary_path = path.with_extension("tmp");
+    fs::write(&temporary_path, serde_json::to_string(value)?)?;
+    fs::rename(&temporary_path, path)
+        .with_context(|| format!("Failed to save checkpoint {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compiler::{block_sampled::CompiledBlockSampledDatalake, CompiledDatalakeEnvelope},
+        evaluator::EvaluatedDatalake,
+    };
+    use alloy_primitives::U256;
+    use hdp_primitives::{datalake::datalake_type::DatalakeType, task_result::TaskResult};
+    use hdp_provider::evm::FetchedStorage;
+
+    fn evaluated_task(task_commitment: &str) -> EvaluatedTask {
+        EvaluatedTask {
+            index: 0,
+            task_commitment: task_commitment.to_string(),
+            encoded_task: "0x01".to_string(),
+            result: TaskResult::Uint256(U256::from(6776)),
+            datalake_result: CompiledDatalakeEnvelope::BlockSampled(CompiledBlockSampledDatalake {
+                values: vec!["0x1a78".to_string()],
+                headers: vec![],
+                accounts: vec![],
+                storages: vec![],
+                mmr_metas: vec![],
+            }),
+            evaluated_datalake: EvaluatedDatalake {
+                encoded_datalake: "0x02".to_string(),
+                datalake_type: DatalakeType::BlockSampled,
+                property_type: 1,
+                time_range: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let config = CheckpointConfig {
+            dir: std::env::temp_dir().join(format!("hdp-checkpoints-{}", std::process::id())),
+            resume: true,
+        };
+        let task_commitments = vec!["0xaa".to_string(), "0xbb".to_string()];
+        let fetched_proofs = FetchedProofs {
+            accounts: vec![],
+            storages: vec![FetchedStorage {
+                block_number: 10,
+                address: "0x75cec1db9dceb703200eaa6595f66885c962b920".to_string(),
+                slot: "0x01".to_string(),
+                value: "0x1a78".to_string(),
+                storage_proof: vec!["0xf8".to_string()],
+            }],
+        };
+
+        let checkpoint = Checkpoint::open(&config, 11155111, &task_commitments).unwrap();
+        assert!(checkpoint.load_fetched_proofs().unwrap().is_none());
+        checkpoint.save_task(&evaluated_task("0xaa")).unwrap();
+        checkpoint.proof_recorder()(fetched_proofs.clone());
+        // a line cut short by a killed run is skipped
+        OpenOptions::new()
+            .append(true)
+            .open(checkpoint.dir().join(FETCHED_PROOFS_FILE))
+            .unwrap()
+            .write_all(b"{\"accounts\":[")
+            .unwrap();
+
+        // another batch doesn't see the checkpoint
+        let other_batch = Checkpoint::open(&config, 1, &task_commitments).unwrap();
+        assert!(other_batch.load_tasks().unwrap().is_empty());
+        other_batch.remove();
+
+        let resumed = Checkpoint::open(&config, 11155111, &task_commitments).unwrap();
+        let tasks = resumed.load_tasks().unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks["0xaa"].result, TaskResult::Uint256(U256::from(6776)));
+        assert_eq!(resumed.load_fetched_proofs().unwrap(), Some(fetched_proofs));
+
+        // without resuming, the checkpoint is discarded
+        let restarted = Checkpoint::open(
+            &CheckpointConfig {
+                resume: false,
+                ..config.clone()
+            },
+            11155111,
+            &task_commitments,
+        )
+        .unwrap();
+        assert!(restarted.load_tasks().unwrap().is_empty());
+        restarted.remove();
+        fs::remove_dir_all(config.dir).unwrap();
+    }
+}
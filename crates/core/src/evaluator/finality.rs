@@ -0,0 +1,176 @@
+//! Guard against datalakes sampling blocks that can still be reorganized.
+//!
+//! A block is final when it is not newer than the finality block: the `finalized` block
+//! of the chain, or the chain head minus a confirmation depth when one is configured.
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use hdp_provider::evm::AbstractProvider;
+
+/// Default interval between two checks of the finality block, about one block
+pub const DEFAULT_FINALITY_POLL_INTERVAL: Duration = Duration::from_secs(12);
+/// Default upper bound of the wait for the sampled blocks to be final
+pub const DEFAULT_FINALITY_MAX_WAIT: Duration = Duration::from_secs(30 * 60);
+
+/// What to do when a datalake samples blocks that are not final yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FinalityPolicy {
+    /// Fail the evaluation
+    #[default]
+    Refuse,
+    /// Log a warning and evaluate the datalakes anyway
+    Warn,
+    /// Wait for the blocks to be final
+    Wait,
+}
+
+impl FromStr for FinalityPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "refuse" => Ok(FinalityPolicy::Refuse),
+            "warn" => Ok(FinalityPolicy::Warn),
+            "wait" => Ok(FinalityPolicy::Wait),
+            _ => bail!("Unknown finality policy: {}", s),
+        }
+    }
+}
+
+impl Display for FinalityPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FinalityPolicy::Refuse => write!(f, "refuse"),
+            FinalityPolicy::Warn => write!(f, "warn"),
+            FinalityPolicy::Wait => write!(f, "wait"),
+        }
+    }
+}
+
+/// [`FinalityConfig`] sets which blocks are final, and what to do with the others.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FinalityConfig {
+    pub policy: FinalityPolicy,
+    /// Blocks at least this deep below the chain head are final.
+    /// Without it, the `finalized` block of the chain is used.
+    pub confirmation_depth: Option<u64>,
+    /// Interval between two checks of the finality block, with the `wait` policy
+    pub poll_interval: Duration,
+    /// Upper bound of the wait, with the `wait` policy
+    pub max_wait: Duration,
+}
+
+impl Default for FinalityConfig {
+    fn default() -> Self {
+        Self {
+            policy: FinalityPolicy::default(),
+            confirmation_depth: None,
+            poll_interval: DEFAULT_FINALITY_POLL_INTERVAL,
+            max_wait: DEFAULT_FINALITY_MAX_WAIT,
+        }
+    }
+}
+
+impl FinalityConfig {
+    /// Check that `last_block` is final, applying the policy if it's not.
+    ///
+    /// Returns the finality block the check was made against.
+    pub async fn check(&self, last_block: u64, provider: &AbstractProvider) -> Result<u64> {
+        let start_wait = Instant::now();
+        loop {
+            let finality_block = self.get_finality_block(provider).await?;
+            if self.can_evaluate(last_block, finality_block)? {
+                return Ok(finality_block);
+            }
+            if start_wait.elapsed() >= self.max_wait {
+                bail!(
+                    "Block {} is still not final after waiting {:?}, the finality block is {}",
+                    last_block,
+                    self.max_wait,
+                    finality_block
+                );
+            }
+            info!(
+                "Waiting for block {} to be final, the finality block is {}",
+                last_block, finality_block
+            );
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    async fn get_finality_block(&self, provider: &AbstractProvider) -> Result<u64> {
+        match self.confirmation_depth {
+            Some(confirmation_depth) => Ok(provider
+                .get_latest_block_number()
+                .await?
+                .saturating_sub(confirmation_depth)),
+            None => provider.get_finalized_block_number().await,
+        }
+    }
+
+    /// Whether the datalakes can be evaluated with `last_block` sampled, `false` to wait
+    fn can_evaluate(&self, last_block: u64, finality_block: u64) -> Result<bool> {
+        if last_block <= finality_block {
+            return Ok(true);
+        }
+        match self.policy {
+            FinalityPolicy::Refuse => bail!(
+                "Block {} is not final yet, the finality block is {}",
+                last_block,
+                finality_block
+            ),
+            FinalityPolicy::Warn => {
+                warn!(
+                    "Block {} is not final yet, the finality block is {}: results may be invalidated by a reorg",
+                    last_block, finality_block
+                );
+                Ok(true)
+            }
+            FinalityPolicy::Wait => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_can_evaluate() {
+        let config = |policy| FinalityConfig {
+            policy,
+            ..Default::default()
+        };
+        for policy in [
+            FinalityPolicy::Refuse,
+            FinalityPolicy::Warn,
+            FinalityPolicy::Wait,
+        ] {
+            assert!(config(policy).can_evaluate(100, 100).unwrap());
+        }
+
+        let error = config(FinalityPolicy::Refuse)
+            .can_evaluate(101, 100)
+            .unwrap_err();
+        assert!(error.to_string().contains("Block 101 is not final yet"));
+        assert!(config(FinalityPolicy::Warn).can_evaluate(101, 100).unwrap());
+        assert!(!config(FinalityPolicy::Wait).can_evaluate(101, 100).unwrap());
+    }
+
+    #[test]
+    fn test_finality_policy_from_str() {
+        assert_eq!(
+            FinalityPolicy::from_str("Wait").unwrap(),
+            FinalityPolicy::Wait
+        );
+        assert!(FinalityPolicy::from_str("ignore").is_err());
+    }
+}
@@ -1,47 +1,96 @@
 use alloy_dyn_abi::DynSolValue;
 use alloy_merkle_tree::standard_binary_tree::StandardMerkleTree;
-use alloy_primitives::{hex::FromHex, FixedBytes, Keccak256, B256, U256};
-use anyhow::{bail, Result};
+use alloy_primitives::{hex::FromHex, FixedBytes, Keccak256};
+use anyhow::{bail, Context, Result};
+use futures::{stream, StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
-    str::FromStr,
-    sync::Arc,
+    time::Duration,
 };
-use tokio::sync::RwLock;
+use tracing::info;
 
 use crate::{
-    compiler::{CompiledDatalakeEnvelope, DatalakeCompiler},
+    compiler::{block_sampled::prefetch_storages, CompiledDatalakeEnvelope, DatalakeCompiler},
     task::ComputationalTaskWithDatalake,
 };
 
+use self::{
+    checkpoint::{Checkpoint, CheckpointConfig},
+    finality::FinalityConfig,
+    progress::{EvaluationEvent, EvaluationHooks},
+};
 use super::task::ComputationalTask;
 
-use hdp_primitives::datalake::{
-    block_sampled::output::{Account, Storage},
-    datalake_type::DatalakeType,
-    envelope::DatalakeEnvelope,
-    output::{Header, MMRMeta, ProcessedResult, Task},
-    transactions::output::{Transaction, TransactionReceipt},
+use hdp_primitives::{
+    datalake::{
+        block_sampled::output::{Account, Storage},
+        datalake_type::DatalakeType,
+        envelope::DatalakeEnvelope,
+        output::{Header, MMRMeta, ProcessedResult, Task},
+        time_range::ResolvedTimeRange,
+        transactions::output::{Transaction, TransactionReceipt},
+    },
+    task_result::TaskResult,
 };
 
 use hdp_provider::evm::AbstractProvider;
 
+pub mod checkpoint;
+pub mod finality;
+pub mod progress;
+
+/// Default number of datalakes compiled concurrently
+pub const DEFAULT_MAX_CONCURRENT_DATALAKES: usize = 4;
+/// Number of times the headers of the tasks are proven again when MMRs grow during a run
+const MAX_REPROVE_ROUNDS: usize = 3;
+
+/// [`EvaluatorConfig`] configures how the tasks are evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvaluatorConfig {
+    /// Which sampled blocks are final, and what to do with the others
+    pub finality: FinalityConfig,
+    /// Maximum number of datalakes compiled at the same time.
+    /// Tasks sharing a datalake are evaluated over a single compilation of it.
+    pub max_concurrent_datalakes: usize,
+    /// Checkpoint the evaluation to resume it after a failure, not checkpointed if `None`
+    pub checkpoint: Option<CheckpointConfig>,
+    /// Stop the evaluation if it takes longer
+    pub timeout: Option<Duration>,
+}
+
+impl Default for EvaluatorConfig {
+    fn default() -> Self {
+        Self {
+            finality: FinalityConfig::default(),
+            max_concurrent_datalakes: DEFAULT_MAX_CONCURRENT_DATALAKES,
+            checkpoint: None,
+            timeout: None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EvaluationResult {
     /// task_commitment -> fetched datalake relevant data
     pub fetched_datalake_results: HashMap<String, CompiledDatalakeEnvelope>,
     /// task_commitment -> compiled_result
-    pub compiled_results: HashMap<String, String>,
+    pub compiled_results: HashMap<String, TaskResult>,
     /// ordered task_commitment
     pub ordered_tasks: Vec<String>,
     /// encoded tasks task_commitment -> encoded task
     pub encoded_tasks: HashMap<String, String>,
     /// encoded datalakes task_commitment -> evaluated datalake
     pub encoded_datalakes: HashMap<String, EvaluatedDatalake>,
+    /// Finality block the sampled blocks were checked against
+    #[serde(default)]
+    pub finality_block: Option<u64>,
+    /// Chain the tasks were evaluated on, unknown for results saved before it was recorded
+    #[serde(default)]
+    pub chain_id: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EvaluatedDatalake {
     /// encoded datalake
     pub encoded_datalake: String,
@@ -49,6 +98,9 @@ pub struct EvaluatedDatalake {
     pub datalake_type: DatalakeType,
     /// ex. "header", "account", "storage"
     pub property_type: u8,
+    /// Time range the block range of the datalake was resolved from
+    #[serde(default)]
+    pub time_range: Option<ResolvedTimeRange>,
 }
 
 impl EvaluationResult {
@@ -59,9 +111,35 @@ impl EvaluationResult {
             fetched_datalake_results: HashMap::new(),
             encoded_tasks: HashMap::new(),
             encoded_datalakes: HashMap::new(),
+            finality_block: None,
+            chain_id: None,
         }
     }
 
+    /// Record the time range the block range of `datalake` was resolved from,
+    /// on every task of the datalake
+    pub fn record_time_range(
+        &mut self,
+        datalake: &DatalakeEnvelope,
+        time_range: ResolvedTimeRange,
+    ) -> Result<()> {
+        let encoded_datalake = datalake.encode()?;
+        let mut recorded = false;
+        for evaluated_datalake in self.encoded_datalakes.values_mut() {
+            if evaluated_datalake.encoded_datalake == encoded_datalake {
+                evaluated_datalake.time_range = Some(time_range);
+                recorded = true;
+            }
+        }
+        if !recorded {
+            bail!(
+                "Datalake {} is not in the evaluation result",
+                encoded_datalake
+            );
+        }
+        Ok(())
+    }
+
     fn get_processed_result(&self) -> Result<ProcessedResult> {
         // 1. build merkle tree
         let (tasks_merkle_tree, results_merkle_tree) = self.build_merkle_tree()?;
@@ -72,11 +150,11 @@ impl EvaluationResult {
 
         // 3. flatten the datalake result for all tasks
         let mut flattened_headers: HashSet<Header> = HashSet::new();
-        let mut flattened_accounts: HashSet<Account> = HashSet::new();
+        let mut merged_accounts: Vec<Account> = Vec::new();
         let mut flattened_storages: HashSet<Storage> = HashSet::new();
         let mut flattened_transactions: HashSet<Transaction> = HashSet::new();
         let mut flattened_transaction_receipts: HashSet<TransactionReceipt> = HashSet::new();
-        let mut assume_mmr_meta: Option<MMRMeta> = None;
+        let mut mmr_metas: Vec<MMRMeta> = Vec::new();
 
         let mut tasks = Vec::new();
 
@@ -90,14 +168,11 @@ impl EvaluationResult {
                 CompiledDatalakeEnvelope::BlockSampled(compiled_block_sampled) => {
                     let header_set: HashSet<Header> =
                         compiled_block_sampled.headers.iter().cloned().collect();
-                    let account_set: HashSet<Account> =
-                        compiled_block_sampled.accounts.iter().cloned().collect();
                     let storage_set: HashSet<Storage> =
                         compiled_block_sampled.storages.iter().cloned().collect();
                     flattened_headers.extend(header_set);
-                    flattened_accounts.extend(account_set);
+                    merge_accounts(&mut merged_accounts, &compiled_block_sampled.accounts);
                     flattened_storages.extend(storage_set);
-                    assume_mmr_meta = Some(compiled_block_sampled.mmr_meta.clone());
                 }
                 CompiledDatalakeEnvelope::Transactions(compiled_transactions_in_block) => {
                     let header_set: HashSet<Header> = compiled_transactions_in_block
@@ -119,9 +194,9 @@ impl EvaluationResult {
                     flattened_headers.extend(header_set);
                     flattened_transactions.extend(transaction_set);
                     flattened_transaction_receipts.extend(transaction_receipt_set);
-                    assume_mmr_meta = Some(compiled_transactions_in_block.mmr_meta.clone());
                 }
             }
+            merge_mmr_metas(&mut mmr_metas, compiled_datalake.get_mmr_metas()?)?;
 
             let result = match self.compiled_results.get(task_commitment) {
                 Some(result) => result,
@@ -148,27 +223,31 @@ impl EvaluationResult {
                 task_commitment: task_commitment.to_string(),
                 task_proof,
                 compiled_result: result.to_string(),
+                result_type: result.result_type(),
                 result_commitment: result_commitment.to_string(),
                 result_proof,
                 encoded_datalake: datalake.encoded_datalake.clone(),
                 datalake_type: datalake.datalake_type.into(),
                 property_type: datalake.property_type,
+                time_range: datalake.time_range,
             };
 
             tasks.push(task);
         }
 
-        let processed_result = ProcessedResult {
+        let mut processed_result = ProcessedResult {
             results_root: result_merkle_root.to_string(),
             tasks_root: task_merkle_root.to_string(),
             headers: flattened_headers.into_iter().collect(),
-            accounts: flattened_accounts.into_iter().collect(),
-            mmr: assume_mmr_meta.unwrap(),
+            accounts: merged_accounts,
+            mmr_metas,
             storages: flattened_storages.into_iter().collect(),
             transactions: flattened_transactions.into_iter().collect(),
             transaction_receipts: flattened_transaction_receipts.into_iter().collect(),
             tasks,
+            finality_block: self.finality_block,
         };
+        processed_result.sort_canonically();
 
         Ok(processed_result)
     }
@@ -206,24 +285,314 @@ impl EvaluationResult {
         Ok(())
     }
 
+    /// Save the full evaluation result, to export or merge it later without fetching chain data
+    pub fn save_evaluation_to_file(&self, file_path: &str) -> Result<()> {
+        let json = serde_json::to_string(self)?;
+        std::fs::write(file_path, json)
+            .with_context(|| format!("Failed to write evaluation result {}", file_path))?;
+        Ok(())
+    }
+
+    /// Load an evaluation result saved with [`EvaluationResult::save_evaluation_to_file`]
+    pub fn load_from_file(file_path: &str) -> Result<Self> {
+        let json = std::fs::read_to_string(file_path)
+            .with_context(|| format!("Failed to read evaluation result {}", file_path))?;
+        serde_json::from_str(&json)
+            .with_context(|| format!("Invalid evaluation result {}", file_path))
+    }
+
+    /// Merge the evaluation result of another run into this one, its tasks coming after these ones.
+    ///
+    /// The runs must be evaluated on the same chain, tasks evaluated by both runs must have the
+    /// same result, and the runs must agree on the state of the MMRs their headers are proven
+    /// against. The task and result merkle trees are built over the merged tasks when the
+    /// result is exported.
+    pub fn merge(&mut self, other: EvaluationResult) -> Result<()> {
+        self.merge_tasks(other, true)
+    }
+
+    /// Merge the evaluation results of other runs into this one like [`EvaluationResult::merge`],
+    /// then prove the headers of the tasks again against the latest states of their MMRs.
+    ///
+    /// Runs whose headers are proven against different states of a same MMR, e.g. runs made
+    /// as it grew, can only be merged this way. The runs must be evaluated on the chain of
+    /// `provider`.
+    pub async fn merge_and_reprove(
+        &mut self,
+        others: Vec<EvaluationResult>,
+        provider: &AbstractProvider,
+        config: &EvaluatorConfig,
+    ) -> Result<()> {
+        for evaluation_result in std::iter::once(&*self).chain(&others) {
+            if let Some(chain_id) = evaluation_result.chain_id {
+                if chain_id != provider.chain_id() {
+                    bail!(
+                        "Tasks evaluated on chain {} can't be proven again on chain {}",
+                        chain_id,
+                        provider.chain_id()
+                    );
+                }
+            }
+        }
+        for other in others {
+            self.merge_tasks(other, false)?;
+        }
+        self.reprove_stale_headers(provider, config).await
+    }
+
+    /// Merge the tasks of another run, checking the states of their MMRs if `check_mmr_metas`
+    fn merge_tasks(&mut self, mut other: EvaluationResult, check_mmr_metas: bool) -> Result<()> {
+        // check everything before changing this result
+        if let (Some(chain_id), Some(other_chain_id)) = (self.chain_id, other.chain_id) {
+            if chain_id != other_chain_id {
+                bail!(
+                    "The merged runs are evaluated on different chains: {} and {}",
+                    chain_id,
+                    other_chain_id
+                );
+            }
+        }
+        if check_mmr_metas {
+            let mut mmr_metas = Vec::new();
+            for compiled_datalake in self
+                .fetched_datalake_results
+                .values()
+                .chain(other.fetched_datalake_results.values())
+            {
+                merge_mmr_metas(&mut mmr_metas, compiled_datalake.get_mmr_metas()?).context(
+                    "The merged runs are proven against different states of an MMR, \
+                     their headers have to be proven again with a provider",
+                )?;
+            }
+        }
+        for task_commitment in &other.ordered_tasks {
+            other.check_task(task_commitment)?;
+            match self.compiled_results.get(task_commitment) {
+                Some(result) if *result != other.compiled_results[task_commitment] => bail!(
+                    "Task {} has different results in the merged runs: {} and {}",
+                    task_commitment,
+                    result,
+                    other.compiled_results[task_commitment]
+                ),
+                _ => {}
+            }
+        }
+
+        for task_commitment in std::mem::take(&mut other.ordered_tasks) {
+            // a task evaluated by both runs is kept once
+            if self.compiled_results.contains_key(&task_commitment) {
+                continue;
+            }
+            let evaluated_task = other.take_task(task_commitment);
+            self.push_task(evaluated_task);
+        }
+
+        // the sampled blocks of both runs are not newer than the latest finality block,
+        // unless one of the runs didn't check it
+        self.finality_block = self
+            .finality_block
+            .zip(other.finality_block)
+            .map(|(finality_block, other_finality_block)| finality_block.max(other_finality_block));
+        self.chain_id = self.chain_id.or(other.chain_id);
+        Ok(())
+    }
+
+    /// Prove the headers of the tasks again against the latest states of their MMRs,
+    /// only the tasks proven against an older state are fetched again
+    async fn reprove_stale_headers(
+        &mut self,
+        provider: &AbstractProvider,
+        config: &EvaluatorConfig,
+    ) -> Result<()> {
+        let header_ranges = self
+            .ordered_tasks
+            .iter()
+            .map(|task_commitment| {
+                self.check_task(task_commitment)?;
+                let evaluated_datalake = &self.encoded_datalakes[task_commitment];
+                let datalake = DatalakeEnvelope::from_index(
+                    evaluated_datalake.datalake_type.into(),
+                    &evaluated_datalake.encoded_datalake,
+                )?;
+                Ok(header_range(&datalake))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(last_block) = header_ranges.iter().map(|(_, end_block)| *end_block).max() {
+            provider.extend_local_mmr(last_block).await?;
+        }
+
+        let mut evaluated_tasks = std::mem::take(&mut self.ordered_tasks)
+            .into_iter()
+            .zip(header_ranges)
+            .map(|(task_commitment, header_range)| (self.take_task(task_commitment), header_range))
+            .collect::<Vec<_>>();
+        let reproved = reprove_stale_headers(&mut evaluated_tasks, provider, config, None).await;
+        for (evaluated_task, _) in evaluated_tasks {
+            self.push_task(evaluated_task);
+        }
+        reproved
+    }
+
+    /// Check that the values of a task are all in this result
+    fn check_task(&self, task_commitment: &str) -> Result<()> {
+        if !self.compiled_results.contains_key(task_commitment)
+            || !self.fetched_datalake_results.contains_key(task_commitment)
+            || !self.encoded_tasks.contains_key(task_commitment)
+            || !self.encoded_datalakes.contains_key(task_commitment)
+        {
+            bail!(
+                "Task {} is missing from the evaluation result",
+                task_commitment
+            );
+        }
+        Ok(())
+    }
+
+    /// Remove the values of a task checked with [`EvaluationResult::check_task`],
+    /// but not its place in the ordered tasks
+    fn take_task(&mut self, task_commitment: String) -> EvaluatedTask {
+        let (Some(result), Some(datalake_result), Some(encoded_task), Some(evaluated_datalake)) = (
+            self.compiled_results.remove(&task_commitment),
+            self.fetched_datalake_results.remove(&task_commitment),
+            self.encoded_tasks.remove(&task_commitment),
+            self.encoded_datalakes.remove(&task_commitment),
+        ) else {
+            unreachable!("tasks are checked before they are taken");
+        };
+        EvaluatedTask {
+            index: 0,
+            task_commitment,
+            encoded_task,
+            result,
+            datalake_result,
+            evaluated_datalake,
+        }
+    }
+
+    /// Add an evaluated task after the tasks of this result
+    fn push_task(&mut self, evaluated_task: EvaluatedTask) {
+        let task_commitment = evaluated_task.task_commitment;
+        // Save the datalake results
+        self.compiled_results
+            .insert(task_commitment.to_string(), evaluated_task.result);
+        // Save order of tasks
+        self.ordered_tasks.push(task_commitment.to_string());
+        // Save the fetched datalake results
+        self.fetched_datalake_results
+            .insert(task_commitment.to_string(), evaluated_task.datalake_result);
+        // Save the task data
+        self.encoded_tasks
+            .insert(task_commitment.to_string(), evaluated_task.encoded_task);
+        // Save the datalake data
+        self.encoded_datalakes
+            .insert(task_commitment, evaluated_task.evaluated_datalake);
+    }
+
     fn to_general_json(&self) -> Result<String> {
         let processed_result = self.get_processed_result()?;
         Ok(serde_json::to_string(&processed_result)?)
     }
 
     fn to_cairo_formatted_json(&self) -> Result<String> {
-        let processed_result = self.get_processed_result()?.to_cairo_format();
+        let processed_result = self.get_processed_result()?.to_cairo_format()?;
         Ok(serde_json::to_string(&processed_result)?)
     }
 }
 
+/// Positions of the tasks whose headers are proven against an older state of one of their
+/// MMRs than another task, or than the state it holds with the same size
+fn stale_tasks<'a>(
+    evaluated_tasks: impl IntoIterator<Item = &'a EvaluatedTask>,
+) -> Result<Vec<usize>> {
+    let task_mmr_metas = evaluated_tasks
+        .into_iter()
+        .map(|evaluated_task| evaluated_task.datalake_result.get_mmr_metas())
+        .collect::<Result<Vec<_>>>()?;
+    // the first largest state of each MMR
+    let mut latest_metas: HashMap<u64, &MMRMeta> = HashMap::new();
+    for mmr_meta in task_mmr_metas.iter().flatten() {
+        match latest_metas.get(&mmr_meta.id) {
+            Some(latest) if latest.size >= mmr_meta.size => {}
+            _ => {
+                latest_metas.insert(mmr_meta.id, mmr_meta);
+            }
+        }
+    }
+    Ok(task_mmr_metas
+        .iter()
+        .enumerate()
+        .filter(|(_, mmr_metas)| {
+            mmr_metas
+                .iter()
+                .any(|mmr_meta| latest_metas[&mmr_meta.id] != mmr_meta)
+        })
+        .map(|(index, _)| index)
+        .collect())
+}
+
+/// Add the MMR metas of a task to the ones of the previous tasks.
+///
+/// Tasks proven against the same MMR have to agree on its state, otherwise
+/// the headers of one of them can't be verified against the output.
+fn merge_mmr_metas(mmr_metas: &mut Vec<MMRMeta>, task_mmr_metas: Vec<MMRMeta>) -> Result<()> {
+    for task_mmr_meta in task_mmr_metas {
+        match mmr_metas.iter().find(|meta| meta.id == task_mmr_meta.id) {
+            Some(meta) if *meta == task_mmr_meta => {}
+            Some(meta) => bail!(
+                "MMR {} changed between tasks: root {} with size {}, then root {} with size {}",
+                meta.id,
+                meta.root,
+                meta.size,
+                task_mmr_meta.root,
+                task_mmr_meta.size
+            ),
+            None => mmr_metas.push(task_mmr_meta),
+        }
+    }
+    Ok(())
+}
+
+/// Add the accounts of a task to the ones of the previous tasks.
+///
+/// Tasks reading several storage slots of a contract share its account proofs,
+/// so an account appears once with a single proof per block.
+fn merge_accounts(accounts: &mut Vec<Account>, task_accounts: &[Account]) {
+    for task_account in task_accounts {
+        let account = match accounts
+            .iter_mut()
+            .find(|account| account.address == task_account.address)
+        {
+            Some(account) => account,
+            None => {
+                accounts.push(Account {
+                    proofs: vec![],
+                    ..task_account.clone()
+                });
+                accounts.last_mut().unwrap()
+            }
+        };
+        for proof in &task_account.proofs {
+            if !account
+                .proofs
+                .iter()
+                .any(|account_proof| account_proof.block_number == proof.block_number)
+            {
+                account.proofs.push(proof.clone());
+            }
+        }
+        account.proofs.sort_by_key(|proof| proof.block_number);
+    }
+}
+
 fn evaluation_result_to_result_commitment(
     task_commitment: &str,
-    compiled_result: &str,
+    compiled_result: &TaskResult,
 ) -> FixedBytes<32> {
     let mut hasher = Keccak256::new();
     hasher.update(Vec::from_hex(task_commitment).unwrap());
-    hasher.update(B256::from(U256::from_str(compiled_result).unwrap()));
+    for word in compiled_result.to_words() {
+        hasher.update(word);
+    }
     hasher.finalize()
 }
 
@@ -233,62 +602,358 @@ impl Default for EvaluationResult {
     }
 }
 
+/// [`Evaluation`] is the result of an evaluation, with the checkpoint of its batch.
+///
+/// The checkpoint is kept until the results are exported, so that a batch whose export
+/// fails can still be resumed.
+pub struct Evaluation {
+    pub result: EvaluationResult,
+    checkpoint: Option<Checkpoint>,
+}
+
+impl Evaluation {
+    /// Remove the checkpoint of the batch, once its results are exported
+    pub fn complete(self) {
+        if let Some(checkpoint) = self.checkpoint {
+            checkpoint.remove();
+        }
+    }
+}
+
+/// Evaluate the tasks over their datalakes.
+///
+/// `hooks` observe the progress of the evaluation, and can cancel it. A cancelled or timed out
+/// evaluation drops its outstanding requests, and is checkpointed like a failed one.
 pub async fn evaluator(
     computational_tasks: Vec<ComputationalTask>,
     datalake_for_tasks: Vec<DatalakeEnvelope>,
-    provider: Arc<RwLock<AbstractProvider>>,
-) -> Result<EvaluationResult> {
-    let mut results = EvaluationResult::new();
-
+    provider: &AbstractProvider,
+    config: &EvaluatorConfig,
+    hooks: &EvaluationHooks,
+) -> Result<Evaluation> {
     let tasks_with_datalake: Vec<ComputationalTaskWithDatalake> = datalake_for_tasks
         .into_iter()
         .zip(computational_tasks)
         .map(|(datalake, task)| ComputationalTaskWithDatalake::new(datalake, task))
         .collect();
 
-    // Evaulate the compute expressions
-    for task_with_datalake in tasks_with_datalake {
+    let Some(checkpoint_config) = &config.checkpoint else {
+        let evaluation = evaluate_tasks(tasks_with_datalake, provider, config, hooks, None);
+        return Ok(Evaluation {
+            result: hooks.run(evaluation, config.timeout).await?,
+            checkpoint: None,
+        });
+    };
+    let task_commitments = tasks_with_datalake
+        .iter()
+        .map(|task_with_datalake| task_with_datalake.commit())
+        .collect::<Vec<_>>();
+    let checkpoint = Checkpoint::open(checkpoint_config, provider.chain_id(), &task_commitments)?;
+    // the proofs are checkpointed as they are fetched, even for the datalakes not compiled yet
+    let provider = provider.with_proof_recorder(checkpoint.proof_recorder());
+    let evaluation = evaluate_tasks(
+        tasks_with_datalake,
+        &provider,
+        config,
+        hooks,
+        Some(&checkpoint),
+    );
+    match hooks.run(evaluation, config.timeout).await {
+        Ok(result) => Ok(Evaluation {
+            result,
+            checkpoint: Some(checkpoint),
+        }),
+        Err(e) => {
+            info!("Evaluation checkpointed in {}", checkpoint.dir().display());
+            Err(e)
+        }
+    }
+}
+
+/// Evaluate the tasks, skipping the ones already evaluated in the checkpoint
+async fn evaluate_tasks(
+    tasks_with_datalake: Vec<ComputationalTaskWithDatalake>,
+    provider: &AbstractProvider,
+    config: &EvaluatorConfig,
+    hooks: &EvaluationHooks,
+    checkpoint: Option<&Checkpoint>,
+) -> Result<EvaluationResult> {
+    let mut results = EvaluationResult::new();
+    results.chain_id = Some(provider.chain_id());
+
+    let mut checkpointed_tasks = HashMap::new();
+    if let Some(checkpoint) = checkpoint {
+        if let Some(fetched_proofs) = checkpoint.load_fetched_proofs()? {
+            provider.load_fetched_proofs(fetched_proofs);
+        }
+        checkpointed_tasks = checkpoint.load_tasks()?;
+    }
+
+    // Refuse, warn on or wait for blocks that can still be reorganized
+    if let Some(last_block) = tasks_with_datalake
+        .iter()
+        .map(|task_with_datalake| task_with_datalake.inner.get_last_block_number())
+        .max()
+    {
+        results.finality_block = Some(config.finality.check(last_block, provider).await?);
+        // Prove the headers of all the datalakes against the same local MMR
+        provider.extend_local_mmr(last_block).await?;
+    }
+
+    // Datalake groups whose tasks are all checkpointed are not compiled again
+    let mut restored_tasks = Vec::new();
+    let mut datalake_groups = Vec::new();
+    for datalake_group in group_by_datalake(tasks_with_datalake) {
+        let is_checkpointed = datalake_group.tasks.iter().all(|(_, task_with_datalake)| {
+            checkpointed_tasks.contains_key(&task_with_datalake.commit())
+        });
+        if !is_checkpointed {
+            datalake_groups.push(datalake_group);
+            continue;
+        }
+        for (index, task_with_datalake) in datalake_group.tasks {
+            let mut evaluated_task = checkpointed_tasks
+                .remove(&task_with_datalake.commit())
+                .unwrap();
+            evaluated_task.index = index;
+            hooks.emit(EvaluationEvent::TaskFinished {
+                task_commitment: evaluated_task.task_commitment.clone(),
+                result: evaluated_task.result.clone(),
+            });
+            restored_tasks.push((evaluated_task, header_range(&datalake_group.datalake)));
+        }
+    }
+    if !restored_tasks.is_empty() {
+        info!(
+            "Resumed {} tasks from the checkpoint, {} datalakes left to compile",
+            restored_tasks.len(),
+            datalake_groups.len()
+        );
+    }
+
+    let block_sampled_datalakes = datalake_groups
+        .iter()
+        .filter_map(|datalake_group| match &datalake_group.datalake {
+            DatalakeEnvelope::BlockSampled(datalake) => Some(datalake),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    match hooks.fetch_observer(None) {
+        Some(observer) => {
+            prefetch_storages(
+                &block_sampled_datalakes,
+                &provider.with_fetch_observer(observer),
+            )
+            .await?
+        }
+        None => prefetch_storages(&block_sampled_datalakes, provider).await?,
+    }
+
+    // Compile each datalake once, at most `max_concurrent_datalakes` at a time,
+    // and evaluate the compute expressions of its tasks over the shared values
+    let compiled_tasks = stream::iter(datalake_groups)
+        .map(|datalake_group| async move {
+            let header_range = header_range(&datalake_group.datalake);
+            let evaluated_tasks = evaluate_datalake_group(datalake_group, provider, hooks).await?;
+            if let Some(checkpoint) = checkpoint {
+                for evaluated_task in &evaluated_tasks {
+                    checkpoint.save_task(evaluated_task)?;
+                }
+            }
+            Ok::<_, anyhow::Error>(
+                evaluated_tasks
+                    .into_iter()
+                    .map(|evaluated_task| (evaluated_task, header_range))
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .buffer_unordered(config.max_concurrent_datalakes.max(1))
+        .try_concat()
+        .await?;
+
+    let mut evaluated_tasks = restored_tasks;
+    evaluated_tasks.extend(compiled_tasks);
+    reprove_stale_headers(&mut evaluated_tasks, provider, config, checkpoint).await?;
+
+    let mut evaluated_tasks = evaluated_tasks
+        .into_iter()
+        .map(|(evaluated_task, _)| evaluated_task)
+        .collect::<Vec<_>>();
+    // Keep the order in which the tasks were given
+    evaluated_tasks.sort_by_key(|evaluated_task| evaluated_task.index);
+
+    for evaluated_task in evaluated_tasks {
+        results.push_task(evaluated_task);
+    }
+
+    Ok(results)
+}
+
+/// Prove the headers of the tasks against the latest states of their MMRs.
+///
+/// Datalakes fetch their headers at different times, so their headers can be proven
+/// against different states of a growing MMR, and resumed tasks against the states as of
+/// the run that evaluated them. The headers of the tasks proven against an older state are
+/// fetched again, until every task is proven against the same state of each MMR.
+async fn reprove_stale_headers(
+    evaluated_tasks: &mut [(EvaluatedTask, (u64, u64))],
+    provider: &AbstractProvider,
+    config: &EvaluatorConfig,
+    checkpoint: Option<&Checkpoint>,
+) -> Result<()> {
+    for round in 0..=MAX_REPROVE_ROUNDS {
+        let stale_tasks = stale_tasks(
+            evaluated_tasks
+                .iter()
+                .map(|(evaluated_task, _)| evaluated_task),
+        )?;
+        if stale_tasks.is_empty() {
+            return Ok(());
+        }
+        if round == MAX_REPROVE_ROUNDS {
+            break;
+        }
+        info!(
+            "MMRs changed between tasks, proving the headers of {} tasks again",
+            stale_tasks.len()
+        );
+
+        // tasks sharing a block range share its headers
+        let stale_ranges = stale_tasks
+            .iter()
+            .map(|index| evaluated_tasks[*index].1)
+            .collect::<HashSet<_>>();
+        stream::iter(stale_ranges)
+            .map(|(start_block, end_block)| {
+                provider.refetch_sequencial_full_header_with_proof(start_block, end_block)
+            })
+            .buffer_unordered(config.max_concurrent_datalakes.max(1))
+            .try_collect::<()>()
+            .await?;
+        for index in stale_tasks {
+            let (evaluated_task, (start_block, end_block)) = &mut evaluated_tasks[index];
+            evaluated_task
+                .datalake_result
+                .reprove_headers(*start_block, *end_block, provider)
+                .await?;
+            if let Some(checkpoint) = checkpoint {
+                checkpoint.save_task(evaluated_task)?;
+            }
+        }
+    }
+    bail!(
+        "MMRs kept growing while proving the headers of the tasks again, after {} rounds",
+        MAX_REPROVE_ROUNDS
+    )
+}
+
+/// Tasks sharing a same datalake, with their position in the given tasks
+struct DatalakeGroup {
+    datalake: DatalakeEnvelope,
+    tasks: Vec<(usize, ComputationalTaskWithDatalake)>,
+}
+
+/// Task evaluated over the values of its compiled datalake
+#[derive(Serialize, Deserialize)]
+struct EvaluatedTask {
+    /// Position in the given tasks, not checkpointed as it depends on the batch
+    #[serde(skip)]
+    index: usize,
+    task_commitment: String,
+    encoded_task: String,
+    result: TaskResult,
+    datalake_result: CompiledDatalakeEnvelope,
+    evaluated_datalake: EvaluatedDatalake,
+}
+
+/// First and last blocks of the headers a datalake is compiled from
+fn header_range(datalake: &DatalakeEnvelope) -> (u64, u64) {
+    match datalake {
+        DatalakeEnvelope::BlockSampled(datalake) => {
+            (datalake.block_range_start, datalake.block_range_end)
+        }
+        DatalakeEnvelope::Transactions(datalake) => (datalake.target_block, datalake.target_block),
+    }
+}
+
+/// Group the tasks by datalake commitment, in the order the datalakes first appear
+fn group_by_datalake(
+    tasks_with_datalake: Vec<ComputationalTaskWithDatalake>,
+) -> Vec<DatalakeGroup> {
+    let mut datalake_groups: Vec<DatalakeGroup> = Vec::new();
+    let mut group_indexes: HashMap<String, usize> = HashMap::new();
+    for (index, task_with_datalake) in tasks_with_datalake.into_iter().enumerate() {
+        let datalake_commitment = task_with_datalake.inner.get_commitment();
+        match group_indexes.get(&datalake_commitment) {
+            Some(group_index) => datalake_groups[*group_index]
+                .tasks
+                .push((index, task_with_datalake)),
+            None => {
+                group_indexes.insert(datalake_commitment, datalake_groups.len());
+                datalake_groups.push(DatalakeGroup {
+                    datalake: task_with_datalake.inner.clone(),
+                    tasks: vec![(index, task_with_datalake)],
+                });
+            }
+        }
+    }
+    datalake_groups
+}
+
+/// Compile the datalake of the group, and apply the aggregate function of every task to its values
+async fn evaluate_datalake_group(
+    datalake_group: DatalakeGroup,
+    provider: &AbstractProvider,
+    hooks: &EvaluationHooks,
+) -> Result<Vec<EvaluatedTask>> {
+    let datalake = datalake_group.datalake;
+    let datalake_commitment = datalake.get_commitment();
+    hooks.emit(EvaluationEvent::DatalakeStarted {
+        datalake_commitment: datalake_commitment.clone(),
+        tasks: datalake_group.tasks.len(),
+    });
+    let evaluated_datalake = EvaluatedDatalake {
+        encoded_datalake: datalake.encode()?,
+        datalake_type: datalake.get_datalake_type(),
+        property_type: datalake.get_collection_type().to_index(),
+        time_range: None,
+    };
+    let compiler = DatalakeCompiler::new(datalake);
+    let datalake_result = match hooks.fetch_observer(Some(datalake_commitment)) {
+        Some(observer) => {
+            compiler
+                .compile(&provider.with_fetch_observer(observer))
+                .await?
+        }
+        None => compiler.compile(provider).await?,
+    };
+    let values = datalake_result.get_values();
+
+    let mut evaluated_tasks = Vec::new();
+    for (index, task_with_datalake) in datalake_group.tasks {
         // task_commitment is the unique identifier for the task
         let task_commitment = task_with_datalake.commit();
         // Encode the task
         let encoded_task = task_with_datalake.encode()?;
-        let inner_datalake = task_with_datalake.inner;
-        let encoded_datalake = inner_datalake.encode()?;
-        let datalake_type = inner_datalake.get_datalake_type();
-        let property_type = inner_datalake.get_collection_type().to_index();
-        let compiler = DatalakeCompiler::new(inner_datalake);
-        let datalake_result = compiler.compile(&provider).await?;
-
         let aggregation_fn = &task_with_datalake.task.aggregate_fn_id;
         let fn_context = task_with_datalake.task.aggregate_fn_ctx;
         // Compute datalake over specified aggregation function
-        let result = aggregation_fn.operation(&datalake_result.get_values(), fn_context)?;
-        // Save the datalake results
-        results
-            .compiled_results
-            .insert(task_commitment.to_string(), result);
-        // Save order of tasks
-        results.ordered_tasks.push(task_commitment.to_string());
-        // Save the fetched datalake results
-        results
-            .fetched_datalake_results
-            .insert(task_commitment.to_string(), datalake_result);
-        // Save the task data
-        results
-            .encoded_tasks
-            .insert(task_commitment.to_string(), encoded_task);
-        // Save the datalake data
-        results.encoded_datalakes.insert(
+        let result = aggregation_fn.operation(&values, fn_context)?;
+        let result = TaskResult::parse(aggregation_fn.result_type(), &result)?;
+        hooks.emit(EvaluationEvent::TaskFinished {
+            task_commitment: task_commitment.clone(),
+            result: result.clone(),
+        });
+        evaluated_tasks.push(EvaluatedTask {
+            index,
             task_commitment,
-            EvaluatedDatalake {
-                encoded_datalake,
-                datalake_type,
-                property_type,
-            },
-        );
+            encoded_task,
+            result,
+            datalake_result: datalake_result.clone(),
+            evaluated_datalake: evaluated_datalake.clone(),
+        });
     }
-
-    Ok(results)
+    Ok(evaluated_tasks)
 }
 
 #[cfg(test)]
@@ -297,7 +962,8 @@ mod tests {
     use crate::compiler::block_sampled::CompiledBlockSampledDatalake;
 
     use super::*;
-    use hdp_primitives::datalake::output::*;
+    use alloy_primitives::U256;
+    use hdp_primitives::datalake::{block_sampled::BlockSampledDatalake, output::*, Datalake};
 
     fn setup() -> EvaluationResult {
         let mut init_eval_result = EvaluationResult::new();
@@ -308,6 +974,7 @@ mod tests {
                 headers: vec![Header {
                     rlp: "f90253a008a4f6a7d5055ce465e285415779bc338134600b750c06396531ce6a29d09f4ba01dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347941268ad189526ac0b386faf06effc46779c340ee6a0fa23637d8a5d4a624479b33410895951995bae67f7c16b00859f9ac630b9e020a0792c487bc3176e482c995a9a1a16041d456db8d52e0db6fb73b540a64e96feaca04406def0dad7a6c6ef8c41a59be6b5b89124391a5b0491c8a5339e859e24d7acb901001a820024432050a200d1bc129162042984e09002002806340a14630c0aca5060c140a0608e043199e90280a1418cb89f1020085394a48f412d00d05041ad00a09002801a30b50d10c008522a2203284384841e055052404040710462e48103580026004a4e6842518210c2060c0729944118e4d0801936d020008811bb0c0464028a0008219056543b1111890cac50c04805000a400040401089904927409ec6720b8001c80a204628d8400064b402a1220480c21418480c24d00446a743000180a880128245028010a00103a8036b06c119a20124c32482280cc14021b430082a9408840030d46c062010f0b290c194040888189e081100c1070280304c0a01808352229a8401c9c38084017f9a188465df90188a4e65746865726d696e64a0178bae25662326acf0824d8441db8493865a53b8c627dc8aea5eb50ed2102fdc8800000000000000008401d76098a06eb2bc6208c3733aa1158ff8a100cb5c7ad1706ac6c3fb95d28f28007a770403808404c20000a0195eac87285a920cb37eb2b2dcf6eb9853efa2547c386bfe58ca2ff0fe167eb5".to_string(),
                     proof: HeaderProof {
+                        mmr_id: 19,
                         leaf_idx: 660751,
                         mmr_path: vec![
                             "0x50b38c27a0e12585ae387c5fd218ccaea57f7ff72cd4739ed9ff0a29ba6fe7a".to_string(),
@@ -324,6 +991,7 @@ mod tests {
                     account_key: "0x962f445fc8476432660877b666f653759ea69189b60d2f4a7008e70555746ad1".to_string(),
                     proofs: vec![MPTProof {
                         block_number: 5382810,
+                        is_exclusion: false,
                         proof: vec![
                         "0xf90211a004a07b0ced9c4e49cf3574d029b4aca46893aaead889508635b766b8bd9ff49aa035557e7ab5adda1f7876e96caf874a825a03267c9bcbd85e14f3578f7b80980ba05190d1fdc6e8506a5cc08e7291498d62aafc44913c4b47dc998d3cff5a7fee29a0cc16f65cc93a89251834e9e703f7bca425ad644dcb8d7502870439a47e7377c3a014623f34ab8b17adca3cf7648bac3f59b67fccf9082cf8bfd1a5f58a3cc5483da07f046112f9c54206ecf2379d2c75c6a343e19f19563a615163d7f032e54b70baa0869bb928152f852a8fb130ba8b95597a49a7c4b53cb6ab7af56f0f2e0a9d22f9a0effce50b7901262428133a0829fc11baf319f1a0a5388ddb0546a55d26ddb01ca096bedf7371a32ebbfbf159c3688efb85b675fc9968274d30f436025735633fd9a09ae43877fde992c6b39eed307abcea8a922918ceb672cc5257f3a2f1d23210d3a0c496cfc0e6c6d082ad5d80d827ad3fd748da5fef22e321f98d110f55166c0104a012155dffd30839241dad6bdba97b30b3b0368ceaf46069aba61cf63c7735ef8aa02b78b3f87d1c29a10c6b584e47f2df8b2f6d333b6c03f62413db4fc732d8b543a05bd948e3417c7e63795702d6d8b249d6bad8f5b47e05058ad83f869e719c76d9a037f216a3e0a186c53c6216a6990f422bf38c28e68e4d29f65feca1d9d518acdca0e56801140a1beffc0a88cad0b104d40024b5bf9586d175496821aff4a649cb3380".to_string(),
                         "0xf90211a03f3580144cee82b6906ca3c934415e6b729e301bc1d2267f115e55ae0bd863b0a07a99440f7094ed5913f8b84f4adfed5f7c5a6fb472aca8c6c121f21c4c2568bea071880cb0a7afa1ff0ef3e4925dd813108ce7ba0ab307e6360d686f05f6c3ad64a07beefec05c82c8047f804931cc7c4641f6336b89b26f133afd7d4e02e58c32eea04c75cf284cd38baff74dcd2af18f1826aa7324d0d71fedbf686bfb9cd75c49e1a02c46c881f9ee6f9b938848e291cf72a18b622380e7033c191562cafddf528b2ca0acf9ae3071a0c5e58391f4b89c6e0a208c7b59bccb1b924dc969ced2c63d8105a01161e667fed4e9e05cdc6fd2fd7e684088ac4ab7f53ca3f1d78a34672651de83a0b6fb07d86d8868648d9a590bd28b7729143a42bca173002a599ca97935beb5caa060c35a900422f89e0d198859069c318255a6a3bbde2de3c2a82c4616febe648ba05fa9f9f55d1b1df4727a469f8c5fed99f5fa03c41e85e5910fd862edebb223a7a0f0ccedb4844f906a3f625945e8b2e1f8c89427612449e93d5c5bd98405bcc7aaa0b19a8af79ddf60dc01378271193e1dc2eda727c10cd76981924457ab8c420543a04961963e7ecd9f398dc7fdebfe8430e5a177c994d3a174a549f622190f5513c6a027c3e382e26b35145fe33fce2342fbd99e2ee6c84da7d4e2bffb228b7d11d3c2a02f082b9dabf679dabe90c6846a161b86e05ed5ceed7d0a90df02cd5bd7f2d51a80".to_string(),
@@ -342,24 +1010,25 @@ mod tests {
                     storage_key: "0x405787fa12a823e0f2b7631cc41b3ba8828b3321ca811111fa75cd3aa3bb5ace".to_string(),
                     proofs: vec![MPTProof {
                         block_number: 5382810,
+                        is_exclusion: false,
                         proof: vec![
                             "0xf8918080a0b7a7c859e6ddbad6c18adb60b9f48842e652021b4f8b875894b8b879568629f880a0e7f9c6d331c7d110c992550a7baa3e051adc1e26a53d928dbd517a313d221863808080808080a0e40cf9c20b1e8e4aaf3201dd3cb84ab06d2bac34e8dc3e918626e5c44c4f0707808080a0c01a2f302bfc71151daac60eeb4c1b73470845d4fe219e71644752abaafb02ab80".to_string(),"0xe9a0305787fa12a823e0f2b7631cc41b3ba8828b3321ca811111fa75cd3aa3bb5ace878609184e72a000".to_string(),
                         ],
                     }],
                 }
               ],
-              mmr_meta: MMRMeta {
+              mmr_metas: vec![MMRMeta {
                 root: "0x7e956408569267d909a31fa404a972db1360a7e02bc3858e97c7c21ff394057".to_string(),
                 size: 660813,
                 id:19,
                 peaks: vec!["0x06a2bfcd354f679b547aa151e4462b6bae75fd80a2a92e3767b24eab609d1d4".to_string(), "0x5967364928f2fee43c8244dc6290cd9d3ea8e9dcb4e072ef6a099e9605f241d".to_string(), "0x30d5538138ec908e6f3b6429ae49702607432c224ef10be72d23c11556f06a0".to_string(), "0x308f10140fbc6043127353ee21fab20d6c12f00ac7a8928911611b71ce5b1ab".to_string(), "0x122a500639912a0a918dc32a73b1268f3417abf6b72a6a0dc814f0986f5124d".to_string(), "0x3b2087462ad3d5c84593fdfeb72f7972695a35097e184d017470b5f99c411fd".to_string(), "0x75c56dd4e70cac0dd54944d78632700da4329824239eb1be974e3b66b56c8b9".to_string(), "0x00225132138a053a102fab30cdd9e04cdcb25ded860d7d93c2a288c7532273e".to_string(), "0x6e5d1c234047cd531f2a1406ab894f4c9487dbef207cf870cca897dea3cf5ee".to_string()],
-              },
+              }],
             })
 
         );
         init_eval_result.compiled_results.insert(
             "0x242fe0d1fa98c743f84a168ff10abbcca83cb9e0424f4541fab5041cd63d3387".to_string(),
-            "10000000000000".to_string(),
+            TaskResult::Uint256(U256::from(10000000000000u64)),
         );
         init_eval_result.ordered_tasks =
             vec!["0x242fe0d1fa98c743f84a168ff10abbcca83cb9e0424f4541fab5041cd63d3387".to_string()];
@@ -373,6 +1042,7 @@ mod tests {
                 encoded_datalake: "0x0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000052229a000000000000000000000000000000000000000000000000000000000052229a000000000000000000000000000000000000000000000000000000000000000100000000000000000000000000000000000000000000000000000000000000a000000000000000000000000000000000000000000000000000000000000000350375cec1db9dceb703200eaa6595f66885c962b92000000000000000000000000000000000000000000000000000000000000000020000000000000000000000".to_string(),
                 datalake_type:DatalakeType::BlockSampled,
                 property_type:3,
+                time_range: None,
             }
         );
 
@@ -393,4 +1063,332 @@ mod tests {
             "0xb540014ad1d08106489adb9d8c893947841c505f1f5794525f4cc8e5d3a92395".to_string()
         );
     }
+
+    fn mmr_meta(id: u64, size: u64) -> MMRMeta {
+        MMRMeta {
+            id,
+            root: format!("0x{:x}", size),
+            size,
+            peaks: vec![],
+        }
+    }
+
+    #[test]
+    fn test_merge_mmr_metas() {
+        let mut mmr_metas = vec![];
+        merge_mmr_metas(&mut mmr_metas, vec![mmr_meta(19, 10), mmr_meta(5, 3)]).unwrap();
+        merge_mmr_metas(&mut mmr_metas, vec![mmr_meta(5, 3), mmr_meta(7, 4)]).unwrap();
+        assert_eq!(
+            mmr_metas,
+            vec![mmr_meta(19, 10), mmr_meta(5, 3), mmr_meta(7, 4)]
+        );
+
+        let error = merge_mmr_metas(&mut mmr_metas, vec![mmr_meta(19, 11)])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("MMR 19 changed between tasks"));
+    }
+
+    #[test]
+    fn test_stale_tasks() {
+        let evaluated_task = |mmr_metas: Vec<MMRMeta>| EvaluatedTask {
+            index: 0,
+            task_commitment: "0xaa".to_string(),
+            encoded_task: "0x01".to_string(),
+            result: TaskResult::Uint256(U256::from(6776)),
+            datalake_result: CompiledDatalakeEnvelope::BlockSampled(CompiledBlockSampledDatalake {
+                values: vec![],
+                headers: vec![],
+                accounts: vec![],
+                storages: vec![],
+                mmr_metas,
+            }),
+            evaluated_datalake: EvaluatedDatalake {
+                encoded_datalake: "0x02".to_string(),
+                datalake_type: DatalakeType::BlockSampled,
+                property_type: 1,
+                time_range: None,
+            },
+        };
+        // a task resumed from a checkpoint, before the MMR 19 grew
+        let resumed_task = evaluated_task(vec![mmr_meta(19, 10)]);
+        let compiled_tasks = [
+            evaluated_task(vec![mmr_meta(19, 11), mmr_meta(5, 3)]),
+            evaluated_task(vec![mmr_meta(5, 3)]),
+        ];
+        assert!(stale_tasks(&compiled_tasks).unwrap().is_empty());
+        assert_eq!(
+            stale_tasks([&resumed_task].into_iter().chain(&compiled_tasks)).unwrap(),
+            vec![0]
+        );
+
+        // two datalake groups fetched before and after the MMR 19 grew
+        let mut tasks = vec![
+            evaluated_task(vec![mmr_meta(19, 11), mmr_meta(5, 3)]),
+            evaluated_task(vec![mmr_meta(19, 12)]),
+            evaluated_task(vec![mmr_meta(5, 3)]),
+        ];
+        assert_eq!(stale_tasks(&tasks).unwrap(), vec![0]);
+        // proven again after the MMR 19 grew once more, the other group is now behind
+        if let CompiledDatalakeEnvelope::BlockSampled(compiled) = &mut tasks[0].datalake_result {
+            compiled.mmr_metas = vec![mmr_meta(19, 13), mmr_meta(5, 3)];
+        }
+        assert_eq!(stale_tasks(&tasks).unwrap(), vec![1]);
+        if let CompiledDatalakeEnvelope::BlockSampled(compiled) = &mut tasks[1].datalake_result {
+            compiled.mmr_metas = vec![mmr_meta(19, 13)];
+        }
+        assert!(stale_tasks(&tasks).unwrap().is_empty());
+        let mut mmr_metas = vec![];
+        for task in &tasks {
+            merge_mmr_metas(
+                &mut mmr_metas,
+                task.datalake_result.get_mmr_metas().unwrap(),
+            )
+            .unwrap();
+        }
+    }
+
+    fn account(address: &str, block_numbers: &[u64]) -> Account {
+        Account {
+            address: address.to_string(),
+            account_key: format!("{}key", address),
+            proofs: block_numbers
+                .iter()
+                .map(|block_number| MPTProof {
+                    block_number: *block_number,
+                    proof: vec![format!("0x{:x}", block_number)],
+                    is_exclusion: false,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_merge_accounts() {
+        let mut accounts = vec![];
+        merge_accounts(
+            &mut accounts,
+            &[account("0xaa", &[2, 3]), account("0xbb", &[1])],
+        );
+        merge_accounts(&mut accounts, &[account("0xaa", &[1, 2])]);
+        assert_eq!(
+            accounts,
+            vec![account("0xaa", &[1, 2, 3]), account("0xbb", &[1])]
+        );
+    }
+
+    #[test]
+    fn test_processed_result_keeps_every_mmr() {
+        let mut evaluation_result = setup();
+        let task_commitment = evaluation_result.ordered_tasks[0].clone();
+        if let Some(CompiledDatalakeEnvelope::BlockSampled(compiled)) = evaluation_result
+            .fetched_datalake_results
+            .get_mut(&task_commitment)
+        {
+            compiled.mmr_metas.insert(0, mmr_meta(23, 1));
+        }
+
+        let processed_result = evaluation_result.get_processed_result().unwrap();
+        let mmr_ids = processed_result
+            .mmr_metas
+            .iter()
+            .map(|meta| meta.id)
+            .collect::<Vec<_>>();
+        assert_eq!(mmr_ids, vec![19, 23]);
+        assert_eq!(processed_result.headers[0].proof.mmr_id, 19);
+    }
+
+    #[test]
+    fn test_group_by_datalake() {
+        let datalake = |block_range_end| {
+            DatalakeEnvelope::BlockSampled(
+                BlockSampledDatalake::new(
+                    10399990,
+                    block_range_end,
+                    "header.base_fee_per_gas".to_string(),
+                    1,
+                )
+                .unwrap(),
+            )
+        };
+        let tasks_with_datalake = vec![
+            ComputationalTaskWithDatalake::new(
+                datalake(10400000),
+                ComputationalTask::new("avg", None),
+            ),
+            ComputationalTaskWithDatalake::new(
+                datalake(10400001),
+                ComputationalTask::new("sum", None),
+            ),
+            ComputationalTaskWithDatalake::new(
+                datalake(10400000),
+                ComputationalTask::new("min", None),
+            ),
+            ComputationalTaskWithDatalake::new(
+                datalake(10400000),
+                ComputationalTask::new("max", None),
+            ),
+        ];
+
+        let datalake_groups = group_by_datalake(tasks_with_datalake);
+        assert_eq!(datalake_groups.len(), 2);
+        assert_eq!(datalake_groups[0].datalake, datalake(10400000));
+        let indexes = datalake_groups
+            .iter()
+            .map(|group| group.tasks.iter().map(|(index, _)| *index).collect())
+            .collect::<Vec<Vec<_>>>();
+        assert_eq!(indexes, vec![vec![0, 2, 3], vec![1]]);
+    }
+
+    #[test]
+    fn test_processed_result_is_canonical() {
+        let mut evaluation_result = setup();
+        let task_commitment = evaluation_result.ordered_tasks[0].clone();
+        if let Some(CompiledDatalakeEnvelope::BlockSampled(compiled)) = evaluation_result
+            .fetched_datalake_results
+            .get_mut(&task_commitment)
+        {
+            let storage = compiled.storages[0].clone();
+            for (address, slot) in [("0xcc", "0x02"), ("0x00", "0x01"), ("0xcc", "0x01")] {
+                compiled.storages.push(Storage {
+                    address: address.to_string(),
+                    slot: slot.to_string(),
+                    ..storage.clone()
+                });
+            }
+            compiled.accounts.insert(0, account("0xff", &[2, 1]));
+        }
+
+        let processed_result = evaluation_result.get_processed_result().unwrap();
+        let storages = processed_result
+            .storages
+            .iter()
+            .map(|storage| (storage.address.as_str(), storage.slot.as_str()))
+            .collect::<Vec<_>>();
+        let mut sorted_storages = storages.clone();
+        sorted_storages.sort();
+        assert_eq!(storages, sorted_storages);
+        assert_eq!(storages[0], ("0x00", "0x01"));
+        assert_eq!(
+            processed_result.accounts.last().unwrap(),
+            &account("0xff", &[1, 2])
+        );
+
+        // the same evaluation always gives the same output
+        assert_eq!(
+            evaluation_result.to_general_json().unwrap(),
+            evaluation_result.to_general_json().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_record_time_range() {
+        let mut evaluation_result = setup();
+        let task_commitment = evaluation_result.ordered_tasks[0].clone();
+        let encoded_datalake = evaluation_result.encoded_datalakes[&task_commitment]
+            .encoded_datalake
+            .clone();
+        let datalake = DatalakeEnvelope::BlockSampled(
+            BlockSampledDatalake::decode(&encoded_datalake).unwrap(),
+        );
+        let time_range = ResolvedTimeRange {
+            start_timestamp: 1709150200,
+            end_timestamp: 1709150240,
+            first_block: 5382810,
+            first_block_timestamp: 1709150232,
+            last_block: 5382810,
+            last_block_timestamp: 1709150232,
+        };
+
+        evaluation_result
+            .record_time_range(&datalake, time_range)
+            .unwrap();
+        let processed_result = evaluation_result.get_processed_result().unwrap();
+        assert_eq!(processed_result.tasks[0].time_range, Some(time_range));
+
+        let other_datalake = DatalakeEnvelope::BlockSampled(
+            BlockSampledDatalake::new(1, 2, "header.number".to_string(), 1).unwrap(),
+        );
+        assert!(evaluation_result
+            .record_time_range(&other_datalake, time_range)
+            .is_err());
+    }
+
+    #[test]
+    fn test_merge_evaluation_results() {
+        let mut evaluation_result = setup();
+        evaluation_result.finality_block = Some(100);
+        let task_commitment = evaluation_result.ordered_tasks[0].clone();
+
+        // a result saved to a file is loaded back as it was
+        let file_path = std::env::temp_dir().join("hdp_test_merge_evaluation_results.json");
+        let file_path = file_path.to_str().unwrap();
+        evaluation_result
+            .save_evaluation_to_file(file_path)
+            .unwrap();
+        let loaded = EvaluationResult::load_from_file(file_path).unwrap();
+        std::fs::remove_file(file_path).unwrap();
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&evaluation_result).unwrap()
+        );
+
+        // another run with the same task and a new one
+        let mut other = setup();
+        other.finality_block = Some(120);
+        let new_task_commitment = format!("0x{}", "11".repeat(32));
+        let datalake_result = other.fetched_datalake_results[&task_commitment].clone();
+        let encoded_datalake = other.encoded_datalakes[&task_commitment].clone();
+        other.compiled_results.insert(
+            new_task_commitment.clone(),
+            TaskResult::Uint256(U256::from(1)),
+        );
+        other
+            .fetched_datalake_results
+            .insert(new_task_commitment.clone(), datalake_result);
+        other
+            .encoded_tasks
+            .insert(new_task_commitment.clone(), "".to_string());
+        other
+            .encoded_datalakes
+            .insert(new_task_commitment.clone(), encoded_datalake);
+        other.ordered_tasks.push(new_task_commitment.clone());
+
+        evaluation_result.merge(other).unwrap();
+        assert_eq!(
+            evaluation_result.ordered_tasks,
+            vec![task_commitment.clone(), new_task_commitment]
+        );
+        assert_eq!(evaluation_result.finality_block, Some(120));
+
+        // the same task with another result can't be merged
+        let mut conflicting = setup();
+        conflicting
+            .compiled_results
+            .insert(task_commitment.clone(), TaskResult::Uint256(U256::from(1)));
+        let error = evaluation_result.merge(conflicting).unwrap_err();
+        assert!(error.to_string().contains("has different results"));
+
+        // the runs must agree on the MMR states
+        let mut other_mmr = setup();
+        if let Some(CompiledDatalakeEnvelope::BlockSampled(compiled)) =
+            other_mmr.fetched_datalake_results.get_mut(&task_commitment)
+        {
+            compiled.mmr_metas[0].size += 1;
+        }
+        let error = evaluation_result.merge(other_mmr).unwrap_err();
+        assert!(error.to_string().contains("proven again"));
+        assert_eq!(evaluation_result.ordered_tasks.len(), 2);
+
+        // the runs must be evaluated on the same chain
+        evaluation_result.chain_id = Some(1);
+        let mut other_chain = setup();
+        other_chain.chain_id = Some(11155111);
+        let error = evaluation_result.merge(other_chain).unwrap_err();
+        assert!(error.to_string().contains("different chains"));
+        let mut same_chain = setup();
+        same_chain.chain_id = Some(1);
+        evaluation_result.merge(same_chain).unwrap();
+        assert_eq!(evaluation_result.chain_id, Some(1));
+    }
 }
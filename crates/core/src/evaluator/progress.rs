@@ -0,0 +1,178 @@
+//! Progress events of an evaluation, and its cancellation.
+//!
+//! Embedders observe an evaluation with a callback, or with the stream of [`event_stream`],
+//! and stop it with a [`CancellationToken`]. A stopped evaluation drops its outstanding
+//! RPC requests.
+
+use anyhow::{bail, Result};
+use std::{future::Future, sync::Arc, time::Duration};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+pub use tokio_util::sync::CancellationToken;
+
+use hdp_primitives::task_result::TaskResult;
+use hdp_provider::evm::progress::{FetchObserver, FetchProgress};
+
+/// Event of an evaluation
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvaluationEvent {
+    /// A datalake started to be compiled, for the given number of tasks
+    DatalakeStarted {
+        datalake_commitment: String,
+        tasks: usize,
+    },
+    /// Headers of a datalake fetched with their MMR proofs
+    HeadersFetched {
+        /// `None` for the storages prefetched for several datalakes
+        datalake_commitment: Option<String>,
+        fetched: u64,
+        total: u64,
+    },
+    /// Blocks of a datalake whose account or storage proofs are fetched
+    ProofsFetched {
+        /// `None` for the storages prefetched for several datalakes
+        datalake_commitment: Option<String>,
+        fetched: u64,
+        total: u64,
+    },
+    /// A task is evaluated, or resumed from the checkpoint
+    TaskFinished {
+        task_commitment: String,
+        result: TaskResult,
+    },
+}
+
+/// Called with every event of an evaluation
+pub type EvaluationObserver = Arc<dyn Fn(EvaluationEvent) + Send + Sync>;
+
+/// Observer sending the events to a stream, until the receiver is dropped
+pub fn event_stream() -> (EvaluationObserver, UnboundedReceiver<EvaluationEvent>) {
+    let (sender, receiver) = unbounded_channel();
+    let observer: EvaluationObserver = Arc::new(move |event| {
+        // the embedder stopped listening, the evaluation goes on
+        let _ = sender.send(event);
+    });
+    (observer, receiver)
+}
+
+/// [`EvaluationHooks`] lets an embedder observe and cancel an evaluation.
+#[derive(Clone, Default)]
+pub struct EvaluationHooks {
+    pub observer: Option<EvaluationObserver>,
+    /// Stop the evaluation once cancelled
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl EvaluationHooks {
+    pub(super) fn emit(&self, event: EvaluationEvent) {
+        if let Some(observer) = &self.observer {
+            observer(event);
+        }
+    }
+
+    /// Observer of the fetches of a datalake, reported as events of the evaluation
+    pub(super) fn fetch_observer(
+        &self,
+        datalake_commitment: Option<String>,
+    ) -> Option<FetchObserver> {
+        let observer = self.observer.clone()?;
+        Some(Arc::new(move |progress| {
+            let datalake_commitment = datalake_commitment.clone();
+            observer(match progress {
+                FetchProgress::Headers { fetched, total } => EvaluationEvent::HeadersFetched {
+                    datalake_commitment,
+                    fetched,
+                    total,
+                },
+                FetchProgress::Proofs { fetched, total } => EvaluationEvent::ProofsFetched {
+                    datalake_commitment,
+                    fetched,
+                    total,
+                },
+            })
+        }))
+    }
+
+    /// Run `evaluation` until it completes, is cancelled or times out.
+    /// The evaluation is dropped when stopped, with its outstanding requests.
+    pub(super) async fn run<T>(
+        &self,
+        evaluation: impl Future<Output = Result<T>>,
+        timeout: Option<Duration>,
+    ) -> Result<T> {
+        let cancelled = async {
+            match &self.cancellation {
+                Some(cancellation) => cancellation.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        let timed_out = async {
+            match timeout {
+                Some(timeout) => {
+                    tokio::time::sleep(timeout).await;
+                    timeout
+                }
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            result = evaluation => result,
+            _ = cancelled => bail!("Evaluation cancelled"),
+            timeout = timed_out => bail!("Evaluation timed out after {:?}", timeout),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stop_evaluation() {
+        let hooks = EvaluationHooks::default();
+        let value = hooks.run(async { Ok(1) }, None).await.unwrap();
+        assert_eq!(value, 1);
+
+        let never = || async {
+            std::future::pending::<()>().await;
+            Ok(())
+        };
+        let error = hooks
+            .run(never(), Some(Duration::from_millis(10)))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("timed out"));
+
+        let cancellation = CancellationToken::new();
+        let hooks = EvaluationHooks {
+            cancellation: Some(cancellation.clone()),
+            ..Default::default()
+        };
+        cancellation.cancel();
+        let error = hooks.run(never(), None).await.unwrap_err();
+        assert_eq!(error.to_string(), "Evaluation cancelled");
+    }
+
+    #[tokio::test]
+    async fn test_event_stream() {
+        let (observer, mut events) = event_stream();
+        let hooks = EvaluationHooks {
+            observer: Some(observer),
+            ..Default::default()
+        };
+        hooks.fetch_observer(Some("0xaa".to_string())).unwrap()(FetchProgress::Proofs {
+            fetched: 1,
+            total: 2,
+        });
+        drop(hooks);
+
+        assert_eq!(
+            events.recv().await,
+            Some(EvaluationEvent::ProofsFetched {
+                datalake_commitment: Some("0xaa".to_string()),
+                fetched: 1,
+                total: 2,
+            })
+        );
+        assert_eq!(events.recv().await, None);
+    }
+}
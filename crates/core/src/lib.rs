@@ -2,5 +2,6 @@ pub mod aggregate_fn;
 pub mod codec;
 pub mod compiler;
 pub mod config;
+pub mod estimator;
 pub mod evaluator;
 pub mod task;
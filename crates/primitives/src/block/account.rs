@@ -1,10 +1,13 @@
 use std::str::FromStr;
 
-use alloy_primitives::{hex, FixedBytes, U256};
+use alloy_primitives::{hex, keccak256, Address, FixedBytes, StorageKey, B256, U256};
 use alloy_rlp::{Decodable, Encodable as _, RlpDecodable, RlpEncodable};
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, RlpDecodable, RlpEncodable, PartialEq)]
+use crate::mpt::verify_hex_proof;
+
+#[derive(Debug, Default, RlpDecodable, RlpEncodable, PartialEq)]
 pub struct Account {
     pub nonce: u64,
     pub balance: U256,
@@ -36,6 +39,36 @@ impl Account {
     pub fn rlp_decode(rlp: &str) -> Self {
         <Account>::decode(&mut hex::decode(rlp).unwrap().as_slice()).unwrap()
     }
+
+    /// Verify the `eth_getProof` account proof of `address` against the state root of a block,
+    /// and return the account stored in the proven leaf, or `None` if the proof shows that
+    /// the account doesn't exist.
+    pub fn verify_proof(
+        state_root: B256,
+        address: &Address,
+        proof: &[String],
+    ) -> Result<Option<Self>> {
+        verify_hex_proof(state_root, keccak256(address).as_slice(), proof)?
+            .map(|leaf| {
+                Account::decode(&mut leaf.as_slice()).context("Invalid account in proof leaf")
+            })
+            .transpose()
+    }
+}
+
+/// Verify the `eth_getProof` storage proof of `slot` against the storage root of an account,
+/// and return the value stored in the proven leaf, or `None` if the proof shows that the
+/// slot is not set.
+pub fn verify_storage_proof(
+    storage_root: B256,
+    slot: &StorageKey,
+    proof: &[String],
+) -> Result<Option<U256>> {
+    verify_hex_proof(storage_root, keccak256(slot).as_slice(), proof)?
+        .map(|leaf| {
+            U256::decode(&mut leaf.as_slice()).context("Invalid storage value in proof leaf")
+        })
+        .transpose()
 }
 
 /// Account data from RPC `eth_getProof` response
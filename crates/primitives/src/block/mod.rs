@@ -1,3 +1,4 @@
 pub mod account;
 pub mod header;
+pub mod tag;
 pub mod tx;
@@ -0,0 +1,168 @@
+//! Blocks given by number, or relative to a block tag of the chain, e.g. `finalized-1000`.
+//!
+//! Tagged blocks move with the chain, so they are resolved to block numbers once, when the
+//! datalake is built, to keep its commitment deterministic.
+
+use anyhow::{bail, Context, Result};
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+/// Block tag of the chain, as understood by `eth_getBlockByNumber`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockTag {
+    Latest,
+    Safe,
+    Finalized,
+}
+
+impl FromStr for BlockTag {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            // `head` is the name some tools give to the latest block
+            "latest" | "head" => Ok(BlockTag::Latest),
+            "safe" => Ok(BlockTag::Safe),
+            "finalized" => Ok(BlockTag::Finalized),
+            _ => bail!("Unknown block tag: {}", s),
+        }
+    }
+}
+
+impl Display for BlockTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockTag::Latest => write!(f, "latest"),
+            BlockTag::Safe => write!(f, "safe"),
+            BlockTag::Finalized => write!(f, "finalized"),
+        }
+    }
+}
+
+/// [`BlockNumberOrTag`] is a block number, or the block `offset` blocks below a block tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockNumberOrTag {
+    Number(u64),
+    Tag { tag: BlockTag, offset: u64 },
+}
+
+impl BlockNumberOrTag {
+    pub fn tag(&self) -> Option<BlockTag> {
+        match self {
+            BlockNumberOrTag::Number(_) => None,
+            BlockNumberOrTag::Tag { tag, .. } => Some(*tag),
+        }
+    }
+
+    /// Block number, given the numbers of the blocks at the tags
+    pub fn resolve(&self, tagged_blocks: &HashMap<BlockTag, u64>) -> Result<u64> {
+        match self {
+            BlockNumberOrTag::Number(block_number) => Ok(*block_number),
+            BlockNumberOrTag::Tag { tag, offset } => {
+                let Some(tagged_block) = tagged_blocks.get(tag) else {
+                    bail!("Block tag {} is not resolved", tag);
+                };
+                match tagged_block.checked_sub(*offset) {
+                    Some(block_number) => Ok(block_number),
+                    None => bail!(
+                        "Block {} is before the genesis block, {} is block {}",
+                        self,
+                        tag,
+                        tagged_block
+                    ),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for BlockNumberOrTag {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(block_number) = s.parse() {
+            return Ok(BlockNumberOrTag::Number(block_number));
+        }
+        let (tag, offset) = match s.split_once('-') {
+            Some((tag, offset)) => (
+                tag,
+                offset
+                    .parse()
+                    .with_context(|| format!("Invalid block offset in {}", s))?,
+            ),
+            None => (s, 0),
+        };
+        let tag = tag.parse().with_context(|| {
+            format!(
+                "Invalid block {}: expected a number, latest (or head), safe or finalized, e.g. finalized-1000",
+                s
+            )
+        })?;
+        Ok(BlockNumberOrTag::Tag { tag, offset })
+    }
+}
+
+impl Display for BlockNumberOrTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockNumberOrTag::Number(block_number) => write!(f, "{}", block_number),
+            BlockNumberOrTag::Tag { tag, offset: 0 } => write!(f, "{}", tag),
+            BlockNumberOrTag::Tag { tag, offset } => write!(f, "{}-{}", tag, offset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_block_number_or_tag() {
+        assert_eq!(
+            "4952200".parse::<BlockNumberOrTag>().unwrap(),
+            BlockNumberOrTag::Number(4952200)
+        );
+        assert_eq!(
+            "latest".parse::<BlockNumberOrTag>().unwrap(),
+            BlockNumberOrTag::Tag {
+                tag: BlockTag::Latest,
+                offset: 0
+            }
+        );
+        assert_eq!(
+            "head-10".parse::<BlockNumberOrTag>().unwrap(),
+            BlockNumberOrTag::Tag {
+                tag: BlockTag::Latest,
+                offset: 10
+            }
+        );
+        let block = "finalized-1000".parse::<BlockNumberOrTag>().unwrap();
+        assert_eq!(
+            block,
+            BlockNumberOrTag::Tag {
+                tag: BlockTag::Finalized,
+                offset: 1000
+            }
+        );
+        assert_eq!(block.to_string(), "finalized-1000");
+        assert!("pending".parse::<BlockNumberOrTag>().is_err());
+        assert!("safe-".parse::<BlockNumberOrTag>().is_err());
+        assert!("safe+10".parse::<BlockNumberOrTag>().is_err());
+        assert!("-10".parse::<BlockNumberOrTag>().is_err());
+    }
+
+    #[test]
+    fn test_resolve_block_number_or_tag() {
+        let tagged_blocks = HashMap::from([(BlockTag::Finalized, 5000)]);
+        let resolve = |block: &str| {
+            block
+                .parse::<BlockNumberOrTag>()
+                .unwrap()
+                .resolve(&tagged_blocks)
+        };
+        assert_eq!(resolve("42").unwrap(), 42);
+        assert_eq!(resolve("finalized").unwrap(), 5000);
+        assert_eq!(resolve("finalized-1000").unwrap(), 4000);
+        assert!(resolve("finalized-5001").is_err());
+        assert!(resolve("latest").is_err());
+    }
+}
@@ -1,5 +1,11 @@
+use std::str::FromStr;
+
+use alloy_primitives::{Address, B256, U256};
+use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::utils::{hex_string_to_bytes, rlp_encode_list};
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct TxFromEtherscan {
@@ -24,3 +30,360 @@ pub struct TxFromEtherscan {
     pub gas_used: String,
     pub confirmations: String,
 }
+
+/// Transaction returned from RPC, in a block fetched with its full transactions
+/// https://ethereum.org/en/developers/docs/apis/json-rpc#eth_gettransactionbyhash
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionFromRpc {
+    pub hash: String,
+    /// Missing from the transactions of nodes that predate typed transactions
+    #[serde(rename = "type")]
+    pub transaction_type: Option<String>,
+    pub chain_id: Option<String>,
+    pub nonce: String,
+    pub gas_price: Option<String>,
+    pub max_priority_fee_per_gas: Option<String>,
+    pub max_fee_per_gas: Option<String>,
+    pub gas: String,
+    pub to: Option<String>,
+    pub value: String,
+    pub input: String,
+    pub access_list: Option<Vec<AccessListItemFromRpc>>,
+    pub max_fee_per_blob_gas: Option<String>,
+    pub blob_versioned_hashes: Option<Vec<String>>,
+    pub authorization_list: Option<Vec<AuthorizationFromRpc>>,
+    pub v: Option<String>,
+    pub y_parity: Option<String>,
+    pub r: String,
+    pub s: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListItemFromRpc {
+    pub address: String,
+    pub storage_keys: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizationFromRpc {
+    pub chain_id: String,
+    pub address: String,
+    pub nonce: String,
+    pub y_parity: String,
+    pub r: String,
+    pub s: String,
+}
+
+impl TransactionFromRpc {
+    /// Encoding of the transaction in the transactions trie: its RLP encoded fields,
+    /// prefixed with its type unless it is a legacy transaction
+    pub fn rlp_encode(&self) -> Result<Vec<u8>> {
+        let transaction_type = transaction_type(&self.transaction_type)?;
+        let nonce = encode_quantity(&self.nonce)?;
+        let gas = encode_quantity(&self.gas)?;
+        let to = match &self.to {
+            Some(to) => alloy_rlp::encode(Address::from_str(to)?),
+            // contract creation
+            None => alloy_rlp::encode(&[][..]),
+        };
+        let value = encode_quantity(&self.value)?;
+        let input = alloy_rlp::encode(hex_string_to_bytes(&self.input)?.as_slice());
+        let r = encode_quantity(&self.r)?;
+        let s = encode_quantity(&self.s)?;
+
+        let fields = match transaction_type {
+            0 => vec![
+                nonce,
+                self.quantity("gasPrice", &self.gas_price)?,
+                gas,
+                to,
+                value,
+                input,
+                self.quantity("v", &self.v)?,
+                r,
+                s,
+            ],
+            1 => vec![
+                self.quantity("chainId", &self.chain_id)?,
+                nonce,
+                self.quantity("gasPrice", &self.gas_price)?,
+                gas,
+                to,
+                value,
+                input,
+                self.encode_access_list()?,
+                self.y_parity()?,
+                r,
+                s,
+            ],
+            2..=4 => {
+                let mut fields = vec![
+                    self.quantity("chainId", &self.chain_id)?,
+                    nonce,
+                    self.quantity("maxPriorityFeePerGas", &self.max_priority_fee_per_gas)?,
+                    self.quantity("maxFeePerGas", &self.max_fee_per_gas)?,
+                    gas,
+                    to,
+                    value,
+                    input,
+                    self.encode_access_list()?,
+                ];
+                if transaction_type == 3 {
+                    fields.push(self.quantity("maxFeePerBlobGas", &self.max_fee_per_blob_gas)?);
+                    fields.push(self.encode_blob_versioned_hashes()?);
+                }
+                if transaction_type == 4 {
+                    fields.push(self.encode_authorization_list()?);
+                }
+                fields.extend([self.y_parity()?, r, s]);
+                fields
+            }
+            _ => bail!(
+                "Transaction {} has unsupported type {}",
+                self.hash,
+                transaction_type
+            ),
+        };
+
+        let mut encoded = vec![];
+        if transaction_type != 0 {
+            encoded.push(transaction_type);
+        }
+        encoded.extend(rlp_encode_list(&fields));
+        Ok(encoded)
+    }
+
+    /// Encoded quantity of a field that is only present in some transaction types
+    fn quantity(&self, name: &str, value: &Option<String>) -> Result<Vec<u8>> {
+        let value = value
+            .as_deref()
+            .with_context(|| format!("Transaction {} has no {}", self.hash, name))?;
+        encode_quantity(value)
+    }
+
+    /// Signature parity of a typed transaction, given as `v` by some nodes
+    fn y_parity(&self) -> Result<Vec<u8>> {
+        match &self.y_parity {
+            Some(y_parity) => encode_quantity(y_parity),
+            None => self.quantity("yParity", &self.v),
+        }
+    }
+
+    fn encode_access_list(&self) -> Result<Vec<u8>> {
+        let access_list = self
+            .access_list
+            .as_ref()
+            .with_context(|| format!("Transaction {} has no accessList", self.hash))?;
+        let items = access_list
+            .iter()
+            .map(|item| {
+                let storage_keys = item
+                    .storage_keys
+                    .iter()
+                    .map(|key| Ok(alloy_rlp::encode(B256::from_str(key)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(rlp_encode_list(&[
+                    alloy_rlp::encode(Address::from_str(&item.address)?),
+                    rlp_encode_list(&storage_keys),
+                ]))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(rlp_encode_list(&items))
+    }
+
+    fn encode_blob_versioned_hashes(&self) -> Result<Vec<u8>> {
+        let hashes = self
+            .blob_versioned_hashes
+            .as_ref()
+            .with_context(|| format!("Transaction {} has no blobVersionedHashes", self.hash))?
+            .iter()
+            .map(|hash| Ok(alloy_rlp::encode(B256::from_str(hash)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(rlp_encode_list(&hashes))
+    }
+
+    fn encode_authorization_list(&self) -> Result<Vec<u8>> {
+        let authorizations = self
+            .authorization_list
+            .as_ref()
+            .with_context(|| format!("Transaction {} has no authorizationList", self.hash))?
+            .iter()
+            .map(|authorization| {
+                Ok(rlp_encode_list(&[
+                    encode_quantity(&authorization.chain_id)?,
+                    alloy_rlp::encode(Address::from_str(&authorization.address)?),
+                    encode_quantity(&authorization.nonce)?,
+                    encode_quantity(&authorization.y_parity)?,
+                    encode_quantity(&authorization.r)?,
+                    encode_quantity(&authorization.s)?,
+                ]))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(rlp_encode_list(&authorizations))
+    }
+}
+
+/// Transaction receipt returned from RPC by `eth_getBlockReceipts`
+/// https://ethereum.org/en/developers/docs/apis/json-rpc#eth_gettransactionreceipt
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionReceiptFromRpc {
+    pub transaction_hash: String,
+    #[serde(rename = "type")]
+    pub transaction_type: Option<String>,
+    /// Status of the receipts since Byzantium
+    pub status: Option<String>,
+    /// State root of the receipts before Byzantium
+    pub root: Option<String>,
+    pub cumulative_gas_used: String,
+    pub logs_bloom: String,
+    pub logs: Vec<LogFromRpc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LogFromRpc {
+    pub address: String,
+    pub topics: Vec<String>,
+    pub data: String,
+}
+
+impl TransactionReceiptFromRpc {
+    /// Encoding of the receipt in the receipts trie: its RLP encoded fields,
+    /// prefixed with the type of its transaction unless it is a legacy transaction
+    pub fn rlp_encode(&self) -> Result<Vec<u8>> {
+        let transaction_type = transaction_type(&self.transaction_type)?;
+        let status_or_root = match (&self.status, &self.root) {
+            (Some(status), _) => encode_quantity(status)?,
+            (None, Some(root)) => alloy_rlp::encode(B256::from_str(root)?),
+            (None, None) => bail!(
+                "Receipt of transaction {} has neither status nor root",
+                self.transaction_hash
+            ),
+        };
+        let logs = self
+            .logs
+            .iter()
+            .map(|log| {
+                let topics = log
+                    .topics
+                    .iter()
+                    .map(|topic| Ok(alloy_rlp::encode(B256::from_str(topic)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(rlp_encode_list(&[
+                    alloy_rlp::encode(Address::from_str(&log.address)?),
+                    rlp_encode_list(&topics),
+                    alloy_rlp::encode(hex_string_to_bytes(&log.data)?.as_slice()),
+                ]))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut encoded = vec![];
+        if transaction_type != 0 {
+            encoded.push(transaction_type);
+        }
+        encoded.extend(rlp_encode_list(&[
+            status_or_root,
+            encode_quantity(&self.cumulative_gas_used)?,
+            alloy_rlp::encode(hex_string_to_bytes(&self.logs_bloom)?.as_slice()),
+            rlp_encode_list(&logs),
+        ]));
+        Ok(encoded)
+    }
+}
+
+fn transaction_type(transaction_type: &Option<String>) -> Result<u8> {
+    match transaction_type {
+        Some(transaction_type) => Ok(u8::try_from(parse_quantity(transaction_type)?)?),
+        None => Ok(0),
+    }
+}
+
+fn parse_quantity(quantity: &str) -> Result<U256> {
+    U256::from_str(quantity).with_context(|| format!("Invalid quantity {}", quantity))
+}
+
+fn encode_quantity(quantity: &str) -> Result<Vec<u8>> {
+    Ok(alloy_rlp::encode(parse_quantity(quantity)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{hex, keccak256};
+
+    #[test]
+    fn test_encode_legacy_transaction() {
+        // the signed transaction of the EIP-155 example
+        let transaction: TransactionFromRpc = serde_json::from_value(serde_json::json!({
+            "hash": "0x33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788",
+            "nonce": "0x9",
+            "gasPrice": "0x4a817c800",
+            "gas": "0x5208",
+            "to": "0x3535353535353535353535353535353535353535",
+            "value": "0xde0b6b3a7640000",
+            "input": "0x",
+            "v": "0x25",
+            "r": "0x28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276",
+            "s": "0x67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+        }))
+        .unwrap();
+        let encoded = transaction.rlp_encode().unwrap();
+        assert_eq!(
+            hex::encode(&encoded),
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+        assert_eq!(keccak256(&encoded).to_string(), transaction.hash);
+    }
+
+    #[test]
+    fn test_encode_typed_transaction() {
+        let transaction: TransactionFromRpc = serde_json::from_value(serde_json::json!({
+            "hash": "0x0",
+            "type": "0x2",
+            "chainId": "0x1",
+            "nonce": "0x0",
+            "maxPriorityFeePerGas": "0x1",
+            "maxFeePerGas": "0x2",
+            "gas": "0x5208",
+            "to": null,
+            "value": "0x0",
+            "input": "0x",
+            "accessList": [],
+            "v": "0x1",
+            "r": "0x1",
+            "s": "0x2",
+        }))
+        .unwrap();
+        assert_eq!(
+            hex::encode(transaction.rlp_encode().unwrap()),
+            "02ce01800102825208808080c0010102"
+        );
+
+        let missing_access_list = TransactionFromRpc {
+            access_list: None,
+            ..transaction
+        };
+        assert!(missing_access_list.rlp_encode().is_err());
+    }
+
+    #[test]
+    fn test_encode_receipt() {
+        let receipt: TransactionReceiptFromRpc = serde_json::from_value(serde_json::json!({
+            "transactionHash": "0x0",
+            "type": "0x2",
+            "status": "0x1",
+            "cumulativeGasUsed": "0x5208",
+            "logsBloom": format!("0x{}", "00".repeat(256)),
+            "logs": [],
+        }))
+        .unwrap();
+        assert_eq!(
+            hex::encode(receipt.rlp_encode().unwrap()),
+            format!("02f9010801825208b90100{}c0", "00".repeat(256))
+        );
+    }
+}
@@ -1,7 +1,9 @@
 use std::str::FromStr;
 
 use crate::{
-    datalake::{datalake_type::DatalakeType, Datalake, DatalakeCollection},
+    datalake::{
+        datalake_type::DatalakeType, time_range::ResolvedTimeRange, Datalake, DatalakeCollection,
+    },
     utils::bytes_to_hex_string,
 };
 
@@ -40,6 +42,20 @@ impl BlockSampledDatalake {
             increment,
         })
     }
+
+    /// Datalake over the blocks of a time range, from its first to its last block
+    pub fn new_in_time_range(
+        time_range: &ResolvedTimeRange,
+        sampled_property: String,
+        increment: u64,
+    ) -> Result<Self> {
+        Self::new(
+            time_range.first_block,
+            time_range.last_block,
+            sampled_property,
+            increment,
+        )
+    }
 }
 
 impl Datalake for BlockSampledDatalake {
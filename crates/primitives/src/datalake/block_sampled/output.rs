@@ -39,6 +39,7 @@ impl Account {
                     block_number: proof.block_number,
                     proof_bytes_len,
                     proof: proof_result,
+                    is_exclusion: proof.is_exclusion,
                 }
             })
             .collect();
@@ -94,6 +95,7 @@ impl Storage {
                     block_number: proof.block_number,
                     proof_bytes_len: storage_proof_bytes_len,
                     proof: storage_proof_result,
+                    is_exclusion: proof.is_exclusion,
                 }
             })
             .collect();
@@ -120,13 +122,14 @@ pub(crate) struct StorageFormatted {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::datalake::output::*;
+    use crate::{datalake::output::*, task_result::TaskResultType};
 
     #[test]
     fn cairo_format_header() {
         let original_header = Header{
             rlp: "f90226a018a6770e7e502f9209082c676922bbf1ad4f984924a17743d3044e6b3ffd8f19a01dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347947cbd790123255d9467d22baa806c9f059e558dc1a0156be497b45c06194d49508c8dca1ecef038ab4d3bd6060de6cfa2c9a4c3591ca0dcf5dc08c6e2720af2576fad9b9cccc66c0b50e53ebdd946bf0529ea750acb27a0d365f953867eadc22b2b2ded7cd620d92214e06671fd95e4f4d0b4747a4d2906b901000040020a0900000206083c210411006001d1080000040000001800a48100083040001000e00102090013424000844400000004004800020030144004a0600820448001000821811080002108880408100000404001140a1000004c004080020a280280280a108000025800044a044903800914004080000000c04015980109800022000002018804242400200a004a00000000201208804808001000c652088103080400100000060c00000000001000100022800a18000a2034a200040200010000013e000030000510000020020401004001100088000052008e0345802b0828b0005000a0011201022002808420402401000020001000820022400840081080834b90248401c9c380838ef3b5846588daac856c696e7578a03310d07ba1b9123c44429746f84d32df7e725178ae2c66404a3afad502c0a402880000000000000000849ac020c3a01e922a1e8e795414af0458d9af8d1fa08f5365cb4efb05273c3004b882cd3c84".to_string(),
             proof: HeaderProof{
+                mmr_id: 2,
                 leaf_idx: 56993,
                 mmr_path: vec!["0x4f582f7c3e936d25c2979f6c473278c17fb4c1cc02b5dc27b8226d41135fc9c".to_string()]
             }
@@ -221,6 +224,7 @@ mod tests {
             proofs: vec![
                 MPTProof {
                     block_number: 4952100,
+                    is_exclusion: false,
                     proof: vec![
                       "0xf90211a0b260487078406bef4549faf0cf3b8689f38e132759b79f1b38f921bb0770725ba0f07c30281fed0e6c948d671b34a5c17926f19abaf816de01df4df13b2f21da0fa0841f208d25bb1a776e3c2171b6480a0c724d3dfda547b2640a44cc588e070b0ea06d0c3d0c26e0d6445ecb5b6a33fcf689223e7730ea633c10b3da596b95e0f1b2a00bf1fd1401f66489c80505a599832504e7ac4b21aa9c9d112773bcfc27092457a0954a14a3720833725342d37906ae2deea600f972e3be67bdfb9b2d9edb6c1591a041272f02efe0185e5efff8a544069f9e4a95ea84529bc19f1e4c2eeb1eac570aa060ca91f51e2cf585b712149c5a445bfac9538702f0d3426bee60aefa4c87ecbfa0cdba627f2918168968ec9b36b4de19bd296d5ac150c1a9b0f9c5fe16d2124cdda02d3691c76b294a895c7d87f1d6fdc9277f2535aadc78490e0d079a3e35659f1ea0bda5248f65638b83926e03c0d51a48620f41350e586285f895bbe1b22dfed40ea0758672f5a0bf7dcc32d1f39bacf1cc3e91a1152d3b8fefe0196b0c84e951a3ada0270bab342f3d7067df0a85afd721081abe2f9ba381e11c4d7696e3ea8374e781a0ebe3b5f3fce25e553b5d58f6fe8e1ca2bb755c36bb676fadcd2cafad96bcb33da047b0214744d7948e4006726fd5d457b1f161c39009214f65eb8294b334e6892ca01220abfad74e06651e36c1cc9f8c4f969bc6405f427808b0b59a8a536e95861780".to_string(),
                       "0xf90211a0a954661b9074f2aaa86f30dd72090c661033ea23bd53d5f4f81868f52fa36244a01796443bb911da9cf09b45140ced3c3b29a7ec3a718d064048309e080c181794a0c985eb0a0e53c4b2263b82e139bb99ab8734afe19f83a7c97d5fb5b5fc06a99ba0d68c13a7b9e4b19c3ef8c9c1ff28e79e6aa010f8c6d35732fb4ce94e48f414e9a02f4562fc142d982ff694d94cb140f320bee079f647061a89d867678cdca6dc9fa08c5cf6bec1e86b386077b2a601054b68c0e16da54f30ec4f997ff867072ff8d2a063f36f3b4d020c5662868715f4a794d12b9e35f2fe0839b0f53b5ca8b34026cea0958229737a3c3b26d0f7724a5f8b9c3f4aa198b8a5df96cd145eed5a1731f406a086f0a5107a6285864856d06c9fe42a852c9820ca3ecd114215a339ed12b9a97fa085670a6d72ee17acfc1a19550001596930bcfd8c5c52f18960bda455c39feef1a0cf03b39cf61db940a99e655a2ce1c9d8658e42d0f7340d414a8cdcadf7abb1bea07e61ec23442705596ce9886a93ed7d827478189654982650619160630fa68119a002eab370c4f7a0075bc748d614d2642402672b47b441a402dc0d941ebc962d30a06c9bc270052090f0ede819cd4858012659e2b12bb76400f7f501afa19ca38f25a0abb7bda7ae949eb2d0a8aca07f02edcb45cade684078b75ada26f76e1d574c73a045ba9374b98c8f1336f76608f91f4e984dab978dac5447d3fdbc0d1fb43c694e80".to_string(),
@@ -708,10 +712,12 @@ mod tests {
             proofs: vec![MPTProof {
                 block_number: 4952100,
                 proof: vec![],
+                is_exclusion: true,
             }],
         };
 
         let formatted_storage = original_storage.to_cairo_format();
+        assert!(formatted_storage.proofs[0].is_exclusion);
         assert_eq!(
             formatted_storage.address,
             vec![
@@ -773,15 +779,17 @@ mod tests {
             encoded_task: "0x23c69fe8ceb11087e27f0b0a89d8dc0cda85ab933464d49bd21d623526acf8c073756d000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000600000000000000000000000000000000000000000000000000000000000000000".to_string(),
             task_commitment: "0x46296bc9cb11408bfa46c5c31a542f12242db2412ee2217b4e8add2bc1927d0b".to_string(),
             compiled_result: "6776".to_string(),
+            result_type: TaskResultType::Uint256,
             result_commitment: "0x40b44b4fe85644ce1d6f8f5035a5f7e91861d064ed0b4189ebb2eb6ce8985f4d".to_string(),
             task_proof: vec![],
             result_proof: vec![],
             encoded_datalake: "0x000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000004b902400000000000000000000000000000000000000000000000000000000004b9024000000000000000000000000000000000000000000000000000000000000000100000000000000000000000000000000000000000000000000000000000000a00000000000000000000000000000000000000000000000000000000000000016027f2c6f930306d3aa736b3a6c6a98f512f74036d40000000000000000000000".to_string(),
             datalake_type: 0,
-            property_type: 2
+            property_type: 2,
+            time_range: None,
         };
 
-        let formatted_task = original_task.to_cairo_format();
+        let formatted_task = original_task.to_cairo_format().unwrap();
 
         assert_eq!(
             formatted_task.encoded_task,
@@ -806,6 +814,14 @@ mod tests {
         );
 
         assert_eq!(formatted_task.task_bytes_len, 128);
+        assert_eq!(formatted_task.result_type, 0);
+        assert_eq!(
+            formatted_task.result,
+            vec![Uint256 {
+                high: "0x00000000000000000000000000000000".to_string(),
+                low: "0x00000000000000000000000000001a78".to_string()
+            }]
+        );
 
         assert_eq!(
             formatted_task.encoded_datalake,
@@ -842,5 +858,12 @@ mod tests {
         );
 
         assert_eq!(formatted_task.datalake_bytes_len, 224);
+
+        // a result that isn't displayed as its type
+        let invalid_task = Task {
+            compiled_result: "six thousand".to_string(),
+            ..original_task
+        };
+        assert!(invalid_task.to_cairo_format().is_err());
     }
 }
@@ -62,4 +62,12 @@ impl DatalakeEnvelope {
             DatalakeEnvelope::Transactions(_) => DatalakeType::TransactionsInBlock,
         }
     }
+
+    /// Most recent block the datalake samples
+    pub fn get_last_block_number(&self) -> u64 {
+        match self {
+            DatalakeEnvelope::BlockSampled(datalake) => datalake.block_range_end,
+            DatalakeEnvelope::Transactions(datalake) => datalake.target_block,
+        }
+    }
 }
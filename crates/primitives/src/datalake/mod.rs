@@ -5,6 +5,7 @@ pub mod block_sampled;
 pub mod datalake_type;
 pub mod envelope;
 pub mod output;
+pub mod time_range;
 pub mod transactions;
 
 pub trait DatalakeCollection {
@@ -1,13 +1,20 @@
 use alloy_primitives::{
     hex::{self, FromHex},
-    FixedBytes,
+    FixedBytes, U256,
 };
+use alloy_rlp::Decodable;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::utils::bytes_to_hex_string;
+use crate::{
+    block::header::Header as BlockHeader,
+    task_result::{TaskResult, TaskResultType},
+    utils::bytes_to_hex_string,
+};
 
 use super::{
     block_sampled::output::{Account, AccountFormatted, Storage, StorageFormatted},
+    time_range::ResolvedTimeRange,
     transactions::output::{
         Transaction, TransactionFormatted, TransactionReceipt, TransactionReceiptFormatted,
     },
@@ -25,6 +32,8 @@ pub struct Uint256 {
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Eq, Hash)]
 pub struct HeaderProof {
+    /// Id of the MMR the header is proven against
+    pub mmr_id: u64,
     pub leaf_idx: u64,
     pub mmr_path: Vec<String>,
 }
@@ -32,6 +41,7 @@ pub struct HeaderProof {
 /// HeaderProofFormatted is the formatted version of HeaderProof
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Eq, Hash)]
 pub struct HeaderProofFormatted {
+    pub mmr_id: u64,
     pub leaf_idx: u64,
     // mmr_path is encoded with poseidon
     pub mmr_path: Vec<String>,
@@ -51,6 +61,7 @@ impl Header {
             rlp: chunk_result.chunks,
             rlp_bytes_len: chunk_result.chunks_len,
             proof: HeaderProofFormatted {
+                mmr_id: proof.mmr_id,
                 leaf_idx: proof.leaf_idx,
                 mmr_path: proof.mmr_path,
             },
@@ -105,6 +116,15 @@ pub fn split_little_endian_hex_into_parts(hex_str: &str) -> Uint256 {
     }
 }
 
+/// Decode the index of a transaction key, a 32 bytes left padded rlp encoded index.
+/// Returns `None` if the key is not a valid one.
+fn decode_index_key(key: &str) -> Option<U256> {
+    let key = FixedBytes::<32>::from_hex(key.trim_start_matches("0x")).ok()?;
+    // rlp encoded integers never start with a zero byte
+    let start = key.iter().position(|byte| *byte != 0)?;
+    U256::decode(&mut &key[start..]).ok()
+}
+
 pub fn split_big_endian_hex_into_parts(hex_str: &str) -> Uint256 {
     let clean_hex = hex_str.trim_start_matches("0x");
     let padded_hex = format!("{:0>64}", clean_hex);
@@ -121,8 +141,11 @@ pub struct Task {
     pub encoded_task: String,
     /// computational task commitment
     pub task_commitment: String,
-    /// raw evaluation result of target compiled task
+    /// raw evaluation result of target compiled task, displayed as its type
     pub compiled_result: String,
+    /// type of the compiled result, ex. "uint256", "bytes32"
+    #[serde(default)]
+    pub result_type: TaskResultType,
     /// results merkle tree's entry value
     pub result_commitment: String,
     pub task_proof: Vec<FixedBytes<32>>,
@@ -133,21 +156,32 @@ pub struct Task {
     pub datalake_type: u8,
     // ex. "header", "account", "storage"
     pub property_type: u8,
+    /// time range the block range of the datalake was resolved from, for reference
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_range: Option<ResolvedTimeRange>,
 }
 
 impl Task {
-    pub fn to_cairo_format(&self) -> TaskFormatted {
+    pub fn to_cairo_format(&self) -> Result<TaskFormatted> {
         let computational_task_chunk_result =
             hex_to_8_byte_chunks_little_endian(&self.encoded_task);
         let datalake_chunk_result = hex_to_8_byte_chunks_little_endian(&self.encoded_datalake);
-        TaskFormatted {
+        let compiled_result = TaskResult::parse(self.result_type, &self.compiled_result)
+            .with_context(|| format!("Invalid compiled result of task {}", self.task_commitment))?;
+        Ok(TaskFormatted {
             task_bytes_len: computational_task_chunk_result.chunks_len,
             encoded_task: computational_task_chunk_result.chunks,
             datalake_bytes_len: datalake_chunk_result.chunks_len,
             encoded_datalake: datalake_chunk_result.chunks,
             datalake_type: self.datalake_type,
             property_type: self.property_type,
-        }
+            result_type: self.result_type.to_index(),
+            result: compiled_result
+                .to_words()
+                .iter()
+                .map(|word| split_big_endian_hex_into_parts(&word.to_string()))
+                .collect(),
+        })
     }
 }
 
@@ -159,12 +193,18 @@ pub struct TaskFormatted {
     pub encoded_datalake: Vec<String>,
     pub datalake_type: u8,
     pub property_type: u8,
+    pub result_type: u8,
+    /// 32 bytes words of the compiled result, as committed
+    pub result: Vec<Uint256>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Eq, Hash)]
 pub struct MPTProof {
     pub block_number: u64,
     pub proof: Vec<String>,
+    /// The proof shows that the key is not in the trie, and the value is 0
+    #[serde(default)]
+    pub is_exclusion: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Eq, Hash)]
@@ -173,6 +213,7 @@ pub struct MPTProofFormatted {
     /// proof_bytes_len is the byte( 8 bit ) length from each proof string
     pub proof_bytes_len: Vec<u64>,
     pub proof: Vec<Vec<String>>,
+    pub is_exclusion: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Eq, Hash)]
@@ -191,16 +232,70 @@ pub struct ProcessedResult {
     // U256 type
     pub tasks_root: String,
     pub headers: Vec<Header>,
-    pub mmr: MMRMeta,
+    /// Metadata of every MMR the headers are proven against
+    pub mmr_metas: Vec<MMRMeta>,
     pub accounts: Vec<Account>,
     pub storages: Vec<Storage>,
     pub transactions: Vec<Transaction>,
     pub transaction_receipts: Vec<TransactionReceipt>,
     pub tasks: Vec<Task>,
+    /// Last final block when the datalakes were evaluated, the sampled blocks are not newer
+    #[serde(default)]
+    pub finality_block: Option<u64>,
 }
 
 impl ProcessedResult {
-    pub fn to_cairo_format(&self) -> ProcessedResultFormatted {
+    /// Sort the proven data in a canonical order, so that a same request always gives the same output:
+    /// headers by block number, accounts by address, storages by address and slot,
+    /// and transactions and receipts by block number and index.
+    ///
+    /// Tasks keep their order, which is the one of the leaves of the merkle trees.
+    pub fn sort_canonically(&mut self) {
+        self.headers.sort_by_cached_key(|header| {
+            (
+                BlockHeader::rlp_decode(&header.rlp).number,
+                header.proof.mmr_id,
+                header.proof.leaf_idx,
+            )
+        });
+        self.mmr_metas.sort_by_key(|mmr_meta| mmr_meta.id);
+        for account in &mut self.accounts {
+            account.proofs.sort_by_key(|proof| proof.block_number);
+        }
+        self.accounts.sort_by(|a, b| a.address.cmp(&b.address));
+        for storage in &mut self.storages {
+            storage.proofs.sort_by_key(|proof| proof.block_number);
+        }
+        // a slot sampled by several datalakes can have several sets of proofs
+        self.storages.sort_by_cached_key(|storage| {
+            (
+                storage.address.clone(),
+                storage.slot.clone(),
+                storage
+                    .proofs
+                    .iter()
+                    .map(|proof| proof.block_number)
+                    .collect::<Vec<_>>(),
+            )
+        });
+        // keys are left padded rlp encoded indexes, so they are ordered by their decoded index
+        self.transactions.sort_by_cached_key(|transaction| {
+            (
+                transaction.block_number,
+                decode_index_key(&transaction.key),
+                transaction.key.clone(),
+            )
+        });
+        self.transaction_receipts.sort_by_cached_key(|receipt| {
+            (
+                receipt.block_number,
+                decode_index_key(&receipt.key),
+                receipt.key.clone(),
+            )
+        });
+    }
+
+    pub fn to_cairo_format(&self) -> Result<ProcessedResultFormatted> {
         let headers = self
             .headers
             .iter()
@@ -230,19 +325,23 @@ impl ProcessedResult {
             .tasks
             .iter()
             .map(|task| task.to_cairo_format())
-            .collect();
+            .collect::<Result<_>>()?;
 
-        ProcessedResultFormatted {
+        Ok(ProcessedResultFormatted {
             results_root: split_big_endian_hex_into_parts(&self.results_root),
             tasks_root: split_big_endian_hex_into_parts(&self.tasks_root),
             headers,
-            mmr: self.mmr.clone(),
+            mmr: match &self.mmr_metas[..] {
+                [mmr_meta] => Some(mmr_meta.clone()),
+                _ => None,
+            },
+            mmr_metas: self.mmr_metas.clone(),
             accounts,
             storages,
             transactions,
             transaction_receipts,
             tasks,
-        }
+        })
     }
 }
 
@@ -251,7 +350,11 @@ pub struct ProcessedResultFormatted {
     pub results_root: Uint256,
     pub tasks_root: Uint256,
     pub headers: Vec<HeaderFormatted>,
-    pub mmr: MMRMeta,
+    /// MMR of the headers when they are all proven against the same one, which is the only
+    /// case the current Cairo program supports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mmr: Option<MMRMeta>,
+    pub mmr_metas: Vec<MMRMeta>,
     accounts: Vec<AccountFormatted>,
     storages: Vec<StorageFormatted>,
     transactions: Vec<TransactionFormatted>,
@@ -286,6 +389,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sort_transactions_by_index() {
+        let transaction = |tx_index: u64| Transaction {
+            key: crate::utils::bytes_to_fixed_bytes32(&alloy_rlp::encode(U256::from(tx_index)))
+                .to_string(),
+            block_number: 10,
+            proof: vec![],
+        };
+        let mut processed_result = ProcessedResult {
+            results_root: String::new(),
+            tasks_root: String::new(),
+            headers: vec![],
+            mmr_metas: vec![],
+            accounts: vec![],
+            storages: vec![],
+            transactions: vec![transaction(128), transaction(1), transaction(0)],
+            transaction_receipts: vec![],
+            tasks: vec![],
+            finality_block: None,
+        };
+        processed_result.sort_canonically();
+        assert_eq!(
+            processed_result.transactions,
+            vec![transaction(0), transaction(1), transaction(128)]
+        );
+        assert_eq!(
+            decode_index_key(&transaction(128).key),
+            Some(U256::from(128))
+        );
+        assert_eq!(decode_index_key("0x00"), None);
+    }
+
     #[test]
     fn test_split_little_endian_hex_into_parts() {
         let hex_str = "0x8ddadb3a246d9988d78871b11dca322a2df53381bfacb9edc42cedfd263b691d";
@@ -0,0 +1,154 @@
+//! Time ranges of datalakes, resolved to the blocks they contain.
+//!
+//! A time is given as a unix timestamp in seconds, a UTC date `YYYY-MM-DD`,
+//! or a UTC date and time `YYYY-MM-DDTHH:MM:SSZ`.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// [`TimeRange`] is an interval of unix timestamps, both ends included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start_timestamp: u64,
+    pub end_timestamp: u64,
+}
+
+impl TimeRange {
+    pub fn new(start_timestamp: u64, end_timestamp: u64) -> Result<Self> {
+        if start_timestamp > end_timestamp {
+            bail!(
+                "Time range starts at {} after its end at {}",
+                start_timestamp,
+                end_timestamp
+            );
+        }
+        Ok(Self {
+            start_timestamp,
+            end_timestamp,
+        })
+    }
+
+    /// Parse the start and end times of a range.
+    /// An end given as a date includes the whole day, e.g. `2024-03-01` to `2024-03-31` is March.
+    pub fn parse(start: &str, end: &str) -> Result<Self> {
+        let start_timestamp = parse_time(start)?;
+        let end_timestamp = match parse_date(end) {
+            Some(date) => date? + SECONDS_PER_DAY - 1,
+            None => parse_time(end)?,
+        };
+        Self::new(start_timestamp, end_timestamp)
+    }
+}
+
+/// [`ResolvedTimeRange`] is a time range with the first and last blocks inside it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ResolvedTimeRange {
+    pub start_timestamp: u64,
+    pub end_timestamp: u64,
+    pub first_block: u64,
+    pub first_block_timestamp: u64,
+    pub last_block: u64,
+    pub last_block_timestamp: u64,
+}
+
+/// Parse a unix timestamp, a date or a date and time
+pub fn parse_time(value: &str) -> Result<u64> {
+    if let Ok(timestamp) = value.parse::<u64>() {
+        return Ok(timestamp);
+    }
+    if let Some(date) = parse_date(value) {
+        return date;
+    }
+    let Some((date, time)) = value
+        .strip_suffix('Z')
+        .and_then(|value| value.split_once('T'))
+    else {
+        bail!(
+            "Invalid time {}: expected a unix timestamp, YYYY-MM-DD or YYYY-MM-DDTHH:MM:SSZ",
+            value
+        );
+    };
+    let date = parse_date(date).with_context(|| format!("Invalid date in {}", value))??;
+    let fields = time
+        .split(':')
+        .map(|field| field.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid time of day in {}", value))?;
+    match fields[..] {
+        [hours, minutes, seconds] if hours < 24 && minutes < 60 && seconds < 60 => {
+            Ok(date + hours * 3600 + minutes * 60 + seconds)
+        }
+        _ => bail!("Invalid time of day in {}", value),
+    }
+}
+
+/// Timestamp of the start of a `YYYY-MM-DD` day, `None` if `value` is not shaped as a date
+fn parse_date(value: &str) -> Option<Result<u64>> {
+    let fields = value.split('-').collect::<Vec<_>>();
+    let [year, month, day] = fields[..] else {
+        return None;
+    };
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let (Ok(year), Ok(month), Ok(day)) = (year.parse(), month.parse(), day.parse()) else {
+        return None;
+    };
+    if year < 1970 || !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Some(Err(anyhow::anyhow!("Invalid date {}", value)));
+    }
+    Some(Ok(days_since_epoch(year, month, day) * SECONDS_PER_DAY))
+}
+
+// `is_multiple_of` is not available on the release toolchain
+#[allow(clippy::manual_is_multiple_of)]
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to a date of the proleptic Gregorian calendar
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    // years start in March, so that the leap day ends them
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("1709251200").unwrap(), 1709251200);
+        assert_eq!(parse_time("1970-01-01").unwrap(), 0);
+        assert_eq!(parse_time("2024-03-01").unwrap(), 1709251200);
+        assert_eq!(parse_time("2024-02-29T12:30:15Z").unwrap(), 1709209815);
+        assert!(parse_time("2023-02-29").is_err());
+        assert!(parse_time("2024-03-01T24:00:00Z").is_err());
+        assert!(parse_time("2024-03-01T12:00").is_err());
+        assert!(parse_time("March").is_err());
+    }
+
+    #[test]
+    fn test_parse_time_range() {
+        // the end date includes its whole day
+        let march = TimeRange::parse("2024-03-01", "2024-03-31").unwrap();
+        assert_eq!(march.start_timestamp, 1709251200);
+        assert_eq!(march.end_timestamp, 1711929599);
+
+        let range = TimeRange::parse("2024-03-01T00:00:00Z", "2024-03-01T01:00:00Z").unwrap();
+        assert_eq!(range.end_timestamp - range.start_timestamp, 3600);
+        assert!(TimeRange::parse("2024-03-31", "2024-03-01").is_err());
+    }
+}
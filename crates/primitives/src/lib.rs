@@ -1,3 +1,7 @@
 pub mod block;
 pub mod datalake;
+pub mod mmr;
+pub mod mpt;
+pub mod result_proof;
+pub mod task_result;
 pub mod utils;
@@ -0,0 +1,387 @@
+//! Merkle mountain range of block headers, in the layout of the Herodotus header accumulators.
+//!
+//! Nodes are addressed by their 1-based position in the MMR (`element_index`), and the root
+//! commits to the size of the MMR: `root = H(size, bag(peaks))`, where the peaks are bagged
+//! from right to left.
+
+use alloy_primitives::{keccak256, B256, U256};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use starknet_crypto::{poseidon_hash, poseidon_hash_many, FieldElement};
+use std::{fmt::Display, str::FromStr};
+
+/// Hashing function of a header accumulator MMR
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum HashingFunction {
+    #[default]
+    Poseidon,
+    Keccak,
+}
+
+impl FromStr for HashingFunction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "poseidon" => Ok(HashingFunction::Poseidon),
+            "keccak" => Ok(HashingFunction::Keccak),
+            _ => bail!("Unknown hashing function: {}", s),
+        }
+    }
+}
+
+impl Display for HashingFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HashingFunction::Poseidon => write!(f, "poseidon"),
+            HashingFunction::Keccak => write!(f, "keccak"),
+        }
+    }
+}
+
+impl HashingFunction {
+    /// Hash of a RLP encoded block header, as appended to the MMR.
+    ///
+    /// Keccak leaves are the block hashes. Poseidon leaves hash the RLP
+    /// split in 8 bytes little endian words.
+    pub fn hash_header(&self, rlp: &[u8]) -> B256 {
+        match self {
+            HashingFunction::Keccak => keccak256(rlp),
+            HashingFunction::Poseidon => {
+                let words = rlp
+                    .chunks(8)
+                    .map(|chunk| {
+                        let mut word = [0u8; 8];
+                        word[..chunk.len()].copy_from_slice(chunk);
+                        FieldElement::from(u64::from_le_bytes(word))
+                    })
+                    .collect::<Vec<_>>();
+                B256::from(poseidon_hash_many(&words).to_bytes_be())
+            }
+        }
+    }
+
+    /// Hash of two nodes of the MMR
+    pub fn hash_pair(&self, left: &B256, right: &B256) -> Result<B256> {
+        match self {
+            HashingFunction::Keccak => Ok(keccak256([left.as_slice(), right.as_slice()].concat())),
+            HashingFunction::Poseidon => Ok(B256::from(
+                poseidon_hash(to_field_element(left)?, to_field_element(right)?).to_bytes_be(),
+            )),
+        }
+    }
+}
+
+fn to_field_element(value: &B256) -> Result<FieldElement> {
+    FieldElement::from_bytes_be(&value.0)
+        .map_err(|_| anyhow!("{} is not a valid field element", value))
+}
+
+/// [`Mmr`] is an append only merkle mountain range that keeps every node in memory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mmr {
+    pub hashing_function: HashingFunction,
+    /// Nodes by 0-based position
+    nodes: Vec<B256>,
+    leaves_count: u64,
+}
+
+impl Mmr {
+    pub fn new(hashing_function: HashingFunction) -> Self {
+        Self {
+            hashing_function,
+            nodes: vec![],
+            leaves_count: 0,
+        }
+    }
+
+    /// Number of nodes in the MMR
+    pub fn size(&self) -> u64 {
+        self.nodes.len() as u64
+    }
+
+    pub fn leaves_count(&self) -> u64 {
+        self.leaves_count
+    }
+
+    /// Node at the given 1-based position
+    pub fn get(&self, element_index: u64) -> Option<B256> {
+        element_index
+            .checked_sub(1)
+            .and_then(|position| self.nodes.get(position as usize))
+            .copied()
+    }
+
+    /// Append a leaf and return its element index
+    pub fn append(&mut self, leaf: B256) -> Result<u64> {
+        let element_index = self.size() + 1;
+        self.nodes.push(leaf);
+
+        // merge while the appended node completes a subtree
+        let mut height = 0;
+        while node_height(self.size() + 1) > height {
+            let right = self.nodes[self.nodes.len() - 1];
+            let left = self.nodes[self.nodes.len() - 1 - sibling_offset(height) as usize];
+            let parent = self.hashing_function.hash_pair(&left, &right)?;
+            self.nodes.push(parent);
+            height += 1;
+        }
+
+        self.leaves_count += 1;
+        Ok(element_index)
+    }
+
+    pub fn peaks(&self) -> Vec<B256> {
+        peak_positions(self.size())
+            .into_iter()
+            .map(|position| self.nodes[position as usize - 1])
+            .collect()
+    }
+
+    pub fn root(&self) -> Result<B256> {
+        root_from_peaks(self.hashing_function, self.size(), &self.peaks())
+    }
+
+    /// Siblings from the node at `element_index` up to its peak
+    pub fn proof(&self, element_index: u64) -> Result<Vec<B256>> {
+        if element_index == 0 || element_index > self.size() {
+            bail!(
+                "Element index {} out of MMR of size {}",
+                element_index,
+                self.size()
+            );
+        }
+        let peaks = peak_positions(self.size());
+        let mut siblings = vec![];
+        let mut position = element_index;
+        let mut height = node_height(position);
+        while !peaks.contains(&position) {
+            if node_height(position + 1) > height {
+                // right child, its parent is the next node
+                siblings.push(self.nodes[(position - sibling_offset(height)) as usize - 1]);
+                position += 1;
+            } else {
+                siblings.push(self.nodes[(position + sibling_offset(height)) as usize - 1]);
+                position += parent_offset(height);
+            }
+            height += 1;
+        }
+        Ok(siblings)
+    }
+}
+
+/// 1-based position of the leaf with the given 0-based index
+pub fn leaf_index_to_element_index(leaf_index: u64) -> u64 {
+    2 * leaf_index - u64::from(leaf_index.count_ones()) + 1
+}
+
+/// 1-based positions of the peaks of a MMR of the given size, from left to right
+pub fn peak_positions(size: u64) -> Vec<u64> {
+    let mut peaks = vec![];
+    let mut offset = 0;
+    let mut remaining = size;
+    for height in (0..64 - size.leading_zeros()).rev() {
+        let tree_size = (1u64 << (height + 1)) - 1;
+        if remaining >= tree_size {
+            offset += tree_size;
+            remaining -= tree_size;
+            peaks.push(offset);
+        }
+    }
+    peaks
+}
+
+/// Root of a MMR: the hash of its size and its peaks bagged from right to left
+pub fn root_from_peaks(
+    hashing_function: HashingFunction,
+    size: u64,
+    peaks: &[B256],
+) -> Result<B256> {
+    let bagged_peaks = match peaks {
+        [] => B256::ZERO,
+        [peak] => *peak,
+        [rest @ .., left, right] => {
+            let mut bag = hashing_function.hash_pair(left, right)?;
+            for peak in rest.iter().rev() {
+                bag = hashing_function.hash_pair(peak, &bag)?;
+            }
+            bag
+        }
+    };
+    hashing_function.hash_pair(&B256::from(U256::from(size)), &bagged_peaks)
+}
+
+/// Verify that `element_hash` is the node at `element_index` of a MMR with the given
+/// size and peaks, given the siblings from the node up to its peak.
+pub fn verify_proof(
+    hashing_function: HashingFunction,
+    element_index: u64,
+    element_hash: B256,
+    siblings: &[B256],
+    size: u64,
+    peaks: &[B256],
+) -> Result<bool> {
+    if element_index == 0 || element_index > size {
+        return Ok(false);
+    }
+    let peak_positions = peak_positions(size);
+    if peak_positions.len() != peaks.len() {
+        return Ok(false);
+    }
+
+    let mut hash = element_hash;
+    let mut position = element_index;
+    let start_height = node_height(position);
+    for (height, sibling) in (start_height..).zip(siblings) {
+        if node_height(position + 1) > height {
+            hash = hashing_function.hash_pair(sibling, &hash)?;
+            position += 1;
+        } else {
+            hash = hashing_function.hash_pair(&hash, sibling)?;
+            position += parent_offset(height);
+        }
+    }
+
+    Ok(peak_positions
+        .iter()
+        .position(|peak_position| *peak_position == position)
+        .is_some_and(|peak| peaks[peak] == hash))
+}
+
+/// Parse a MMR node hash. The indexer serializes poseidon hashes without leading zeros.
+pub fn parse_node_hash(value: &str) -> Result<B256> {
+    U256::from_str_radix(value.trim_start_matches("0x"), 16)
+        .map(B256::from)
+        .map_err(|_| anyhow!("Invalid MMR node hash {}", value))
+}
+
+/// Height of the node at the given 1-based position, leaves have height 0
+fn node_height(position: u64) -> u32 {
+    let mut position = position;
+    // jump left until the position is the top of a perfect tree (all ones)
+    while position.count_zeros() != position.leading_zeros() {
+        let most_significant_bit = 1u64 << (63 - position.leading_zeros());
+        position -= most_significant_bit - 1;
+    }
+    63 - position.leading_zeros()
+}
+
+/// Distance from a left node to its right sibling
+fn sibling_offset(height: u32) -> u64 {
+    (2u64 << height) - 1
+}
+
+/// Distance from a left node to its parent
+fn parent_offset(height: u32) -> u64 {
+    2u64 << height
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashing_function_from_str() {
+        assert_eq!(
+            HashingFunction::from_str("poseidon").unwrap(),
+            HashingFunction::Poseidon
+        );
+        assert_eq!(
+            HashingFunction::from_str("KECCAK").unwrap(),
+            HashingFunction::Keccak
+        );
+        assert!(HashingFunction::from_str("sha256").is_err());
+        assert_eq!(HashingFunction::Keccak.to_string(), "keccak");
+    }
+
+    #[test]
+    fn test_mmr_layout() {
+        assert_eq!(
+            (1..=8).map(node_height).collect::<Vec<_>>(),
+            vec![0, 0, 1, 0, 0, 1, 2, 0]
+        );
+        assert_eq!(peak_positions(7), vec![7]);
+        assert_eq!(peak_positions(10), vec![7, 10]);
+        assert_eq!(peak_positions(11), vec![7, 10, 11]);
+        assert_eq!(
+            (0..6).map(leaf_index_to_element_index).collect::<Vec<_>>(),
+            vec![1, 2, 4, 5, 8, 9]
+        );
+    }
+
+    #[test]
+    fn test_mmr_append_and_proof() {
+        for hashing_function in [HashingFunction::Keccak, HashingFunction::Poseidon] {
+            let mut mmr = Mmr::new(hashing_function);
+            let leaves = (0..11u64)
+                .map(|i| hashing_function.hash_header(&i.to_be_bytes()))
+                .collect::<Vec<_>>();
+            for (leaf_index, leaf) in leaves.iter().enumerate() {
+                let element_index = mmr.append(*leaf).unwrap();
+                assert_eq!(
+                    element_index,
+                    leaf_index_to_element_index(leaf_index as u64)
+                );
+            }
+            assert_eq!(mmr.size(), 19);
+            assert_eq!(mmr.leaves_count(), 11);
+            assert_eq!(mmr.peaks().len(), 3);
+
+            let root = mmr.root().unwrap();
+            let expected_root = root_from_peaks(hashing_function, 19, &mmr.peaks()).unwrap();
+            assert_eq!(root, expected_root);
+
+            for (leaf_index, leaf) in leaves.iter().enumerate() {
+                let element_index = leaf_index_to_element_index(leaf_index as u64);
+                let proof = mmr.proof(element_index).unwrap();
+                assert!(verify_proof(
+                    hashing_function,
+                    element_index,
+                    *leaf,
+                    &proof,
+                    mmr.size(),
+                    &mmr.peaks()
+                )
+                .unwrap());
+                // a proof doesn't verify another leaf
+                assert!(!verify_proof(
+                    hashing_function,
+                    element_index,
+                    leaves[(leaf_index + 1) % leaves.len()],
+                    &proof,
+                    mmr.size(),
+                    &mmr.peaks()
+                )
+                .unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_node_hash() {
+        let hash = B256::repeat_byte(0xab);
+        assert_eq!(parse_node_hash(&hash.to_string()).unwrap(), hash);
+        assert_eq!(
+            parse_node_hash("0x794bd0825d51a5662ba8ca27d71d1000b8e1bfb708b34b6541428d6da58795")
+                .unwrap(),
+            parse_node_hash("0x000794bd0825d51a5662ba8ca27d71d1000b8e1bfb708b34b6541428d6da58795")
+                .unwrap()
+        );
+        assert!(parse_node_hash("0xzz").is_err());
+    }
+
+    #[test]
+    fn test_keccak_mmr_root() {
+        let mut mmr = Mmr::new(HashingFunction::Keccak);
+        let a = B256::repeat_byte(1);
+        let b = B256::repeat_byte(2);
+        mmr.append(a).unwrap();
+        mmr.append(b).unwrap();
+        let parent = keccak256([a.as_slice(), b.as_slice()].concat());
+        assert_eq!(mmr.get(3), Some(parent));
+        let expected_root =
+            keccak256([B256::from(U256::from(3)).as_slice(), parent.as_slice()].concat());
+        assert_eq!(mmr.root().unwrap(), expected_root);
+    }
+}
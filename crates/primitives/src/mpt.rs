@@ -0,0 +1,468 @@
+//! Verification of Merkle Patricia Trie proofs, as returned by `eth_getProof`, and tries
+//! built in memory to prove the transactions and receipts of a block.
+//!
+//! A proof is the list of RLP encoded trie nodes on the path from the root to the key.
+//! Nodes shorter than 32 bytes are embedded in their parent instead of being hashed.
+
+use std::collections::BTreeMap;
+
+use alloy_primitives::{b256, hex, keccak256, B256};
+use alloy_rlp::Header as RlpHeader;
+use anyhow::{bail, Context, Result};
+
+use crate::utils::rlp_encode_list;
+
+/// Root of an empty trie, `keccak256(rlp(""))`
+pub const EMPTY_ROOT: B256 =
+    b256!("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421");
+
+/// Verify a proof of `key` against `root`, and return the value stored at `key`.
+///
+/// Returns `None` if the proof shows that the key is not in the trie.
+/// Fails if the proof is not a valid path from `root`.
+pub fn verify_proof(root: B256, key: &[u8], proof: &[Vec<u8>]) -> Result<Option<Vec<u8>>> {
+    let nibbles = to_nibbles(key);
+    let mut proof_nodes = proof.iter();
+    let mut position = 0;
+
+    if root == EMPTY_ROOT && proof.is_empty() {
+        return Ok(None);
+    }
+    let mut node = next_hashed_node(&mut proof_nodes, root)?;
+
+    let value = loop {
+        let items = rlp_list_items(&node)?;
+        let child = match items.len() {
+            17 => {
+                if position == nibbles.len() {
+                    break rlp_string(items[16])?.to_vec();
+                }
+                let child = items[nibbles[position] as usize];
+                position += 1;
+                child
+            }
+            2 => {
+                let (path, is_leaf) = decode_compact_path(rlp_string(items[0])?)?;
+                let remaining = &nibbles[position..];
+                if is_leaf {
+                    if remaining != path.as_slice() {
+                        break vec![];
+                    }
+                    break rlp_string(items[1])?.to_vec();
+                }
+                if !remaining.starts_with(&path) {
+                    break vec![];
+                }
+                position += path.len();
+                items[1]
+            }
+            length => bail!("Invalid trie node with {} items", length),
+        };
+
+        node = if child.first().is_some_and(|prefix| *prefix >= 0xc0) {
+            // node embedded in its parent
+            child.to_vec()
+        } else {
+            match rlp_string(child)? {
+                [] => break vec![],
+                hash if hash.len() == 32 => {
+                    next_hashed_node(&mut proof_nodes, B256::from_slice(hash))?
+                }
+                reference => bail!("Invalid trie node reference {}", hex::encode(reference)),
+            }
+        };
+    };
+
+    if proof_nodes.next().is_some() {
+        bail!("Proof has nodes after the end of the path");
+    }
+    Ok((!value.is_empty()).then_some(value))
+}
+
+/// Verify a proof given as hex strings, as returned by `eth_getProof`
+pub fn verify_hex_proof(root: B256, key: &[u8], proof: &[String]) -> Result<Option<Vec<u8>>> {
+    let proof = proof
+        .iter()
+        .map(|node| hex::decode(node).with_context(|| format!("Invalid proof node {}", node)))
+        .collect::<Result<Vec<_>>>()?;
+    verify_proof(root, key, &proof)
+}
+
+/// [`Trie`] is a Merkle Patricia Trie built from all of its entries at once, e.g. from the
+/// transactions of a block keyed by their RLP encoded index.
+#[derive(Debug, Clone)]
+pub struct Trie {
+    root: Option<Node>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    /// RLP encoding of the node, with its children hashed or embedded
+    encoded: Vec<u8>,
+    kind: NodeKind,
+}
+
+#[derive(Debug, Clone)]
+enum NodeKind {
+    Leaf,
+    Extension { path: Vec<u8>, child: Box<Node> },
+    Branch { children: Vec<Option<Node>> },
+}
+
+impl Trie {
+    pub fn new(entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> Self {
+        // sorted by nibbles, so that the entries under a node are contiguous
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| (to_nibbles(&key), value))
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        Self {
+            root: (!entries.is_empty()).then(|| build_node(&entries, 0)),
+        }
+    }
+
+    /// Trie of the values keyed by their RLP encoded index, as the transactions and
+    /// receipts tries of a block
+    pub fn from_indexed_values(values: impl IntoIterator<Item = Vec<u8>>) -> Self {
+        Self::new(
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(index, value)| (alloy_rlp::encode(index as u64), value)),
+        )
+    }
+
+    pub fn root(&self) -> B256 {
+        match &self.root {
+            Some(node) => keccak256(&node.encoded),
+            None => EMPTY_ROOT,
+        }
+    }
+
+    /// Nodes on the path from the root to `key`, which prove its value or its absence
+    pub fn proof(&self, key: &[u8]) -> Vec<Vec<u8>> {
+        let nibbles = to_nibbles(key);
+        let mut proof = vec![];
+        let mut position = 0;
+        let mut next = self.root.as_ref();
+        while let Some(node) = next {
+            // embedded nodes are part of their parent
+            if proof.is_empty() || node.encoded.len() >= 32 {
+                proof.push(node.encoded.clone());
+            }
+            next = match &node.kind {
+                NodeKind::Leaf => None,
+                NodeKind::Extension { path, child } => {
+                    if !nibbles[position..].starts_with(path) {
+                        break;
+                    }
+                    position += path.len();
+                    Some(child.as_ref())
+                }
+                NodeKind::Branch { children } => {
+                    let Some(nibble) = nibbles.get(position) else {
+                        break;
+                    };
+                    position += 1;
+                    children[*nibble as usize].as_ref()
+                }
+            };
+        }
+        proof
+    }
+}
+
+/// Build the node of the sorted `entries`, whose keys share their first `depth` nibbles
+fn build_node(entries: &[(Vec<u8>, Vec<u8>)], depth: usize) -> Node {
+    if let [(key, value)] = entries {
+        return Node {
+            encoded: rlp_encode_list(&[
+                alloy_rlp::encode(encode_compact_path(&key[depth..], true).as_slice()),
+                alloy_rlp::encode(value.as_slice()),
+            ]),
+            kind: NodeKind::Leaf,
+        };
+    }
+
+    let first = &entries[0].0[depth..];
+    let last = &entries[entries.len() - 1].0[depth..];
+    let shared = first.iter().zip(last).take_while(|(a, b)| a == b).count();
+    if shared > 0 {
+        let path = first[..shared].to_vec();
+        let child = build_node(entries, depth + shared);
+        return Node {
+            encoded: rlp_encode_list(&[
+                alloy_rlp::encode(encode_compact_path(&path, false).as_slice()),
+                node_reference(&child),
+            ]),
+            kind: NodeKind::Extension {
+                path,
+                child: Box::new(child),
+            },
+        };
+    }
+
+    // a key ending here is the value of the branch, it is the first of the sorted keys
+    let (value, entries) = match entries.split_first() {
+        Some(((key, value), rest)) if key.len() == depth => (value.as_slice(), rest),
+        _ => (&[][..], entries),
+    };
+    let children = (0..16u8)
+        .map(|nibble| {
+            let start = entries.partition_point(|(key, _)| key[depth] < nibble);
+            let end = entries.partition_point(|(key, _)| key[depth] <= nibble);
+            (start < end).then(|| build_node(&entries[start..end], depth + 1))
+        })
+        .collect::<Vec<_>>();
+    let mut items = children
+        .iter()
+        .map(|child| match child {
+            Some(child) => node_reference(child),
+            None => alloy_rlp::encode(&[][..]),
+        })
+        .collect::<Vec<_>>();
+    items.push(alloy_rlp::encode(value));
+    Node {
+        encoded: rlp_encode_list(&items),
+        kind: NodeKind::Branch { children },
+    }
+}
+
+/// Reference of a node in its parent: the node itself when shorter than 32 bytes,
+/// its hash otherwise
+fn node_reference(node: &Node) -> Vec<u8> {
+    if node.encoded.len() < 32 {
+        node.encoded.clone()
+    } else {
+        alloy_rlp::encode(keccak256(&node.encoded).as_slice())
+    }
+}
+
+/// Hex prefix encode a path, flagging whether it ends in a leaf
+fn encode_compact_path(nibbles: &[u8], is_leaf: bool) -> Vec<u8> {
+    let odd = nibbles.len() % 2 == 1;
+    let flag = (if is_leaf { 2 } else { 0 }) + u8::from(odd);
+    // odd paths keep their first nibble in the flag byte
+    let (first, rest) = match nibbles.split_first() {
+        Some((nibble, rest)) if odd => (flag << 4 | nibble, rest),
+        _ => (flag << 4, nibbles),
+    };
+    std::iter::once(first)
+        .chain(rest.chunks(2).map(|pair| pair[0] << 4 | pair[1]))
+        .collect()
+}
+
+fn next_hashed_node<'a>(
+    proof_nodes: &mut impl Iterator<Item = &'a Vec<u8>>,
+    hash: B256,
+) -> Result<Vec<u8>> {
+    let node = proof_nodes
+        .next()
+        .with_context(|| format!("Proof is missing the node {}", hash))?;
+    if keccak256(node) != hash {
+        bail!("Proof node doesn't match its hash {}", hash);
+    }
+    Ok(node.clone())
+}
+
+fn to_nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .collect()
+}
+
+/// Decode a hex prefix encoded path, returning its nibbles and whether it ends in a leaf
+fn decode_compact_path(encoded: &[u8]) -> Result<(Vec<u8>, bool)> {
+    let Some(first) = encoded.first() else {
+        bail!("Empty trie node path");
+    };
+    let flag = first >> 4;
+    if flag > 3 {
+        bail!("Invalid trie node path flag {}", flag);
+    }
+    let mut nibbles = to_nibbles(encoded);
+    // odd paths keep their first nibble in the flag byte
+    nibbles.drain(..if flag & 1 == 1 { 1 } else { 2 });
+    Ok((nibbles, flag >= 2))
+}
+
+/// Raw encodings of the items of a RLP list
+fn rlp_list_items(encoded: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut buf = encoded;
+    let header = RlpHeader::decode(&mut buf)?;
+    if !header.list || buf.len() != header.payload_length {
+        bail!("Trie node is not a RLP list");
+    }
+
+    let mut items = vec![];
+    while !buf.is_empty() {
+        let mut item = buf;
+        let item_header = RlpHeader::decode(&mut item)?;
+        let length = buf.len() - item.len() + item_header.payload_length;
+        if length > buf.len() {
+            bail!("Trie node item overflows the node");
+        }
+        items.push(&buf[..length]);
+        buf = &buf[length..];
+    }
+    Ok(items)
+}
+
+/// Payload of a RLP string
+fn rlp_string(encoded: &[u8]) -> Result<&[u8]> {
+    let mut buf = encoded;
+    let header = RlpHeader::decode(&mut buf)?;
+    if header.list || buf.len() != header.payload_length {
+        bail!("Trie node item is not a RLP string");
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_rlp::Encodable;
+
+    fn encode_string(value: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        value.encode(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_decode_compact_path() {
+        assert_eq!(
+            decode_compact_path(&[0x20, 0x12]).unwrap(),
+            (vec![1, 2], true)
+        );
+        assert_eq!(
+            decode_compact_path(&[0x31, 0x23]).unwrap(),
+            (vec![1, 2, 3], true)
+        );
+        assert_eq!(
+            decode_compact_path(&[0x00, 0x12]).unwrap(),
+            (vec![1, 2], false)
+        );
+        assert_eq!(decode_compact_path(&[0x1a]).unwrap(), (vec![0xa], false));
+        assert!(decode_compact_path(&[0x40]).is_err());
+
+        for path in [vec![], vec![1], vec![1, 2], vec![0xa, 0xb, 0xc]] {
+            for is_leaf in [false, true] {
+                let encoded = encode_compact_path(&path, is_leaf);
+                assert_eq!(
+                    decode_compact_path(&encoded).unwrap(),
+                    (path.clone(), is_leaf)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_trie_root() {
+        assert_eq!(Trie::new(vec![]).root(), EMPTY_ROOT);
+        // go-ethereum's trie insertion test, with a key prefixing another one
+        let trie = Trie::new(
+            [
+                ("doe", "reindeer"),
+                ("dog", "puppy"),
+                ("dogglesworth", "cat"),
+            ]
+            .map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes().to_vec())),
+        );
+        assert_eq!(
+            trie.root(),
+            b256!("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3")
+        );
+        for (key, value) in [
+            ("doe", "reindeer"),
+            ("dog", "puppy"),
+            ("dogglesworth", "cat"),
+        ] {
+            let proof = trie.proof(key.as_bytes());
+            assert_eq!(
+                verify_proof(trie.root(), key.as_bytes(), &proof).unwrap(),
+                Some(value.as_bytes().to_vec())
+            );
+        }
+    }
+
+    #[test]
+    fn test_trie_proofs_of_indexed_values() {
+        // indexes 0, 0x7f and 0x80 have RLP encodings of different lengths
+        let values = (0..300u64)
+            .map(|index| index.to_be_bytes().repeat(5))
+            .collect::<Vec<_>>();
+        let trie = Trie::from_indexed_values(values.clone());
+        let root = trie.root();
+        for (index, value) in values.into_iter().enumerate() {
+            let key = alloy_rlp::encode(index as u64);
+            let proof = trie.proof(&key);
+            assert_eq!(verify_proof(root, &key, &proof).unwrap(), Some(value));
+        }
+        let missing_key = alloy_rlp::encode(300u64);
+        let proof = trie.proof(&missing_key);
+        assert_eq!(verify_proof(root, &missing_key, &proof).unwrap(), None);
+    }
+
+    #[test]
+    fn test_verify_proof() {
+        // two keys sharing their first nibble, split by a branch on the second one
+        let key_a = [0x12u8; 32];
+        let mut key_b = [0x12u8; 32];
+        key_b[0] = 0x13;
+        let value_a = vec![0xaa; 40];
+        let value_b = vec![0xbb; 40];
+
+        let leaf = |key: &[u8; 32], value: &[u8]| {
+            // the leaf path starts after the extension and branch nibbles
+            let mut path = vec![0x20];
+            path.extend_from_slice(&key[1..]);
+            rlp_encode_list(&[encode_string(&path), encode_string(value)])
+        };
+        let leaf_a = leaf(&key_a, &value_a);
+        let leaf_b = leaf(&key_b, &value_b);
+
+        let mut branch_items = vec![encode_string(&[]); 17];
+        branch_items[2] = encode_string(keccak256(&leaf_a).as_slice());
+        branch_items[3] = encode_string(keccak256(&leaf_b).as_slice());
+        let branch = rlp_encode_list(&branch_items);
+        let extension = rlp_encode_list(&[
+            encode_string(&[0x11]),
+            encode_string(keccak256(&branch).as_slice()),
+        ]);
+        let root = keccak256(&extension);
+
+        let proof_a = vec![extension.clone(), branch.clone(), leaf_a.clone()];
+        assert_eq!(
+            verify_proof(root, &key_a, &proof_a).unwrap(),
+            Some(value_a.clone())
+        );
+        let proof_b = vec![extension.clone(), branch.clone(), leaf_b];
+        assert_eq!(verify_proof(root, &key_b, &proof_b).unwrap(), Some(value_b));
+
+        // a key branching to an empty child is proven absent
+        let mut key_c = key_a;
+        key_c[0] = 0x14;
+        let proof_c = vec![extension.clone(), branch.clone()];
+        assert_eq!(verify_proof(root, &key_c, &proof_c).unwrap(), None);
+        // a key diverging from the extension is proven absent
+        assert_eq!(
+            verify_proof(root, &[0x22; 32], std::slice::from_ref(&extension)).unwrap(),
+            None
+        );
+
+        // tampered value, wrong root, and truncated or extended proofs
+        let mut tampered_leaf = leaf_a.clone();
+        *tampered_leaf.last_mut().unwrap() = 0xab;
+        let tampered = vec![extension.clone(), branch.clone(), tampered_leaf];
+        assert!(verify_proof(root, &key_a, &tampered).is_err());
+        assert!(verify_proof(B256::ZERO, &key_a, &proof_a).is_err());
+        assert!(verify_proof(root, &key_a, &proof_a[..2]).is_err());
+        let extended = vec![extension, branch, leaf_a.clone(), leaf_a];
+        assert!(verify_proof(root, &key_a, &extended).is_err());
+
+        assert_eq!(verify_proof(EMPTY_ROOT, &key_a, &[]).unwrap(), None);
+    }
+}
@@ -0,0 +1,172 @@
+//! Proof that the result of one task is in the results root of a processed batch.
+//!
+//! The task and result commitments are leaves of OpenZeppelin compatible standard merkle trees,
+//! a leaf being `keccak256(keccak256(abi.encode(commitment)))`.
+//! A verifier recomputes the result commitment as `keccak256(task_commitment || result)`,
+//! with `result` the ABI encoded words of the result, and checks both proofs against the roots.
+
+use std::str::FromStr;
+
+use alloy_dyn_abi::DynSolValue;
+use alloy_primitives::{hex, keccak256, B256};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{datalake::output::ProcessedResult, task_result::TaskResult};
+
+/// [`ResultProof`] is the proof bundle of one task, extracted from a [`ProcessedResult`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResultProof {
+    pub tasks_root: B256,
+    pub results_root: B256,
+    pub task_commitment: B256,
+    pub compiled_result: TaskResult,
+    pub result_commitment: B256,
+    /// Proof of the task commitment in the tasks root
+    pub task_proof: Vec<B256>,
+    /// Proof of the result commitment in the results root
+    pub result_proof: Vec<B256>,
+}
+
+impl ResultProof {
+    /// ABI encoded arguments for a Solidity verifier:
+    ///
+    /// `(bytes32 tasksRoot, bytes32 resultsRoot, bytes32 taskCommitment, bytes32[] result,
+    ///   bytes32 resultCommitment, bytes32[] taskProof, bytes32[] resultProof)`
+    pub fn abi_encode(&self) -> Vec<u8> {
+        let words = |words: &[B256]| {
+            DynSolValue::Array(
+                words
+                    .iter()
+                    .map(|word| DynSolValue::FixedBytes(*word, 32))
+                    .collect(),
+            )
+        };
+        DynSolValue::Tuple(vec![
+            DynSolValue::FixedBytes(self.tasks_root, 32),
+            DynSolValue::FixedBytes(self.results_root, 32),
+            DynSolValue::FixedBytes(self.task_commitment, 32),
+            words(&self.compiled_result.to_words()),
+            DynSolValue::FixedBytes(self.result_commitment, 32),
+            words(&self.task_proof),
+            words(&self.result_proof),
+        ])
+        .abi_encode_params()
+    }
+
+    /// [`ResultProof::abi_encode`] as `0x` prefixed hex
+    pub fn to_calldata(&self) -> String {
+        hex::encode_prefixed(self.abi_encode())
+    }
+}
+
+impl ProcessedResult {
+    /// Extract the proof bundle of the task committed as `task_commitment`.
+    ///
+    /// Fails if the task is not in the batch, or if its result doesn't match its result commitment.
+    pub fn get_result_proof(&self, task_commitment: &str) -> Result<ResultProof> {
+        let task_commitment = B256::from_str(task_commitment)
+            .with_context(|| format!("Invalid task commitment {}", task_commitment))?;
+        let Some(task) = self.tasks.iter().find(|task| {
+            B256::from_str(&task.task_commitment)
+                .is_ok_and(|commitment| commitment == task_commitment)
+        }) else {
+            bail!("Task {} is not in the processed result", task_commitment);
+        };
+
+        let compiled_result = TaskResult::parse(task.result_type, &task.compiled_result)?;
+        let result_commitment = B256::from_str(&task.result_commitment)?;
+        let mut committed = task_commitment.to_vec();
+        for word in compiled_result.to_words() {
+            committed.extend_from_slice(word.as_slice());
+        }
+        if keccak256(&committed) != result_commitment {
+            bail!(
+                "Result {} of task {} doesn't match its result commitment {}",
+                compiled_result,
+                task_commitment,
+                result_commitment
+            );
+        }
+
+        Ok(ResultProof {
+            tasks_root: B256::from_str(&self.tasks_root)?,
+            results_root: B256::from_str(&self.results_root)?,
+            task_commitment,
+            compiled_result,
+            result_commitment,
+            task_proof: task.task_proof.clone(),
+            result_proof: task.result_proof.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{datalake::output::Task, task_result::TaskResultType};
+    use alloy_primitives::U256;
+
+    fn batch(result_commitment: B256) -> ProcessedResult {
+        ProcessedResult {
+            results_root: B256::repeat_byte(2).to_string(),
+            tasks_root: B256::repeat_byte(1).to_string(),
+            headers: vec![],
+            mmr_metas: vec![],
+            accounts: vec![],
+            storages: vec![],
+            transactions: vec![],
+            transaction_receipts: vec![],
+            tasks: vec![Task {
+                encoded_task: "0x".to_string(),
+                task_commitment: B256::repeat_byte(3).to_string(),
+                compiled_result: "6776".to_string(),
+                result_type: TaskResultType::Uint256,
+                result_commitment: result_commitment.to_string(),
+                task_proof: vec![B256::repeat_byte(4)],
+                result_proof: vec![B256::repeat_byte(5), B256::repeat_byte(6)],
+                encoded_datalake: "0x".to_string(),
+                datalake_type: 0,
+                property_type: 1,
+                time_range: None,
+            }],
+            finality_block: None,
+        }
+    }
+
+    #[test]
+    fn test_get_result_proof() {
+        let task_commitment = B256::repeat_byte(3);
+        let mut committed = task_commitment.to_vec();
+        committed.extend_from_slice(B256::from(U256::from(6776)).as_slice());
+        let processed_result = batch(keccak256(&committed));
+
+        let result_proof = processed_result
+            .get_result_proof(&task_commitment.to_string())
+            .unwrap();
+        assert_eq!(
+            result_proof.compiled_result,
+            TaskResult::Uint256(U256::from(6776))
+        );
+        assert_eq!(result_proof.tasks_root, B256::repeat_byte(1));
+        assert_eq!(result_proof.result_proof.len(), 2);
+
+        // 7 head words, then the result, task proof and result proof arrays
+        let calldata = result_proof.abi_encode();
+        assert_eq!(calldata.len(), 32 * (7 + 2 + 2 + 3));
+        assert_eq!(&calldata[64..96], task_commitment.as_slice());
+        assert_eq!(U256::from_be_slice(&calldata[96..128]), U256::from(7 * 32));
+        assert_eq!(
+            &calldata[7 * 32 + 32..7 * 32 + 64],
+            B256::from(U256::from(6776)).as_slice()
+        );
+
+        assert!(processed_result
+            .get_result_proof(&B256::repeat_byte(9).to_string())
+            .is_err());
+        let error = batch(B256::ZERO)
+            .get_result_proof(&task_commitment.to_string())
+            .unwrap_err();
+        assert!(error.to_string().contains("doesn't match"));
+    }
+}
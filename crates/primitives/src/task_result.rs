@@ -0,0 +1,226 @@
+//! Typed results of the computational tasks.
+//!
+//! A result is committed in the results merkle tree as `keccak256(task_commitment || words)`,
+//! where `words` are the 32 bytes words of the result, ABI encoded:
+//!
+//! - `uint256`: big endian
+//! - `int256`: two's complement, big endian
+//! - `bytes32`: as is
+//! - `address`: left padded with zeros
+//! - `words`: every word as is, in order
+//!
+//! A `uint256` result is committed as before results were typed.
+
+use std::{fmt::Display, str::FromStr};
+
+use alloy_primitives::{hex, Address, B256, I256, U256};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Type of a task result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskResultType {
+    #[default]
+    Uint256,
+    Int256,
+    Bytes32,
+    Address,
+    /// Several 32 bytes words
+    Words,
+}
+
+impl TaskResultType {
+    pub fn to_index(&self) -> u8 {
+        match self {
+            TaskResultType::Uint256 => 0,
+            TaskResultType::Int256 => 1,
+            TaskResultType::Bytes32 => 2,
+            TaskResultType::Address => 3,
+            TaskResultType::Words => 4,
+        }
+    }
+}
+
+impl FromStr for TaskResultType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "uint256" => Ok(TaskResultType::Uint256),
+            "int256" => Ok(TaskResultType::Int256),
+            "bytes32" => Ok(TaskResultType::Bytes32),
+            "address" => Ok(TaskResultType::Address),
+            "words" => Ok(TaskResultType::Words),
+            _ => bail!("Unknown task result type: {}", s),
+        }
+    }
+}
+
+impl Display for TaskResultType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskResultType::Uint256 => write!(f, "uint256"),
+            TaskResultType::Int256 => write!(f, "int256"),
+            TaskResultType::Bytes32 => write!(f, "bytes32"),
+            TaskResultType::Address => write!(f, "address"),
+            TaskResultType::Words => write!(f, "words"),
+        }
+    }
+}
+
+/// Result of a computational task
+///
+/// Displayed as a decimal number for integers, and as `0x` prefixed hex otherwise.
+/// Several words are displayed as the hex of their concatenation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "TypedValue", into = "TypedValue")]
+pub enum TaskResult {
+    Uint256(U256),
+    Int256(I256),
+    Bytes32(B256),
+    Address(Address),
+    Words(Vec<B256>),
+}
+
+impl TaskResult {
+    /// Parse a result displayed as `result_type`
+    pub fn parse(result_type: TaskResultType, value: &str) -> Result<Self> {
+        let result = match result_type {
+            TaskResultType::Uint256 => TaskResult::Uint256(
+                U256::from_str(value).with_context(|| format!("Invalid uint256 {}", value))?,
+            ),
+            TaskResultType::Int256 => TaskResult::Int256(
+                I256::from_str(value).with_context(|| format!("Invalid int256 {}", value))?,
+            ),
+            TaskResultType::Bytes32 => TaskResult::Bytes32(
+                B256::from_str(value).with_context(|| format!("Invalid bytes32 {}", value))?,
+            ),
+            TaskResultType::Address => TaskResult::Address(
+                Address::from_str(value).with_context(|| format!("Invalid address {}", value))?,
+            ),
+            TaskResultType::Words => {
+                let bytes =
+                    hex::decode(value).with_context(|| format!("Invalid words {}", value))?;
+                if bytes.is_empty() || bytes.len() % 32 != 0 {
+                    bail!("Invalid words {}: not a sequence of 32 bytes words", value);
+                }
+                TaskResult::Words(bytes.chunks(32).map(B256::from_slice).collect())
+            }
+        };
+        Ok(result)
+    }
+
+    pub fn result_type(&self) -> TaskResultType {
+        match self {
+            TaskResult::Uint256(_) => TaskResultType::Uint256,
+            TaskResult::Int256(_) => TaskResultType::Int256,
+            TaskResult::Bytes32(_) => TaskResultType::Bytes32,
+            TaskResult::Address(_) => TaskResultType::Address,
+            TaskResult::Words(_) => TaskResultType::Words,
+        }
+    }
+
+    /// ABI encoded 32 bytes words of the result, that are committed
+    pub fn to_words(&self) -> Vec<B256> {
+        match self {
+            TaskResult::Uint256(value) => vec![B256::from(*value)],
+            TaskResult::Int256(value) => vec![B256::from(value.into_raw())],
+            TaskResult::Bytes32(value) => vec![*value],
+            TaskResult::Address(value) => vec![value.into_word()],
+            TaskResult::Words(words) => words.clone(),
+        }
+    }
+}
+
+impl Display for TaskResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskResult::Uint256(value) => write!(f, "{}", value),
+            TaskResult::Int256(value) => write!(f, "{}", value),
+            TaskResult::Bytes32(value) => write!(f, "{}", value),
+            TaskResult::Address(value) => write!(f, "{:#x}", value),
+            TaskResult::Words(words) => write!(f, "0x{}", hex::encode(words.concat())),
+        }
+    }
+}
+
+/// Serialized form of a [`TaskResult`], its type and displayed value
+#[derive(Serialize, Deserialize)]
+struct TypedValue {
+    #[serde(rename = "type")]
+    result_type: TaskResultType,
+    value: String,
+}
+
+impl From<TaskResult> for TypedValue {
+    fn from(result: TaskResult) -> Self {
+        Self {
+            result_type: result.result_type(),
+            value: result.to_string(),
+        }
+    }
+}
+
+impl TryFrom<TypedValue> for TaskResult {
+    type Error = anyhow::Error;
+
+    fn try_from(typed_value: TypedValue) -> Result<Self> {
+        TaskResult::parse(typed_value.result_type, &typed_value.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{address, b256};
+
+    #[test]
+    fn test_task_result_words() {
+        assert_eq!(
+            TaskResult::Uint256(U256::from(10000000000000u64)).to_words(),
+            vec![b256!(
+                "000000000000000000000000000000000000000000000000000009184e72a000"
+            )]
+        );
+        assert_eq!(
+            TaskResult::Int256(I256::MINUS_ONE).to_words(),
+            vec![B256::repeat_byte(0xff)]
+        );
+        assert_eq!(
+            TaskResult::Address(address!("75cec1db9dceb703200eaa6595f66885c962b920")).to_words(),
+            vec![b256!(
+                "00000000000000000000000075cec1db9dceb703200eaa6595f66885c962b920"
+            )]
+        );
+        let words = vec![B256::repeat_byte(1), B256::repeat_byte(2)];
+        assert_eq!(TaskResult::Words(words.clone()).to_words(), words);
+    }
+
+    #[test]
+    fn test_task_result_round_trip() {
+        let results = [
+            TaskResult::Uint256(U256::from(10000000000000u64)),
+            TaskResult::Int256(I256::from_dec_str("-42").unwrap()),
+            TaskResult::Bytes32(B256::repeat_byte(0xab)),
+            TaskResult::Address(address!("75cec1db9dceb703200eaa6595f66885c962b920")),
+            TaskResult::Words(vec![B256::repeat_byte(1), B256::repeat_byte(2)]),
+        ];
+        for result in results {
+            let parsed = TaskResult::parse(result.result_type(), &result.to_string()).unwrap();
+            assert_eq!(parsed, result);
+        }
+
+        assert_eq!(
+            TaskResult::Int256(I256::from_dec_str("-42").unwrap()).to_string(),
+            "-42"
+        );
+        assert_eq!(
+            TaskResult::Address(address!("75cec1db9dceb703200eaa6595f66885c962b920")).to_string(),
+            "0x75cec1db9dceb703200eaa6595f66885c962b920"
+        );
+        assert!(TaskResult::parse(TaskResultType::Uint256, "0xzz").is_err());
+        assert!(TaskResult::parse(TaskResultType::Bytes32, "").is_err());
+        assert!(TaskResult::parse(TaskResultType::Words, "0x0102").is_err());
+    }
+}
@@ -46,6 +46,21 @@ pub fn hex_string_to_bytes(hex_string: &str) -> Result<Vec<u8>> {
     Ok(hex::decode(hex_string)?)
 }
 
+/// RLP encode a list of already RLP encoded items
+pub fn rlp_encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload_length = items.iter().map(Vec::len).sum();
+    let mut out = vec![];
+    alloy_rlp::Header {
+        list: true,
+        payload_length,
+    }
+    .encode(&mut out);
+    for item in items {
+        out.extend_from_slice(item);
+    }
+    out
+}
+
 /// Get the last byte of a byte array as a u8
 pub fn last_byte_to_u8(bytes: &[u8]) -> u8 {
     *bytes.last().unwrap_or(&0)
@@ -0,0 +1,443 @@
+//! [`LocalAccumulator`] builds a header accumulator MMR from RPC headers, for chains
+//! that are not indexed by the Herodotus indexer (devnets, private chains, anvil).
+//!
+//! Leaf `n` of the MMR is the header of block `n`, starting from genesis. The MMR is
+//! persisted as JSON after every update, and extended on the next runs.
+
+use std::{
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+};
+
+use alloy_primitives::{hex, B256};
+use anyhow::{bail, Context, Result};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use hdp_primitives::{
+    block::header::Header,
+    datalake::output::MMRMeta,
+    mmr::{leaf_index_to_element_index, HashingFunction, Mmr},
+};
+
+use super::{config::LocalMmrConfig, memory::StoredHeaders};
+
+/// Id of the local MMR in the generated [`MMRMeta`]
+pub const LOCAL_MMR_ID: u64 = 0;
+
+#[derive(Debug)]
+pub(crate) struct LocalAccumulator {
+    path: PathBuf,
+    hashing_function: HashingFunction,
+    chain_id: u64,
+    /// Loaded from `path` on first use
+    state: Mutex<Option<AccumulatorState>>,
+}
+
+impl LocalAccumulator {
+    pub fn new(config: &LocalMmrConfig, chain_id: u64) -> Self {
+        Self {
+            path: config.path.clone(),
+            hashing_function: config.hashing_function,
+            chain_id,
+            state: Mutex::new(None),
+        }
+    }
+
+    /// Extend the MMR up to `end_block`.
+    ///
+    /// The MMR is extended over the last block of a batch before its datalakes are compiled,
+    /// so that the headers of every datalake are proven against the same MMR state.
+    pub async fn extend<F, Fut>(
+        &self,
+        end_block: u64,
+        fetch_header: F,
+        max_concurrent_requests: usize,
+    ) -> Result<()>
+    where
+        F: Fn(u64) -> Fut,
+        Fut: Future<Output = Result<Header>>,
+    {
+        let mut state_guard = self.state.lock().await;
+        let state = match state_guard.as_mut() {
+            Some(state) => state,
+            None => state_guard.insert(self.load()?),
+        };
+        self.append_headers(state, end_block, &fetch_header, max_concurrent_requests)
+            .await?;
+        Ok(())
+    }
+
+    /// Extend the MMR up to `end_block` and return the headers of the range with
+    /// their MMR proofs, in the same shape as the indexer.
+    pub async fn get_sequencial_headers_with_proof<F, Fut>(
+        &self,
+        start_block: u64,
+        end_block: u64,
+        fetch_header: F,
+        max_concurrent_requests: usize,
+    ) -> Result<(StoredHeaders, MMRMeta)>
+    where
+        F: Fn(u64) -> Fut,
+        Fut: Future<Output = Result<Header>>,
+    {
+        let mut state_guard = self.state.lock().await;
+        let state = match state_guard.as_mut() {
+            Some(state) => state,
+            None => state_guard.insert(self.load()?),
+        };
+
+        let leaves_count = state.mmr.leaves_count();
+        let appended_headers = self
+            .append_headers(state, end_block, &fetch_header, max_concurrent_requests)
+            .await?;
+
+        // headers already in the MMR are checked against it
+        let mut headers = stream::iter(start_block..leaves_count.min(end_block + 1))
+            .map(|block_number| {
+                let header = fetch_header(block_number);
+                async move { header.await.map(|header| (block_number, header)) }
+            })
+            .buffered(max_concurrent_requests.max(1));
+        let mut stored_headers = StoredHeaders::new();
+        while let Some((block_number, header)) = headers.try_next().await? {
+            state.check_header(block_number, &header, &self.path)?;
+            stored_headers.insert(block_number, (header.rlp_encode(), vec![], 0, LOCAL_MMR_ID));
+        }
+        for (block_number, header) in appended_headers {
+            if block_number >= start_block {
+                stored_headers.insert(block_number, (header.rlp_encode(), vec![], 0, LOCAL_MMR_ID));
+            }
+        }
+
+        for (block_number, stored_header) in stored_headers.iter_mut() {
+            let (element_index, mmr_path) = state.header_proof(*block_number)?;
+            stored_header.1 = mmr_path;
+            stored_header.2 = element_index;
+        }
+
+        Ok((stored_headers, state.meta()?))
+    }
+
+    /// Append the headers up to `end_block` that are not in the MMR yet, and save it
+    async fn append_headers<F, Fut>(
+        &self,
+        state: &mut AccumulatorState,
+        end_block: u64,
+        fetch_header: &F,
+        max_concurrent_requests: usize,
+    ) -> Result<Vec<(u64, Header)>>
+    where
+        F: Fn(u64) -> Fut,
+        Fut: Future<Output = Result<Header>>,
+    {
+        let leaves_count = state.mmr.leaves_count();
+        if end_block < leaves_count {
+            return Ok(vec![]);
+        }
+        info!(
+            "Appending blocks {} - {} to the local MMR",
+            leaves_count, end_block
+        );
+
+        let mut headers = stream::iter(leaves_count..=end_block)
+            .map(|block_number| {
+                let header = fetch_header(block_number);
+                async move { header.await.map(|header| (block_number, header)) }
+            })
+            .buffered(max_concurrent_requests.max(1));
+        let mut appended_headers = vec![];
+        while let Some((block_number, header)) = headers.try_next().await? {
+            state.append_header(block_number, &header, &self.path)?;
+            appended_headers.push((block_number, header));
+        }
+        self.save(state)?;
+        Ok(appended_headers)
+    }
+
+    fn load(&self) -> Result<AccumulatorState> {
+        if !self.path.exists() {
+            info!("Creating a local MMR at {}", self.path.display());
+            return Ok(AccumulatorState::new(self.chain_id, self.hashing_function));
+        }
+
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read local MMR {}", self.path.display()))?;
+        let state: AccumulatorState = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid local MMR {}", self.path.display()))?;
+        if state.chain_id != self.chain_id {
+            bail!(
+                "Local MMR {} accumulates chain {}, not chain {}",
+                self.path.display(),
+                state.chain_id,
+                self.chain_id
+            );
+        }
+        if state.mmr.hashing_function != self.hashing_function {
+            bail!(
+                "Local MMR {} is hashed with {}, not {}",
+                self.path.display(),
+                state.mmr.hashing_function,
+                self.hashing_function
+            );
+        }
+        Ok(state)
+    }
+
+    /// Write the MMR to a temporary file first, so that an interrupted run doesn't corrupt it
+    fn save(&self, state: &AccumulatorState) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let temporary_path = self.path.with_extension("tmp");
+        fs::write(&temporary_path, serde_json::to_string(state)?)?;
+        fs::rename(&temporary_path, &self.path)
+            .with_context(|| format!("Failed to save local MMR {}", self.path.display()))
+    }
+}
+
+/// Persisted state of the [`LocalAccumulator`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct AccumulatorState {
+    chain_id: u64,
+    mmr: Mmr,
+    /// Hash of the last appended header, to detect reorgs
+    last_block_hash: Option<B256>,
+}
+
+impl AccumulatorState {
+    fn new(chain_id: u64, hashing_function: HashingFunction) -> Self {
+        Self {
+            chain_id,
+            mmr: Mmr::new(hashing_function),
+            last_block_hash: None,
+        }
+    }
+
+    fn append_header(&mut self, block_number: u64, header: &Header, path: &Path) -> Result<()> {
+        if block_number != self.mmr.leaves_count() {
+            bail!(
+                "Block {} can't be appended to a local MMR of {} blocks",
+                block_number,
+                self.mmr.leaves_count()
+            );
+        }
+        if let Some(last_block_hash) = self.last_block_hash {
+            if header.parent_hash != last_block_hash {
+                bail!(
+                    "Parent of block {} doesn't match the local MMR, the chain was reorganized: remove {} to rebuild it",
+                    block_number,
+                    path.display()
+                );
+            }
+        }
+
+        self.mmr
+            .append(self.mmr.hashing_function.hash_header(&rlp_bytes(header)))?;
+        self.last_block_hash = Some(header.hash_slow());
+        Ok(())
+    }
+
+    fn check_header(&self, block_number: u64, header: &Header, path: &Path) -> Result<()> {
+        let leaf = self
+            .mmr
+            .get(leaf_index_to_element_index(block_number))
+            .with_context(|| format!("Block {} is not in the local MMR", block_number))?;
+        if leaf != self.mmr.hashing_function.hash_header(&rlp_bytes(header)) {
+            bail!(
+                "Header of block {} doesn't match the local MMR, the chain was reorganized: remove {} to rebuild it",
+                block_number,
+                path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Element index and MMR path of the header of the given block
+    fn header_proof(&self, block_number: u64) -> Result<(u64, Vec<String>)> {
+        let element_index = leaf_index_to_element_index(block_number);
+        let mmr_path = self
+            .mmr
+            .proof(element_index)?
+            .iter()
+            .map(|sibling| sibling.to_string())
+            .collect();
+        Ok((element_index, mmr_path))
+    }
+
+    fn meta(&self) -> Result<MMRMeta> {
+        Ok(MMRMeta {
+            id: LOCAL_MMR_ID,
+            root: self.mmr.root()?.to_string(),
+            size: self.mmr.size(),
+            peaks: self
+                .mmr
+                .peaks()
+                .iter()
+                .map(|peak| peak.to_string())
+                .collect(),
+        })
+    }
+}
+
+fn rlp_bytes(header: &Header) -> Vec<u8> {
+    hex::decode(header.rlp_encode()).expect("rlp_encode returns valid hex")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdp_primitives::mmr::verify_proof;
+    use std::str::FromStr;
+
+    fn chain(length: u64) -> Vec<Header> {
+        let mut headers: Vec<Header> = vec![];
+        for number in 0..length {
+            let mut header = Header::rlp_decode(TEST_HEADER_RLP);
+            header.number = number;
+            header.parent_hash = headers
+                .last()
+                .map(|parent| parent.hash_slow())
+                .unwrap_or_default();
+            headers.push(header);
+        }
+        headers
+    }
+
+    #[test]
+    fn test_accumulator_state() {
+        let path = Path::new("local_mmr.json");
+        let headers = chain(6);
+        let mut state = AccumulatorState::new(31337, HashingFunction::Keccak);
+        for (block_number, header) in headers.iter().enumerate() {
+            state
+                .append_header(block_number as u64, header, path)
+                .unwrap();
+        }
+        state.check_header(3, &headers[3], path).unwrap();
+        assert!(state.check_header(3, &headers[4], path).is_err());
+        // a header whose parent isn't the last appended block
+        assert!(state.append_header(6, &headers[2], path).is_err());
+
+        let meta = state.meta().unwrap();
+        assert_eq!(meta.size, 10);
+        let peaks = meta
+            .peaks
+            .iter()
+            .map(|peak| B256::from_str(peak).unwrap())
+            .collect::<Vec<_>>();
+        for (block_number, header) in headers.iter().enumerate() {
+            let (element_index, mmr_path) = state.header_proof(block_number as u64).unwrap();
+            let siblings = mmr_path
+                .iter()
+                .map(|sibling| B256::from_str(sibling).unwrap())
+                .collect::<Vec<_>>();
+            // keccak leaves are the block hashes
+            assert!(verify_proof(
+                HashingFunction::Keccak,
+                element_index,
+                header.hash_slow(),
+                &siblings,
+                meta.size,
+                &peaks
+            )
+            .unwrap());
+        }
+    }
+
+    #[test]
+    fn test_accumulator_persistence() {
+        let path = std::env::temp_dir().join(format!("hdp-local-mmr-{}.json", std::process::id()));
+        let accumulator = LocalAccumulator::new(
+            &LocalMmrConfig {
+                path: path.clone(),
+                hashing_function: HashingFunction::Poseidon,
+            },
+            31337,
+        );
+        let mut state = accumulator.load().unwrap();
+        for (block_number, header) in chain(3).iter().enumerate() {
+            state
+                .append_header(block_number as u64, header, &path)
+                .unwrap();
+        }
+        accumulator.save(&state).unwrap();
+        assert_eq!(accumulator.load().unwrap(), state);
+
+        let other_chain = LocalAccumulator::new(
+            &LocalMmrConfig {
+                path: path.clone(),
+                hashing_function: HashingFunction::Poseidon,
+            },
+            1,
+        );
+        assert!(other_chain.load().is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_datalakes_of_a_batch_share_the_mmr() {
+        let path =
+            std::env::temp_dir().join(format!("hdp-local-mmr-batch-{}.json", std::process::id()));
+        let accumulator = LocalAccumulator::new(
+            &LocalMmrConfig {
+                path: path.clone(),
+                hashing_function: HashingFunction::Keccak,
+            },
+            31337,
+        );
+        let headers = chain(12);
+        let fetch_header = |block_number: u64| {
+            let header = headers[block_number as usize].clone();
+            async move { Ok(header) }
+        };
+
+        // the MMR already holds the first blocks, the batch ends at block 11
+        accumulator.extend(4, fetch_header, 4).await.unwrap();
+        accumulator.extend(11, fetch_header, 4).await.unwrap();
+        let (first_headers, first_meta) = accumulator
+            .get_sequencial_headers_with_proof(2, 6, fetch_header, 4)
+            .await
+            .unwrap();
+        let (second_headers, second_meta) = accumulator
+            .get_sequencial_headers_with_proof(5, 11, fetch_header, 4)
+            .await
+            .unwrap();
+        assert_eq!(first_meta, second_meta);
+        assert_eq!(first_meta.size, 22);
+
+        let peaks = first_meta
+            .peaks
+            .iter()
+            .map(|peak| B256::from_str(peak).unwrap())
+            .collect::<Vec<_>>();
+        for stored_headers in [&first_headers, &second_headers] {
+            for (block_number, (_, mmr_path, element_index, mmr_id)) in stored_headers {
+                assert_eq!(*mmr_id, LOCAL_MMR_ID);
+                let siblings = mmr_path
+                    .iter()
+                    .map(|sibling| B256::from_str(sibling).unwrap())
+                    .collect::<Vec<_>>();
+                assert!(verify_proof(
+                    HashingFunction::Keccak,
+                    *element_index,
+                    headers[*block_number as usize].hash_slow(),
+                    &siblings,
+                    first_meta.size,
+                    &peaks
+                )
+                .unwrap());
+            }
+        }
+        assert_eq!(first_headers.len(), 5);
+        assert_eq!(second_headers.len(), 7);
+        fs::remove_file(path).unwrap();
+    }
+
+    const TEST_HEADER_RLP: &str = "f90266a045adb684cb5458019c496206c1383894c360fe969a1028ba44955eadfa585cc5a01dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d4934794b636a68f834b4d75af9edc5fb0138bb4758ed293a01db2388923f7c78680b4a46bae725637013d74ad787ec5c861d3ade3df882d81a093586eb5f2781ded334a2a03d178f41dc06f271d7f1ff429e4da6ef42d12a773a0361590775fea7857cc048b9324c03e96f287199803ce1440ff1e12c5c6008049b901000420000a200308000025201005a30400008962800402185dc600144280040082221400010101200458002b0d88008028004206808408400402108f0812246200240a204365100109051c082a020081204200001060440090044044448100082100028001060640c011401a802000090331000408243804009402201240802082820403801141050a4a00208283202050000f10058894008000411050512800220a200000042275800280894080000202460040030000408001ce00282400000002a8c24210000200014a30040015020b04800020608800000850440240c06100011002000000200988001800000880128a050400329081c144080a040800000480839eb0f68401c9c380836f9a8e8465aa87809f496c6c756d696e61746520446d6f63726174697a6520447374726962757465a0c653e1c1cee990147f4439776cc3ead6f175e081998c33c93da41653112e89ce8800000000000000000da039db3f9d1fe0756e5aef4e2f0241ad957e999e49c981809c018425d0080f6cd2830400008405320000a0713ce910d12e99ba96492ff2f6411d4e0a3e567ab419e92e60cf5fc4aa74db7a";
+}
@@ -0,0 +1,170 @@
+use anyhow::{Context, Result};
+use hdp_primitives::mmr::HashingFunction;
+use reqwest::Url;
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+/// Default number of retries of a failed request
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+/// Default backoff of the first retry
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Default upper bound of the backoff between two attempts
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Default number of requests in flight when fetching a range of blocks
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 50;
+/// Default timeout of a single request
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// Default number of RPC endpoints that have to agree on a response
+pub const DEFAULT_QUORUM: usize = 1;
+/// Default MMR indexer endpoint, see its swagger doc: https://rs-indexer.api.herodotus.cloud/swagger
+pub const DEFAULT_INDEXER_URL: &str = "https://rs-indexer.api.herodotus.cloud/accumulators";
+/// Default type of the contract the indexed MMRs are accumulated in
+pub const DEFAULT_INDEXER_CONTRACT_TYPE: &str = "AGGREGATOR";
+/// Default number of blocks fetched from the indexer per request
+pub const DEFAULT_INDEXER_PAGE_SIZE: u64 = 1000;
+/// Default number of indexer pages fetched concurrently
+pub const DEFAULT_INDEXER_MAX_CONCURRENT_PAGES: usize = 4;
+
+/// [`ProviderConfig`] configures how the provider talks to the RPC and indexer endpoints.
+///
+/// Transient failures (429, 5xx, timeouts, connection errors and rate limiting JSON-RPC
+/// errors) are retried with exponential backoff and jitter. Every endpoint has its own
+/// token bucket rate limiter.
+///
+/// When several RPC endpoints are given, a failing endpoint fails over to the next one.
+/// With a `quorum` above 1, every endpoint is queried and at least `quorum` of them
+/// have to agree on header hashes and proof roots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderConfig {
+    /// Maximum number of retries of a failed request
+    pub max_retries: u32,
+    /// Backoff of the first retry, doubled on every following attempt
+    pub initial_backoff: Duration,
+    /// Upper bound of the backoff between two attempts
+    pub max_backoff: Duration,
+    /// Maximum number of requests per second sent to one endpoint, `None` disables rate limiting
+    pub max_requests_per_second: Option<u32>,
+    /// Maximum number of requests in flight when fetching a range of blocks
+    pub max_concurrent_requests: usize,
+    /// Timeout of a single request
+    pub request_timeout: Duration,
+    /// Number of RPC endpoints that have to agree on a response
+    pub quorum: usize,
+    /// MMR indexer endpoint and the accumulator it is queried for
+    pub indexer: IndexerConfig,
+    /// Build the header MMR locally instead of fetching it from the indexer
+    pub local_mmr: Option<LocalMmrConfig>,
+    /// Certificates trusted by the RPC and indexer clients
+    pub tls: TlsConfig,
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            max_requests_per_second: None,
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            quorum: DEFAULT_QUORUM,
+            indexer: IndexerConfig::default(),
+            local_mmr: None,
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+/// [`Endpoint`] is the url of a RPC or indexer endpoint, with the headers sent to it.
+///
+/// Headers carry the credentials of the endpoint, e.g. an api key or a bearer token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Endpoint {
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            headers: vec![],
+        }
+    }
+
+    /// Send the header with every request to the endpoint
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Authenticate to the endpoint with a bearer token, e.g. a JWT
+    pub fn with_bearer_auth(self, token: &str) -> Self {
+        self.with_header("Authorization", format!("Bearer {}", token))
+    }
+}
+
+impl From<Url> for Endpoint {
+    fn from(url: Url) -> Self {
+        Self::new(url)
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let url = Url::parse(s).with_context(|| format!("Invalid url {}", s))?;
+        Ok(Self::new(url))
+    }
+}
+
+/// [`TlsConfig`] sets the certificates trusted when connecting to the endpoints.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlsConfig {
+    /// PEM encoded CA certificates trusted on top of the system ones,
+    /// e.g. of a self-hosted indexer
+    pub ca_certificates: Vec<PathBuf>,
+    /// Accept invalid certificates, only meant for local development
+    pub accept_invalid_certs: bool,
+}
+
+/// [`IndexerConfig`] selects the MMR indexer headers and MMR proofs are fetched from.
+///
+/// The url can point to a staging or self-hosted instance of the indexer,
+/// as long as it serves the same `/proofs` API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexerConfig {
+    /// Base url of the indexer accumulators API, with its headers
+    pub endpoint: Endpoint,
+    /// Hashing function of the MMR to fetch proofs from
+    pub hashing_function: HashingFunction,
+    /// Type of the contract the MMR is accumulated in, e.g. `AGGREGATOR`
+    pub contract_type: String,
+    /// Number of blocks fetched per request, large ranges are fetched page by page
+    pub page_size: u64,
+    /// Maximum number of pages fetched concurrently
+    pub max_concurrent_pages: usize,
+}
+
+impl Default for IndexerConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: DEFAULT_INDEXER_URL
+                .parse()
+                .expect("Default indexer url is valid"),
+            hashing_function: HashingFunction::default(),
+            contract_type: DEFAULT_INDEXER_CONTRACT_TYPE.to_string(),
+            page_size: DEFAULT_INDEXER_PAGE_SIZE,
+            max_concurrent_pages: DEFAULT_INDEXER_MAX_CONCURRENT_PAGES,
+        }
+    }
+}
+
+/// [`LocalMmrConfig`] configures the header MMR built from RPC headers,
+/// for chains without an indexer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalMmrConfig {
+    /// File the MMR is persisted to, and extended from on the next runs
+    pub path: PathBuf,
+    pub hashing_function: HashingFunction,
+}
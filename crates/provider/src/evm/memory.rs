@@ -1,249 +1,179 @@
-//! This provider is currently not used.
+//! In memory caches of the data fetched during a run.
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use hdp_primitives::mpt::Trie;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
 
 pub type RlpEncodedValue = String;
 pub type MPTProof = Vec<String>;
 
-/// `StoredMMR` is a tuple of root, size, and peaks.
-pub type StoredMMR = (String, u64, Vec<String>);
-
-/// `StoredMMR` is a map of mmr_id to root, size, and peaks.
-type StoredMMRs = HashMap<u64, StoredMMR>;
-
 /// `StoredHeader` is a tuple of RLP encoded header and MMR proof and element_index and mmr_id.
 pub type StoredHeader = (RlpEncodedValue, MPTProof, u64, u64);
 
 /// `StoredHeader` is a map of block number to a tuple of RLP encoded header and MMR proof and element_index and mmr_id.
 pub type StoredHeaders = HashMap<u64, StoredHeader>;
 
-/// `StoredAccount` is a map of account address to a tuple of RLP encoded account, MPT proof and stored storage.
-type StoredAccounts = HashMap<String, (RlpEncodedValue, MPTProof, StoredStorages)>;
+/// `StoredTrie` is the encoded transactions or receipts of a block, with their trie.
+pub type StoredTrie = Arc<(Vec<Vec<u8>>, Trie)>;
 
-/// `StoredStorage` is a map of storage slot to a tuple of value and MPT proof.
-type StoredStorages = HashMap<String, (String, MPTProof)>;
+/// Account and storage proofs fetched during a run, to persist them and
+/// not fetch them again when an interrupted evaluation is resumed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FetchedProofs {
+    pub accounts: Vec<FetchedAccount>,
+    pub storages: Vec<FetchedStorage>,
+}
 
-/// [`InMemoryProvider`] is a memoizer that stores the data in memory.
-#[derive(Debug, Clone)]
-pub struct InMemoryProvider {
-    pub cached_headers: StoredHeaders,
-    pub cached_accounts: HashMap<u64, StoredAccounts>,
-    pub cached_mmrs: StoredMMRs,
+/// Called with the account and storage proofs of every fetch of the provider, as they are
+/// fetched, to persist them during the run
+pub type ProofRecorder = Arc<dyn Fn(FetchedProofs) + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FetchedAccount {
+    pub block_number: u64,
+    pub address: String,
+    pub rlp_encoded_account: RlpEncodedValue,
+    pub account_proof: Vec<String>,
 }
 
-impl Default for InMemoryProvider {
-    fn default() -> InMemoryProvider {
-        InMemoryProvider::new()
-    }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FetchedStorage {
+    pub block_number: u64,
+    pub address: String,
+    pub slot: String,
+    pub value: String,
+    pub storage_proof: Vec<String>,
 }
 
-impl InMemoryProvider {
-    pub fn new() -> InMemoryProvider {
-        InMemoryProvider {
-            cached_headers: HashMap::new(),
-            cached_accounts: HashMap::new(),
-            cached_mmrs: HashMap::new(),
-        }
-    }
+/// [`RequestCache`] memoizes fetched values by key, and dedupes requests in flight:
+/// concurrent requests of a same key wait for a single fetch.
+///
+/// Failed fetches are not cached, the next request of the key fetches it again.
+/// Clones of the cache share their values.
+pub(crate) struct RequestCache<K, V> {
+    cells: Arc<Mutex<HashMap<K, Arc<OnceCell<V>>>>>,
+}
 
-    /// Create a memoizer with pre-filled data
-    /// * Note: This is used for testing
-    #[allow(dead_code)]
-    fn pre_filled_memoizer(
-        cached_headers: StoredHeaders,
-        cached_accounts: HashMap<u64, StoredAccounts>,
-        cached_mmrs: StoredMMRs,
-    ) -> InMemoryProvider {
-        InMemoryProvider {
-            cached_headers,
-            cached_accounts,
-            cached_mmrs,
+impl<K, V> Clone for RequestCache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            cells: self.cells.clone(),
         }
     }
+}
 
-    #[allow(dead_code)]
-    pub fn get_mmr(&self, mmr_id: u64) -> Option<(String, u64, Vec<String>)> {
-        self.cached_mmrs.get(&mmr_id).cloned()
-    }
-
-    #[allow(dead_code)]
-    pub fn get_full_headers(
-        &self,
-        block_numbers: Vec<u64>,
-    ) -> Vec<Option<(RlpEncodedValue, MPTProof, u64, u64)>> {
-        block_numbers
-            .iter()
-            .map(|block_number| self.get_full_header_with_proof(*block_number))
-            .collect()
-    }
-
-    pub fn get_full_header_with_proof(
-        &self,
-        block_number: u64,
-    ) -> Option<(RlpEncodedValue, MPTProof, u64, u64)> {
-        self.cached_headers
-            .get(&block_number)
-            .map(|(header, proof, element_index, tree_id)| {
-                (header.clone(), proof.clone(), *element_index, *tree_id)
-            })
-    }
-
-    /// Get RLP encoded headers from the memoizer
-    /// Returns a vector of `Option<RlpEncodedValue>` for each block number
-    #[allow(dead_code)]
-    pub fn get_rlp_headers(&self, block_numbers: Vec<u64>) -> Vec<Option<RlpEncodedValue>> {
-        block_numbers
-            .iter()
-            .map(|block_number| self.get_rlp_header(*block_number))
-            .collect()
-    }
-
-    pub fn get_rlp_header(&self, block_number: u64) -> Option<RlpEncodedValue> {
-        self.cached_headers
-            .get(&block_number)
-            .map(|(header, _, _, _)| header.clone())
-    }
-
-    /// Get RLP encoded accounts and account proofs from the memoizer for multiple block numbers
-    #[allow(dead_code)]
-    pub fn get_account_from_blocks(
-        &self,
-        block_numbers: Vec<u64>,
-        account: String,
-    ) -> Vec<Option<(RlpEncodedValue, MPTProof)>> {
-        block_numbers
-            .iter()
-            .map(|block_number| self.get_account(*block_number, &account))
-            .collect()
-    }
-
-    /// Get RLP encoded account and account proof from the memoizer
-    pub fn get_account(
-        &self,
-        block_number: u64,
-        account: &str,
-    ) -> Option<(RlpEncodedValue, MPTProof)> {
-        self.cached_accounts
-            .get(&block_number)
-            .and_then(|accounts| accounts.get(account))
-            .map(|(account, proof, _)| (account.clone(), proof.clone()))
+impl<K, V> Default for RequestCache<K, V> {
+    fn default() -> Self {
+        Self {
+            cells: Arc::new(Mutex::new(HashMap::new())),
+        }
     }
+}
 
-    /// Get only the RLP encoded account from the memoizer
-    #[allow(dead_code)]
-    pub fn get_rlp_account(&self, block_number: u64, account: String) -> Option<RlpEncodedValue> {
-        self.cached_accounts
-            .get(&block_number)
-            .and_then(|accounts| accounts.get(&account))
-            .map(|(account, _, _)| account.clone())
+impl<K: Eq + Hash, V: Clone> RequestCache<K, V> {
+    /// Return the cached value of `key`, or fetch it with `fetch`.
+    /// If a fetch of `key` is already in flight, wait for it instead.
+    pub async fn get_or_fetch<F, Fut>(&self, key: K, fetch: F) -> Result<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V>>,
+    {
+        let cell = self.cell(key);
+        let value = cell.get_or_try_init(fetch).await?;
+        Ok(value.clone())
     }
 
-    /// Get RLP encoded storages and storage proofs from the memoizer for multiple block numbers
-    #[allow(dead_code)]
-    pub fn get_storage_from_blocks(
-        &self,
-        block_numbers: Vec<u64>,
-        account: String,
-        slot: String,
-    ) -> Vec<Option<(String, MPTProof)>> {
-        block_numbers
-            .iter()
-            .map(|block_number| self.get_storage(*block_number, account.clone(), slot.clone()))
-            .collect()
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.lock().get(key).and_then(|cell| cell.get()).cloned()
     }
 
-    /// Get RLP encoded storage and storage proof from the memoizer
-    pub fn get_storage(
-        &self,
-        block_number: u64,
-        account: String,
-        slot: String,
-    ) -> Option<(String, MPTProof)> {
-        self.cached_accounts
-            .get(&block_number)
-            .and_then(|accounts| accounts.get(&account))
-            .and_then(|(_, _, storages)| storages.get(&slot))
-            .map(|(value, proof)| (value.clone(), proof.clone()))
+    /// Cache a value fetched along another request.
+    /// A value already cached or in flight is kept.
+    pub fn set(&self, key: K, value: V) {
+        // fails only if the key is already set or being fetched
+        let _ = self.cell(key).set(value);
     }
 
-    /// Get only the storage value from the memoizer
-    #[allow(dead_code)]
-    pub fn get_storage_value(
-        &self,
-        block_number: u64,
-        account: String,
-        slot: String,
-    ) -> Option<String> {
-        self.cached_accounts
-            .get(&block_number)
-            .and_then(|accounts| accounts.get(&account))
-            .and_then(|(_, _, storages)| storages.get(&slot))
-            .map(|(value, _)| value.clone())
+    /// Cache a value fetched again, in place of the cached one
+    pub fn replace(&self, key: K, value: V) {
+        self.lock()
+            .insert(key, Arc::new(OnceCell::new_with(Some(value))));
     }
 
-    /// unoptimized version of get_storage_value, for testing purposes
-    #[allow(dead_code)]
-    pub fn set_headers(&mut self, headers: Vec<(u64, RlpEncodedValue)>) {
-        for (block_number, encoded_header) in headers {
-            self.set_header(block_number, encoded_header);
-        }
+    fn cell(&self, key: K) -> Arc<OnceCell<V>> {
+        self.lock().entry(key).or_default().clone()
     }
 
-    /// for testing purposes
-    pub fn set_header(&mut self, block_number: u64, encoded_header: RlpEncodedValue) {
-        self.cached_headers
-            .insert(block_number, (encoded_header, vec![], 0, 0));
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<K, Arc<OnceCell<V>>>> {
+        // the map is never left half updated, so a panic holding the lock can be ignored
+        self.cells.lock().unwrap_or_else(|e| e.into_inner())
     }
+}
 
-    #[allow(dead_code)]
-    pub fn set_full_header_with_proof(
-        &mut self,
-        block_number: u64,
-        encoded_header: RlpEncodedValue,
-        header_proof: MPTProof,
-        element_index: u64,
-        tree_id: u64,
-    ) {
-        self.cached_headers.insert(
-            block_number,
-            (encoded_header, header_proof, element_index, tree_id),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    #[tokio::test]
+    async fn test_request_cache_dedupes_requests_in_flight() {
+        let cache = RequestCache::default();
+        let fetches = AtomicUsize::new(0);
+        let fetch = || async {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok("header".to_string())
+        };
+
+        let (first, second) = tokio::join!(
+            cache.get_or_fetch(1u64, fetch),
+            cache.get_or_fetch(1u64, fetch)
         );
+        assert_eq!(first.unwrap(), "header");
+        assert_eq!(second.unwrap(), "header");
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // clones share the cached values
+        assert_eq!(cache.clone().get(&1), Some("header".to_string()));
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[tokio::test]
+    async fn test_request_cache_retries_failed_requests() {
+        let cache = RequestCache::default();
+        let failed = cache
+            .get_or_fetch(1u64, || async { bail!("rate limited") })
+            .await;
+        assert!(failed.is_err());
+        assert_eq!(cache.get(&1), None);
+
+        let value = cache
+            .get_or_fetch(1u64, || async { Ok(10u64) })
+            .await
+            .unwrap();
+        assert_eq!(value, 10);
+
+        // a value set along another request doesn't replace a cached one
+        cache.set(1, 20);
+        cache.set(2, 20);
+        assert_eq!(cache.get(&1), Some(10));
+        assert_eq!(cache.get(&2), Some(20));
+        // a value fetched again does
+        cache.replace(2, 30);
+        assert_eq!(cache.get(&2), Some(30));
+        cache.replace(2, 20);
+        assert_eq!(cache.get(&2), Some(20));
     }
-
-    // pub fn set_mmr_data(&mut self, mmr_id: u64, root: String, size: u64, peaks: Vec<String>) {
-    //     self.cached_mmrs.insert(mmr_id, (root, size, peaks));
-    // }
-
-    // pub fn set_account(
-    //     &mut self,
-    //     block_number: u64,
-    //     address: String,
-    //     encoded_account: RlpEncodedValue,
-    //     account_proof: MPTProof,
-    // ) {
-    //     self.cached_accounts
-    //         .entry(block_number)
-    //         .or_default()
-    //         .insert(address, (encoded_account, account_proof, HashMap::new()));
-    // }
-
-    // #[allow(clippy::too_many_arguments)]
-    // pub fn set_storage(
-    //     &mut self,
-    //     block_number: u64,
-    //     address: String,
-    //     encoded_account: RlpEncodedValue,
-    //     account_proof: MPTProof,
-    //     slot: String,
-    //     value: String,
-    //     storage_proof: MPTProof,
-    // ) {
-    //     self.cached_accounts
-    //         .entry(block_number)
-    //         .or_default()
-    //         .entry(address)
-    //         .or_insert((encoded_account, account_proof, HashMap::new()))
-    //         .2
-    //         .insert(slot, (value, storage_proof));
-    // }
 }
@@ -1,107 +1,423 @@
-use alloy_primitives::Bytes;
-use anyhow::{bail, Result};
-use core::panic;
-use eth_trie_proofs::{tx_receipt_trie::TxReceiptsMptHandler, tx_trie::TxsMptHandler};
-use futures::future::join_all;
-use std::{collections::HashMap, sync::Arc, time::Instant};
-use tokio::sync::RwLock;
+use alloy_primitives::{hex, Bytes, B256};
+use anyhow::{anyhow, bail, Context, Result};
+use futures::{stream, StreamExt, TryStreamExt};
+use reqwest::Url;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 use tracing::{error, info};
 
 use hdp_primitives::{
-    block::{account::Account, header::Header},
-    datalake::output::MMRMeta,
+    block::{
+        account::Account,
+        header::{Header, MMRMetaFromNewIndexer, MMRProofFromNewIndexer},
+        tag::BlockNumberOrTag,
+    },
+    datalake::{
+        output::MMRMeta,
+        time_range::{ResolvedTimeRange, TimeRange},
+    },
+    mmr::{parse_node_hash, root_from_peaks, verify_proof, HashingFunction},
+    mpt::{verify_hex_proof, Trie},
 };
 
 use self::{
-    memory::{InMemoryProvider, RlpEncodedValue, StoredHeader, StoredHeaders},
+    accumulator::LocalAccumulator,
+    config::{Endpoint, ProviderConfig},
+    memory::{RequestCache, RlpEncodedValue, StoredHeaders, StoredTrie},
+    multi::MultiRpcProvider,
+    progress::{FetchObserver, FetchProgress},
     rpc::RpcProvider,
 };
 
+pub(crate) mod accumulator;
+pub mod config;
 pub(crate) mod memory;
+pub(crate) mod multi;
+pub mod progress;
 pub(crate) mod rpc;
+pub(crate) mod transport;
+
+pub use self::memory::{FetchedAccount, FetchedProofs, FetchedStorage, ProofRecorder};
 
-// For more information swagger doc: https://rs-indexer.api.herodotus.cloud/swagger
-const HERODOTUS_RS_INDEXER_URL: &str = "https://rs-indexer.api.herodotus.cloud/accumulators";
+/// Number of times pages are fetched again when a MMR grows during a fetch
+const MAX_INDEXER_REFETCH_ROUNDS: usize = 3;
 
-/// [`AbstractProvider`] abstracts the fetching of data from the RPC and memory.
-///  It uses a [`InMemoryProvider`] and a [`RpcProvider`] to fetch data.
+/// [`AbstractProvider`] abstracts the fetching of data from the RPC endpoints and the indexer.
 ///
-/// TODO: Optimization idea, Lock only rpc provider and keep the memory provider unlocked
-/// but handle requests so that it would not make duplicate requests
+/// It is meant to be shared by concurrent tasks: the fetched data is cached in memory,
+/// and concurrent requests of the same data wait for a single fetch.
+/// Clones of the provider share their caches.
 #[derive(Clone)]
 pub struct AbstractProvider {
-    /// [`InMemoryProvider`] is used to fetch data from memory.
-    // TODO: It's not using for now
-    memory: InMemoryProvider,
-    /// Fetch data from the RPC
-    rpc_provider: RpcProvider,
-    /// Fetch block headers and MMR data from the Herodotus indexer.
+    /// Fetch data from the RPC endpoints
+    rpc_provider: MultiRpcProvider,
+    /// Fetch block headers and MMR data from the MMR indexer.
     indexer: RpcProvider,
+    /// Build the header MMR locally, used instead of the indexer when configured
+    local_accumulator: Option<Arc<LocalAccumulator>>,
+    /// Retry, rate limit, concurrency, timeout and indexer settings
+    config: ProviderConfig,
+    chain_id: u64,
+    /// Headers with their MMR proofs and the MMR metas, by block range
+    header_ranges: RequestCache<(u64, u64), (StoredHeaders, Vec<MMRMeta>)>,
+    /// RLP encoded headers from the RPC, by block number
+    rlp_headers: RequestCache<u64, RlpEncodedValue>,
+    /// RLP encoded accounts and account proofs, by block number and address
+    accounts: RequestCache<(u64, String), (RlpEncodedValue, Vec<String>)>,
+    /// Storage values and storage proofs, by block number, address and slot
+    storages: RequestCache<(u64, String, String), (String, Vec<String>)>,
+    /// Encoded transactions and their trie, by chain id and block number
+    txs_tries: RequestCache<(u64, u64), StoredTrie>,
+    /// Encoded transaction receipts and their trie, by chain id and block number
+    tx_receipts_tries: RequestCache<(u64, u64), StoredTrie>,
+    /// Reports the progress of the fetches, not shared by the clones of the provider
+    fetch_observer: Option<FetchObserver>,
+    /// Records the fetched proofs, not shared by the clones of the provider
+    proof_recorder: Option<ProofRecorder>,
 }
 
 impl AbstractProvider {
-    pub fn new(rpc_url: &'static str, chain_id: u64) -> Self {
+    pub fn new(rpc_url: Url, chain_id: u64) -> Result<Self> {
+        Self::new_with_config(
+            vec![Endpoint::new(rpc_url)],
+            chain_id,
+            ProviderConfig::default(),
+        )
+    }
+
+    /// Create a provider over several RPC endpoints, given in failover order
+    pub fn new_with_config(
+        rpc_endpoints: Vec<Endpoint>,
+        chain_id: u64,
+        config: ProviderConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            rpc_provider: MultiRpcProvider::new(rpc_endpoints, chain_id, &config)?,
+            indexer: RpcProvider::new_with_config(
+                config.indexer.endpoint.clone(),
+                chain_id,
+                &config,
+            )?,
+            local_accumulator: config
+                .local_mmr
+                .as_ref()
+                .map(|local_mmr| Arc::new(LocalAccumulator::new(local_mmr, chain_id))),
+            config,
+            chain_id,
+            header_ranges: RequestCache::default(),
+            rlp_headers: RequestCache::default(),
+            accounts: RequestCache::default(),
+            storages: RequestCache::default(),
+            txs_tries: RequestCache::default(),
+            tx_receipts_tries: RequestCache::default(),
+            fetch_observer: None,
+            proof_recorder: None,
+        })
+    }
+
+    /// Clone of the provider reporting the progress of its fetches to `observer`.
+    ///
+    /// Data served from the caches, or fetched by another clone, is not reported.
+    pub fn with_fetch_observer(&self, observer: FetchObserver) -> Self {
         Self {
-            memory: InMemoryProvider::new(),
-            rpc_provider: RpcProvider::new(rpc_url, chain_id),
-            indexer: RpcProvider::new(HERODOTUS_RS_INDEXER_URL, chain_id),
+            fetch_observer: Some(observer),
+            ..self.clone()
+        }
+    }
+
+    /// Clone of the provider recording the account and storage proofs it fetches with `recorder`.
+    ///
+    /// Proofs served from the caches, or fetched by another clone, are not recorded.
+    pub fn with_proof_recorder(&self, recorder: ProofRecorder) -> Self {
+        Self {
+            proof_recorder: Some(recorder),
+            ..self.clone()
+        }
+    }
+
+    fn record(&self, fetched_proofs: FetchedProofs) {
+        if let Some(recorder) = &self.proof_recorder {
+            recorder(fetched_proofs);
+        }
+    }
+
+    fn report(&self, progress: FetchProgress) {
+        if let Some(observer) = &self.fetch_observer {
+            observer(progress);
+        }
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    /// Cache proofs persisted by a previous run, they are not fetched again
+    pub fn load_fetched_proofs(&self, fetched_proofs: FetchedProofs) {
+        for account in fetched_proofs.accounts {
+            self.accounts.set(
+                (account.block_number, account.address),
+                (account.rlp_encoded_account, account.account_proof),
+            );
+        }
+        for storage in fetched_proofs.storages {
+            self.storages.set(
+                (storage.block_number, storage.address, storage.slot),
+                (storage.value, storage.storage_proof),
+            );
+        }
+    }
+
+    /// Fetches the number of the head of the chain
+    pub async fn get_latest_block_number(&self) -> Result<u64> {
+        self.rpc_provider.get_block_number_by_tag("latest").await
+    }
+
+    /// Fetches the number of the last finalized block, which can't be reorganized anymore
+    pub async fn get_finalized_block_number(&self) -> Result<u64> {
+        self.rpc_provider
+            .get_block_number_by_tag("finalized")
+            .await
+            .context("Failed to fetch the finalized block, the chain may not support the `finalized` tag")
+    }
+
+    /// Resolves blocks given by number or relative to a block tag to block numbers.
+    /// Every tag is fetched once, so that the blocks relative to the same tag agree.
+    pub async fn resolve_blocks(&self, blocks: &[BlockNumberOrTag]) -> Result<Vec<u64>> {
+        let mut tagged_blocks = HashMap::new();
+        let tags = blocks
+            .iter()
+            .filter_map(|block| block.tag())
+            .collect::<HashSet<_>>();
+        for tag in tags {
+            let block_number = self
+                .rpc_provider
+                .get_block_number_by_tag(&tag.to_string())
+                .await
+                .with_context(|| format!("Failed to fetch the {} block", tag))?;
+            tagged_blocks.insert(tag, block_number);
         }
+        blocks
+            .iter()
+            .map(|block| {
+                let block_number = block.resolve(&tagged_blocks)?;
+                if block.tag().is_some() {
+                    info!("Block {} resolved to {}", block, block_number);
+                }
+                Ok(block_number)
+            })
+            .collect()
+    }
+
+    /// Fetches the timestamp of a block, from its cached header
+    pub async fn get_block_timestamp(&self, block_number: u64) -> Result<u64> {
+        let rlp_header = self.get_rlp_header(block_number).await?;
+        Ok(Header::rlp_decode(&rlp_header).timestamp)
+    }
+
+    /// Resolves a time range to the first and last blocks of the chain inside it,
+    /// with a binary search over the timestamps of the headers.
+    pub async fn resolve_time_range(&self, time_range: TimeRange) -> Result<ResolvedTimeRange> {
+        let head = self.get_latest_block_number().await?;
+        let timestamp_of = |block_number| self.get_block_timestamp(block_number);
+        resolve_blocks_in_time_range(time_range, head, &timestamp_of).await
     }
 
-    // TODO: wip
+    /// Appends the headers up to `end_block` to the local MMR, when one is configured.
+    ///
+    /// Called with the last block of a batch before its datalakes are fetched, so that the
+    /// headers of all of them are proven against the same local MMR.
+    pub async fn extend_local_mmr(&self, end_block: u64) -> Result<()> {
+        let Some(local_accumulator) = &self.local_accumulator else {
+            return Ok(());
+        };
+        local_accumulator
+            .extend(
+                end_block,
+                |block_number| self.fetch_header(block_number),
+                self.config.max_concurrent_requests,
+            )
+            .await
+    }
+
+    async fn fetch_header(&self, block_number: u64) -> Result<Header> {
+        self.rpc_provider
+            .get_block_by_number(block_number)
+            .await
+            .map(|block| Header::from(&block))
+    }
+
+    /// Fetches the headers of the blocks in the given range with their MMR proofs,
+    /// and the metadata of every MMR the headers are proven against.
     pub async fn get_sequencial_full_header_with_proof(
         &self,
         start_block: u64,
         end_block: u64,
-    ) -> Result<(StoredHeaders, MMRMeta)> {
-        //? A map of block numbers to a boolean indicating whether the block was fetched.
-        let mut blocks_map: HashMap<u64, StoredHeader> = HashMap::new();
+    ) -> Result<(StoredHeaders, Vec<MMRMeta>)> {
+        self.header_ranges
+            .get_or_fetch((start_block, end_block), || {
+                self.fetch_sequencial_full_header_with_proof(start_block, end_block)
+            })
+            .await
+    }
+
+    /// Fetches the headers of the range again, proven against the current state of their
+    /// MMRs, in place of the cached ones
+    pub async fn refetch_sequencial_full_header_with_proof(
+        &self,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<()> {
+        let headers = self
+            .fetch_sequencial_full_header_with_proof(start_block, end_block)
+            .await?;
+        self.header_ranges
+            .replace((start_block, end_block), headers);
+        Ok(())
+    }
+
+    async fn fetch_sequencial_full_header_with_proof(
+        &self,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<(StoredHeaders, Vec<MMRMeta>)> {
+        if let Some(local_accumulator) = &self.local_accumulator {
+            let start_fetch = Instant::now();
+            let result = local_accumulator
+                .get_sequencial_headers_with_proof(
+                    start_block,
+                    end_block,
+                    |block_number| self.fetch_header(block_number),
+                    self.config.max_concurrent_requests,
+                )
+                .await;
+            info!("Time taken (local MMR): {:?}", start_fetch.elapsed());
+            let (headers, mmr_meta) = result?;
+            let total = end_block - start_block + 1;
+            self.report(FetchProgress::Headers {
+                fetched: total,
+                total,
+            });
+            return Ok((headers, vec![mmr_meta]));
+        }
 
         // Fetch MMR data and header data from Herodotus indexer
         let start_fetch = Instant::now();
-
-        let mmr_data = self
-            .indexer
-            .get_sequencial_headers_and_mmr_from_indexer(start_block, end_block)
+        let result = self
+            .get_sequencial_full_header_with_proof_from_indexer(start_block, end_block)
             .await;
+        info!(
+            "Time taken (fetch from Indexer): {:?}",
+            start_fetch.elapsed()
+        );
+        if let Err(e) = &result {
+            error!(
+                "Something went wrong while fetching MMR data from indexer: {}",
+                e
+            );
+        }
+        result
+    }
 
-        match mmr_data {
-            Ok(mmr) => {
-                info!("Successfully fetched MMR data from indexer");
-                let duration = start_fetch.elapsed();
-                info!("Time taken (fetch from Indexer): {:?}", duration);
-                for block_proof in &mmr.1 {
-                    blocks_map.insert(
-                        *block_proof.0,
-                        (
-                            block_proof.1.rlp_block_header.value.clone(),
-                            block_proof.1.siblings_hashes.clone(),
-                            block_proof.1.element_index,
-                            mmr.0.mmr_id,
-                        ),
-                    );
-                }
+    /// Fetches the range from the indexer in pages of `indexer.page_size` blocks, so that
+    /// only `indexer.max_concurrent_pages` responses are held in memory at a time.
+    ///
+    /// The indexer proves every page against the current state of its MMRs. If a MMR
+    /// grew while the range was being fetched, the pages proven against its older state
+    /// are fetched again, so that all the headers of a MMR share the same [`MMRMeta`].
+    async fn get_sequencial_full_header_with_proof_from_indexer(
+        &self,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<(StoredHeaders, Vec<MMRMeta>)> {
+        let pages = indexer_pages(start_block, end_block, self.config.indexer.page_size);
+        let mut blocks_map: StoredHeaders = HashMap::new();
+        let mut latest_metas: HashMap<u64, MMRMeta> = HashMap::new();
+        let mut page_metas: HashMap<(u64, u64), Vec<MMRMeta>> = HashMap::new();
 
-                Ok((
-                    blocks_map,
-                    MMRMeta {
-                        id: mmr.0.mmr_id,
-                        root: mmr.0.mmr_root,
-                        size: mmr.0.mmr_size,
-                        peaks: mmr.0.mmr_peaks,
-                    },
-                ))
+        let mut pending_pages = pages.clone();
+        for round in 0..=MAX_INDEXER_REFETCH_ROUNDS {
+            if round > 0 {
+                info!(
+                    "MMR grew while fetching from indexer, fetching {} pages again",
+                    pending_pages.len()
+                );
             }
-            Err(e) => {
-                let duration = start_fetch.elapsed();
-                info!("Time taken (during from Indexer): {:?}", duration);
-                error!(
-                    "Something went wrong while fetching MMR data from indexer: {}",
-                    e
+
+            let pending_count = pending_pages.len();
+            let pending_blocks = pending_pages
+                .iter()
+                .map(|(from_block, to_block)| to_block - from_block + 1)
+                .sum();
+            let mut responses = stream::iter(pending_pages)
+                .map(|(from_block, to_block)| async move {
+                    self.indexer
+                        .get_sequencial_headers_and_mmr_from_indexer(
+                            from_block,
+                            to_block,
+                            self.config.indexer.hashing_function,
+                            &self.config.indexer.contract_type,
+                        )
+                        .await
+                        .map(|mmrs| (from_block, to_block, mmrs))
+                })
+                .buffer_unordered(self.config.indexer.max_concurrent_pages.max(1));
+
+            let mut fetched_count = 0;
+            let mut fetched_blocks = 0;
+            while let Some((from_block, to_block, mmrs)) = responses.try_next().await? {
+                let (headers, metas) = merge_mmrs_from_indexer(from_block, to_block, mmrs)?;
+                verify_header_proofs(self.config.indexer.hashing_function, &headers, &metas)?;
+                blocks_map.extend(headers);
+                for meta in &metas {
+                    match latest_metas.get(&meta.id) {
+                        Some(latest) if latest.size >= meta.size => {}
+                        _ => {
+                            latest_metas.insert(meta.id, meta.clone());
+                        }
+                    }
+                }
+                page_metas.insert((from_block, to_block), metas);
+
+                fetched_count += 1;
+                fetched_blocks += to_block - from_block + 1;
+                self.report(FetchProgress::Headers {
+                    fetched: fetched_blocks,
+                    total: pending_blocks,
+                });
+                info!(
+                    "Fetched MMR proofs of blocks {} - {} from indexer ({}/{})",
+                    from_block, to_block, fetched_count, pending_count
                 );
-                Err(e)
+            }
+
+            pending_pages = pages
+                .iter()
+                .filter(|page| {
+                    page_metas[page]
+                        .iter()
+                        .any(|meta| latest_metas[&meta.id] != *meta)
+                })
+                .copied()
+                .collect();
+            if pending_pages.is_empty() {
+                let mut mmr_metas = latest_metas.into_values().collect::<Vec<_>>();
+                mmr_metas.sort_by_key(|mmr_meta| mmr_meta.id);
+                return Ok((blocks_map, mmr_metas));
             }
         }
+
+        bail!(
+            "MMR kept growing while fetching blocks {} - {} from indexer",
+            start_block,
+            end_block
+        )
     }
 
     // /// Fetches the headers of the blocks and relevant MMR metatdata in the given block range.
@@ -212,23 +528,14 @@ impl AbstractProvider {
     //     }
     // }
 
-    // Unoptimized version of get_rlp_header, just for testing purposes
-    pub async fn get_rlp_header(&mut self, block_number: u64) -> RlpEncodedValue {
-        match self.memory.get_rlp_header(block_number) {
-            Some(header) => header,
-            None => {
-                let header_rpc = self
-                    .rpc_provider
-                    .get_block_by_number(block_number)
-                    .await
-                    .unwrap();
-                let block_header = Header::from(&header_rpc);
-                let rlp_encoded = block_header.rlp_encode();
-                self.memory.set_header(block_number, rlp_encoded.clone());
-
-                rlp_encoded
-            }
-        }
+    /// Fetches the RLP encoded header of a block from the RPC, without its MMR proof
+    pub async fn get_rlp_header(&self, block_number: u64) -> Result<RlpEncodedValue> {
+        self.rlp_headers
+            .get_or_fetch(block_number, || async {
+                let header_rpc = self.rpc_provider.get_block_by_number(block_number).await?;
+                Ok(Header::from(&header_rpc).rlp_encode())
+            })
+            .await
     }
 
     // pub async fn get_account_with_proof(
@@ -261,103 +568,81 @@ impl AbstractProvider {
     // Get account with proof in given range of blocks
     // This need to be used for block sampled datalake
     pub async fn get_range_account_with_proof(
-        &mut self,
+        &self,
         block_range_start: u64,
         block_range_end: u64,
         increment: u64,
         address: String,
     ) -> Result<HashMap<u64, (String, Vec<String>)>> {
         let start_fetch = Instant::now();
-        //? A map of block numbers to a boolean indicating whether the block was fetched.
-        // This contains rlp encoded account and account proof
-        let blocks_map = Arc::new(RwLock::new(HashMap::new()));
 
+        // the blocks the compiler samples, multiple of the increment
         let target_block_range: Vec<u64> = (block_range_start..=block_range_end)
-            .step_by(increment as usize)
+            .filter(|block| block % increment == 0)
             .collect();
 
-        for block_number in &target_block_range {
-            let account = self.memory.get_account(*block_number, &address);
-            if let Some(account) = account {
-                let mut blocks_map_write = blocks_map.write().await;
-                blocks_map_write.insert(*block_number, (true, account.0, account.1));
-            }
-        }
-
-        // chunk the target_block_range into 50
-        let target_block_range_chunks: Vec<Vec<u64>> =
-            target_block_range.chunks(50).map(|x| x.to_vec()).collect();
-
-        for one_chunked_block_range in target_block_range_chunks {
-            // Prepare and execute futures for missing blocks
-            let fetch_futures: Vec<_> = one_chunked_block_range
-                .clone()
-                .into_iter()
-                .map(|block_number| {
-                    let blocks_map_clone = blocks_map.clone();
-                    let rpc_clone = self.rpc_provider.clone(); // Assume self.rpc can be cloned or is wrapped in Arc
-                    let address_clone = address.clone();
-                    async move {
-                        let should_fetch = {
-                            let read_guard = blocks_map_clone.read().await;
-                            !read_guard.contains_key(&block_number)
-                        };
-                        if should_fetch {
-                            match rpc_clone
-                                .get_proof(block_number, &address_clone, None)
-                                .await
-                            {
-                                Ok(account_from_rpc) => {
-                                    let mut write_guard = blocks_map_clone.write().await;
-                                    let retrieved_account = Account::from(&account_from_rpc);
-                                    let rlp_encoded_account = retrieved_account.rlp_encode();
-                                    let account_proof = account_from_rpc.account_proof;
-                                    write_guard.insert(
-                                        block_number,
-                                        (true, rlp_encoded_account, account_proof),
-                                    );
-                                    // Assuming conversion to RlpEncodedValue is done here
-                                }
-                                Err(e) => {
-                                    error!(
-                                        "Failed to fetch account in block {}: {}",
-                                        block_number, e
-                                    );
-                                    bail!(e);
-                                    // Optionally handle errors by inserting a placeholder or error indicator
-                                }
-                            }
-                        }
-                        Ok(())
-                    }
-                })
-                .collect();
-
-            // Execute fetch operations in parallel
-            join_all(fetch_futures).await;
-        }
+        // This contains rlp encoded account and account proof,
+        // missing blocks are fetched at most `max_concurrent_requests` at a time
+        let total = target_block_range.len() as u64;
+        let fetched = AtomicU64::new(0);
+        let result = stream::iter(target_block_range)
+            .map(|block_number| {
+                let address = &address;
+                let fetched = &fetched;
+                async move {
+                    let account = self
+                        .accounts
+                        .get_or_fetch((block_number, address.clone()), || {
+                            self.fetch_account_with_proof(block_number, address)
+                        })
+                        .await?;
+                    self.report(FetchProgress::Proofs {
+                        fetched: fetched.fetch_add(1, Ordering::Relaxed) + 1,
+                        total,
+                    });
+                    Ok::<_, anyhow::Error>((block_number, account))
+                }
+            })
+            .buffer_unordered(self.config.max_concurrent_requests.max(1))
+            .try_collect()
+            .await?;
 
-        // Construct the final result vector from blocks_map
-        let blocks_map_read = blocks_map.read().await;
         let duration = start_fetch.elapsed();
         info!("Time taken (Account Fetch): {:?}", duration);
 
-        let mut result = HashMap::new();
+        Ok(result)
+    }
 
-        for block in &target_block_range {
-            if !blocks_map_read.contains_key(block) {
-                bail!("Failed to fetch account in block {}", block);
+    async fn fetch_account_with_proof(
+        &self,
+        block_number: u64,
+        address: &str,
+    ) -> Result<(RlpEncodedValue, Vec<String>)> {
+        match self
+            .rpc_provider
+            .get_proof(block_number, address, None)
+            .await
+        {
+            Ok(account_from_rpc) => {
+                let retrieved_account = Account::from(&account_from_rpc);
+                let rlp_encoded_account = retrieved_account.rlp_encode();
+                let account_proof = account_from_rpc.account_proof;
+                self.record(FetchedProofs {
+                    accounts: vec![FetchedAccount {
+                        block_number,
+                        address: address.to_string(),
+                        rlp_encoded_account: rlp_encoded_account.clone(),
+                        account_proof: account_proof.clone(),
+                    }],
+                    storages: vec![],
+                });
+                Ok((rlp_encoded_account, account_proof))
+            }
+            Err(e) => {
+                error!("Failed to fetch account in block {}: {}", block_number, e);
+                bail!("Failed to fetch account in block {}: {}", block_number, e);
             }
-            result.insert(
-                *block,
-                blocks_map_read
-                    .get(block)
-                    .map(|(_, b, c)| (b.clone(), c.clone()))
-                    .unwrap(),
-            );
         }
-
-        Ok(result)
     }
 
     // pub async fn get_storage_value_with_proof(
@@ -399,7 +684,7 @@ impl AbstractProvider {
     // Get storage with proof in given range of blocks
     // This need to be used for block sampled datalake
     pub async fn get_range_storage_with_proof(
-        &mut self,
+        &self,
         block_range_start: u64,
         block_range_end: u64,
         increment: u64,
@@ -407,222 +692,702 @@ impl AbstractProvider {
         slot: String,
     ) -> Result<HashMap<u64, (String, Vec<String>, String, Vec<String>)>> {
         let start_fetch = Instant::now();
-        //? A map of block numbers to a boolean indicating whether the block was fetched.
-        // This contains rlp encoded account, account proof, storage value and storage proof
-        let blocks_map = Arc::new(RwLock::new(HashMap::new()));
 
+        // the blocks the compiler samples, multiple of the increment
         let target_block_range: Vec<u64> = (block_range_start..=block_range_end)
-            .step_by(increment as usize)
+            .filter(|block| block % increment == 0)
             .collect();
 
-        for block_number in &target_block_range {
-            let storage = self
-                .memory
-                .get_storage(*block_number, address.clone(), slot.clone());
-            let account = self.memory.get_account(*block_number, &address);
-            if let Some(storage) = storage {
-                let retrieved_account = account.unwrap();
-                let mut blocks_map_write = blocks_map.write().await;
-                blocks_map_write.insert(
-                    *block_number,
-                    (
-                        true,
-                        retrieved_account.0,
-                        retrieved_account.1,
-                        storage.0,
-                        storage.1,
-                    ),
-                );
-            }
-        }
-
-        // chunk the target_block_range into 50
-        let target_block_range_chunks: Vec<Vec<u64>> =
-            target_block_range.chunks(50).map(|x| x.to_vec()).collect();
-
-        for one_chunk_block_range in target_block_range_chunks {
-            // Prepare and execute futures for missing blocks
-            let fetch_futures: Vec<_> = one_chunk_block_range
-                .clone()
-                .into_iter()
-                .map(|block_number| {
-                    let blocks_map_clone = blocks_map.clone();
-                    let rpc_clone = self.rpc_provider.clone(); // Assume self.rpc can be cloned or is wrapped in Arc
-                    let address_clone = address.clone();
-                    let slot_clone = slot.clone();
-                    async move {
-                        let should_fetch = {
-                            let read_guard = blocks_map_clone.read().await;
-                            !read_guard.contains_key(&block_number)
-                        };
-                        if should_fetch {
-                            match rpc_clone
-                                .get_proof(block_number, &address_clone, Some(vec![slot_clone]))
-                                .await
-                            {
-                                Ok(account_from_rpc) => {
-                                    let mut write_guard = blocks_map_clone.write().await;
-                                    let retrieved_account = Account::from(&account_from_rpc);
-                                    let storage = &account_from_rpc.storage_proof[0];
-                                    let rlp_encoded = retrieved_account.rlp_encode();
-                                    let storage_value = storage.value.clone();
-                                    let storage_proof =
-                                        account_from_rpc.storage_proof[0].proof.clone();
-                                    let account_proof = account_from_rpc.account_proof;
-                                    write_guard.insert(
-                                        block_number,
-                                        (
-                                            true,
-                                            rlp_encoded,
-                                            account_proof,
-                                            storage_value,
-                                            storage_proof,
-                                        ),
-                                    );
-                                    // Assuming conversion to RlpEncodedValue is done here
-                                }
-                                Err(e) => {
-                                    // TODO: handle error in proper way
-                                    if e.to_string().contains("No storage proof found") {
-                                        error!("Storage value not exist: {}", e);
-                                        panic!("{}", e);
-                                    } else {
-                                        error!(
-                                            "Failed to fetch storage in block {}: {}",
-                                            block_number, e
-                                        );
-                                        bail!(e);
-                                    }
-                                }
-                            }
-                        }
-                        Ok(())
-                    }
-                })
-                .collect();
-
-            // Execute fetch operations in parallel
-            join_all(fetch_futures).await;
-        }
+        // This contains rlp encoded account, account proof, storage value and storage proof,
+        // missing blocks are fetched at most `max_concurrent_requests` at a time
+        let total = target_block_range.len() as u64;
+        let fetched = AtomicU64::new(0);
+        let result = stream::iter(target_block_range)
+            .map(|block_number| {
+                let address = &address;
+                let slot = &slot;
+                let fetched = &fetched;
+                async move {
+                    let storage = self
+                        .storages
+                        .get_or_fetch((block_number, address.clone(), slot.clone()), || async {
+                            let storages = self
+                                .fetch_storages_of_block(block_number, address, vec![slot.clone()])
+                                .await?;
+                            storages.into_iter().next().with_context(|| {
+                                format!(
+                                    "No storage proof of slot {} of {} in block {}",
+                                    slot, address, block_number
+                                )
+                            })
+                        })
+                        .await?;
+                    // cached along the storage, unless the account was being fetched on its own
+                    let account = self
+                        .accounts
+                        .get_or_fetch((block_number, address.clone()), || {
+                            self.fetch_account_with_proof(block_number, address)
+                        })
+                        .await?;
+                    self.report(FetchProgress::Proofs {
+                        fetched: fetched.fetch_add(1, Ordering::Relaxed) + 1,
+                        total,
+                    });
+                    Ok::<_, anyhow::Error>((
+                        block_number,
+                        (account.0, account.1, storage.0, storage.1),
+                    ))
+                }
+            })
+            .buffer_unordered(self.config.max_concurrent_requests.max(1))
+            .try_collect()
+            .await?;
 
-        // Construct the final result vector from blocks_map
-        let blocks_map_read = blocks_map.read().await;
         let duration = start_fetch.elapsed();
         info!("Time taken (Storage Fetch): {:?}", duration);
 
-        let mut result = HashMap::new();
+        Ok(result)
+    }
 
-        for block in &target_block_range {
-            if !blocks_map_read.contains_key(block) {
-                bail!("Failed to fetch storage in block {}", block);
+    /// Fetches the account and the given storage slots of `address` in each block, with a
+    /// single `eth_getProof` request per block. The account proof is shared by the slots.
+    ///
+    /// The results are cached, so that the datalakes sampling these slots don't fetch them again.
+    pub async fn fetch_storages_with_proof(
+        &self,
+        address: &str,
+        slots_by_block: Vec<(u64, Vec<String>)>,
+    ) -> Result<()> {
+        // Fetch the slots that are not cached yet, at most `max_concurrent_requests` blocks at a time
+        let total = slots_by_block.len() as u64;
+        let fetched = AtomicU64::new(0);
+        stream::iter(slots_by_block)
+            .map(|(block_number, slots)| {
+                let fetched = &fetched;
+                let slots = slots
+                    .into_iter()
+                    .filter(|slot| {
+                        self.storages
+                            .get(&(block_number, address.to_string(), slot.clone()))
+                            .is_none()
+                    })
+                    .collect::<Vec<_>>();
+                async move {
+                    if !slots.is_empty() {
+                        self.fetch_storages_of_block(block_number, address, slots)
+                            .await?;
+                    }
+                    self.report(FetchProgress::Proofs {
+                        fetched: fetched.fetch_add(1, Ordering::Relaxed) + 1,
+                        total,
+                    });
+                    Ok::<_, anyhow::Error>(())
+                }
+            })
+            .buffer_unordered(self.config.max_concurrent_requests.max(1))
+            .try_collect::<()>()
+            .await
+    }
+
+    /// Fetches the account and the given storage slots of `address` in one `eth_getProof`
+    /// request, and caches them. Returns the storages in the order of `slots`.
+    async fn fetch_storages_of_block(
+        &self,
+        block_number: u64,
+        address: &str,
+        slots: Vec<String>,
+    ) -> Result<Vec<(String, Vec<String>)>> {
+        let account_from_rpc = match self
+            .rpc_provider
+            .get_proof(block_number, address, Some(slots.clone()))
+            .await
+        {
+            Ok(account_from_rpc) => account_from_rpc,
+            Err(e) => {
+                error!("Failed to fetch storage in block {}: {}", block_number, e);
+                bail!("Failed to fetch storage in block {}: {}", block_number, e);
             }
-            result.insert(
-                *block,
-                blocks_map_read
-                    .get(block)
-                    .map(|(_, a, b, c, d)| (a.clone(), b.clone(), c.clone(), d.clone()))
-                    .unwrap(),
+        };
+
+        // storage proofs are returned in the order of the requested slots
+        if account_from_rpc.storage_proof.len() != slots.len() {
+            bail!(
+                "RPC returned {} storage proofs for {} slots of {} in block {}",
+                account_from_rpc.storage_proof.len(),
+                slots.len(),
+                address,
+                block_number
             );
         }
-
-        Ok(result)
+        let account = FetchedAccount {
+            block_number,
+            address: address.to_string(),
+            rlp_encoded_account: Account::from(&account_from_rpc).rlp_encode(),
+            account_proof: account_from_rpc.account_proof,
+        };
+        self.accounts.set(
+            (block_number, address.to_string()),
+            (
+                account.rlp_encoded_account.clone(),
+                account.account_proof.clone(),
+            ),
+        );
+        let storages = account_from_rpc
+            .storage_proof
+            .into_iter()
+            .map(|storage| (storage.value, storage.proof))
+            .collect::<Vec<_>>();
+        let mut fetched_storages = Vec::new();
+        for (slot, (value, storage_proof)) in slots.into_iter().zip(&storages) {
+            self.storages.set(
+                (block_number, address.to_string(), slot.clone()),
+                (value.clone(), storage_proof.clone()),
+            );
+            fetched_storages.push(FetchedStorage {
+                block_number,
+                address: address.to_string(),
+                slot,
+                value: value.clone(),
+                storage_proof: storage_proof.clone(),
+            });
+        }
+        self.record(FetchedProofs {
+            accounts: vec![account],
+            storages: fetched_storages,
+        });
+        Ok(storages)
     }
 
     /// Fetches the encoded transaction with proof from the MPT trie for the given block number.
-    /// The transaction is fetched from the MPT trie and the proof is generated from the MPT trie.
+    /// The transactions are fetched from the RPC endpoints and the proof is generated from
+    /// the trie built from them.
+    ///
+    /// The trie is checked against `transactions_root`, the root committed in the MMR proven
+    /// header of the block, and every returned proof is verified against it.
+    ///
+    /// The trie of a block is built once per run, and reused by the datalakes targeting it.
     pub async fn get_tx_with_proof_from_block(
         &self,
         target_block: u64,
         incremental: u64,
+        transactions_root: B256,
     ) -> Result<Vec<(u64, u64, String, Vec<String>)>> {
-        let mut tx_with_proof = vec![];
-        let mut txs_mpt_handler = TxsMptHandler::new(self.rpc_provider.url).unwrap();
-        txs_mpt_handler
-            .build_tx_tree_from_block(target_block)
-            .await
-            .unwrap();
-        let txs = txs_mpt_handler.get_elements().unwrap();
-        let txs_length = txs.len();
-        let target_tx_index_range = (0..txs_length).step_by(incremental as usize);
-        for tx_index in target_tx_index_range {
-            let target_tx_index = tx_index as u64;
-            let proof = txs_mpt_handler
-                .get_proof(target_tx_index)
-                .unwrap()
-                .into_iter()
-                .map(|x| Bytes::from(x).to_string())
-                .collect::<Vec<_>>();
-            let consensus_tx = txs[tx_index].clone();
-            let rlp = Bytes::from(consensus_tx.rlp_encode()).to_string();
-            tx_with_proof.push((target_block, target_tx_index, rlp, proof));
-        }
+        let txs_trie = self
+            .txs_tries
+            .get_or_fetch((self.chain_id, target_block), || async {
+                let txs = self
+                    .rpc_provider
+                    .get_block_transactions(target_block)
+                    .await?;
+                let trie = Trie::from_indexed_values(txs.clone());
+                Ok(Arc::new((txs, trie)))
+            })
+            .await?;
+        let (txs, trie) = txs_trie.as_ref();
+
+        let tx_with_proof = elements_with_proof(target_block, incremental, txs, trie);
+        verify_trie_proofs(
+            "transactions",
+            target_block,
+            transactions_root,
+            trie.root(),
+            &tx_with_proof,
+        )?;
 
         Ok(tx_with_proof)
     }
 
     /// Fetches the encoded transaction receipt with proof from the MPT trie for the given block number.
-    /// The transaction receipt is fetched from the MPT trie and the proof is generated from the MPT trie.
+    /// The receipts are fetched from the RPC endpoints and the proof is generated from
+    /// the trie built from them.
+    ///
+    /// The trie is checked against `receipts_root`, the root committed in the MMR proven
+    /// header of the block, and every returned proof is verified against it.
+    ///
+    /// The trie of a block is built once per run, and reused by the datalakes targeting it.
     pub async fn get_tx_receipt_with_proof_from_block(
         &self,
         target_block: u64,
         incremental: u64,
+        receipts_root: B256,
     ) -> Result<Vec<(u64, u64, String, Vec<String>)>> {
-        let mut tx_receipt_with_proof = vec![];
-        let mut tx_reciepts_mpt_handler = TxReceiptsMptHandler::new(self.rpc_provider.url).unwrap();
+        let tx_receipts_trie = self
+            .tx_receipts_tries
+            .get_or_fetch((self.chain_id, target_block), || async {
+                let tx_receipts = self.rpc_provider.get_block_receipts(target_block).await?;
+                let trie = Trie::from_indexed_values(tx_receipts.clone());
+                Ok(Arc::new((tx_receipts, trie)))
+            })
+            .await?;
+        let (tx_receipts, trie) = tx_receipts_trie.as_ref();
 
-        tx_reciepts_mpt_handler
-            .build_tx_receipts_tree_from_block(target_block)
-            .await
-            .unwrap();
-        let tx_receipts = tx_reciepts_mpt_handler.get_elements().unwrap();
-        let tx_receipts_length = tx_receipts.len();
-        let target_tx_receipt_index_range = (0..tx_receipts_length).step_by(incremental as usize);
-        for tx_receipt_index in target_tx_receipt_index_range {
-            let target_tx_receipt_index = tx_receipt_index as u64;
-            let proof = tx_reciepts_mpt_handler
-                .get_proof(target_tx_receipt_index)
-                .unwrap()
+        let tx_receipt_with_proof =
+            elements_with_proof(target_block, incremental, tx_receipts, trie);
+        verify_trie_proofs(
+            "receipts",
+            target_block,
+            receipts_root,
+            trie.root(),
+            &tx_receipt_with_proof,
+        )?;
+
+        Ok(tx_receipt_with_proof)
+    }
+}
+
+/// Every `incremental`th element of a transactions or receipts trie, from the first one,
+/// with its index, its encoding and its proof
+fn elements_with_proof(
+    block_number: u64,
+    incremental: u64,
+    elements: &[Vec<u8>],
+    trie: &Trie,
+) -> Vec<(u64, u64, String, Vec<String>)> {
+    (0..elements.len())
+        .step_by(incremental.max(1) as usize)
+        .map(|index| {
+            let index = index as u64;
+            let proof = trie
+                .proof(&alloy_rlp::encode(index))
                 .into_iter()
-                .map(|x| Bytes::from(x).to_string())
-                .collect::<Vec<_>>();
-            let consensus_tx_receipt = tx_receipts[tx_receipt_index].clone();
-            let rlp = Bytes::from(consensus_tx_receipt.rlp_encode()).to_string();
-            tx_receipt_with_proof.push((target_block, target_tx_receipt_index, rlp, proof));
+                .map(|node| Bytes::from(node).to_string())
+                .collect();
+            let rlp = Bytes::from(elements[index as usize].clone()).to_string();
+            (block_number, index, rlp, proof)
+        })
+        .collect()
+}
+
+/// Check that a transactions or receipts trie built from the RPC block body has the root
+/// committed in the header, and that every element proof verifies against that root.
+fn verify_trie_proofs(
+    trie: &str,
+    block_number: u64,
+    header_root: B256,
+    trie_root: B256,
+    elements_with_proof: &[(u64, u64, String, Vec<String>)],
+) -> Result<()> {
+    if trie_root != header_root {
+        bail!(
+            "The {} trie of block {} built from RPC has root {}, but the header commits to {}",
+            trie,
+            block_number,
+            trie_root,
+            header_root
+        );
+    }
+
+    for (_, index, rlp, proof) in elements_with_proof {
+        let key = alloy_rlp::encode(index);
+        let value = verify_hex_proof(header_root, &key, proof).with_context(|| {
+            format!(
+                "Invalid proof of {} {} in block {}",
+                trie, index, block_number
+            )
+        })?;
+        let expected_value = hex::decode(rlp)?;
+        if value.as_deref() != Some(expected_value.as_slice()) {
+            bail!(
+                "Proof of {} {} in block {} doesn't prove the returned value",
+                trie,
+                index,
+                block_number
+            );
         }
+    }
+    Ok(())
+}
 
-        Ok(tx_receipt_with_proof)
+/// Split a block range into inclusive pages of at most `page_size` blocks
+fn indexer_pages(start_block: u64, end_block: u64, page_size: u64) -> Vec<(u64, u64)> {
+    let page_size = page_size.max(1);
+    (start_block..=end_block)
+        .step_by(page_size as usize)
+        .map(|from_block| (from_block, end_block.min(from_block + page_size - 1)))
+        .collect()
+}
+
+/// Merge the MMRs returned by the indexer into one [`StoredHeaders`] map.
+///
+/// Every header keeps the id of the MMR it is proven against. When several MMRs contain
+/// the same block, the first one returned is used. Only the metadata of the used MMRs
+/// is returned, sorted by id.
+fn merge_mmrs_from_indexer(
+    start_block: u64,
+    end_block: u64,
+    mmrs: Vec<(MMRMetaFromNewIndexer, HashMap<u64, MMRProofFromNewIndexer>)>,
+) -> Result<(StoredHeaders, Vec<MMRMeta>)> {
+    let mut blocks_map: StoredHeaders = HashMap::new();
+    let mut mmr_metas: Vec<MMRMeta> = vec![];
+
+    for (meta, proofs) in mmrs {
+        let mut is_used = false;
+        for (block_number, proof) in proofs {
+            if blocks_map.contains_key(&block_number) {
+                continue;
+            }
+            blocks_map.insert(
+                block_number,
+                (
+                    proof.rlp_block_header.value,
+                    proof.siblings_hashes,
+                    proof.element_index,
+                    meta.mmr_id,
+                ),
+            );
+            is_used = true;
+        }
+        if is_used {
+            mmr_metas.push(MMRMeta {
+                id: meta.mmr_id,
+                root: meta.mmr_root,
+                size: meta.mmr_size,
+                peaks: meta.mmr_peaks,
+            });
+        }
+    }
+
+    if let Some(missing_block) =
+        (start_block..=end_block).find(|block| !blocks_map.contains_key(block))
+    {
+        bail!(
+            "No MMR proof found for block {} in range {} - {}",
+            missing_block,
+            start_block,
+            end_block
+        );
+    }
+    mmr_metas.sort_by_key(|mmr_meta| mmr_meta.id);
+
+    Ok((blocks_map, mmr_metas))
+}
+
+/// Verify that every header hashes to a leaf of its MMR, and that the MMR root commits to
+/// its peaks, so that inconsistent indexer data is rejected before it reaches the program.
+fn verify_header_proofs(
+    hashing_function: HashingFunction,
+    headers: &StoredHeaders,
+    mmr_metas: &[MMRMeta],
+) -> Result<()> {
+    let mut peaks_by_mmr = HashMap::new();
+    for mmr_meta in mmr_metas {
+        let peaks = mmr_meta
+            .peaks
+            .iter()
+            .map(|peak| parse_node_hash(peak))
+            .collect::<Result<Vec<_>>>()?;
+        let root = root_from_peaks(hashing_function, mmr_meta.size, &peaks)?;
+        if root != parse_node_hash(&mmr_meta.root)? {
+            bail!(
+                "Root {} of MMR {} doesn't match its size {} and peaks",
+                mmr_meta.root,
+                mmr_meta.id,
+                mmr_meta.size
+            );
+        }
+        peaks_by_mmr.insert(mmr_meta.id, (mmr_meta.size, peaks));
     }
+
+    for (block_number, (rlp, mmr_path, element_index, mmr_id)) in headers {
+        let Some((size, peaks)) = peaks_by_mmr.get(mmr_id) else {
+            bail!(
+                "Header of block {} is proven against unknown MMR {}",
+                block_number,
+                mmr_id
+            );
+        };
+        let rlp = hex::decode(rlp)
+            .map_err(|e| anyhow!("Invalid RLP of header of block {}: {}", block_number, e))?;
+        let siblings = mmr_path
+            .iter()
+            .map(|sibling| parse_node_hash(sibling))
+            .collect::<Result<Vec<_>>>()?;
+        let is_valid = verify_proof(
+            hashing_function,
+            *element_index,
+            hashing_function.hash_header(&rlp),
+            &siblings,
+            *size,
+            peaks,
+        )?;
+        if !is_valid {
+            bail!(
+                "MMR proof of header of block {} doesn't verify against MMR {} (leaf index {}, size {})",
+                block_number,
+                mmr_id,
+                element_index,
+                size
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Resolve a time range to the blocks up to `head` inside it, block timestamps being increasing
+async fn resolve_blocks_in_time_range<F, Fut>(
+    time_range: TimeRange,
+    head: u64,
+    timestamp_of: &F,
+) -> Result<ResolvedTimeRange>
+where
+    F: Fn(u64) -> Fut,
+    Fut: Future<Output = Result<u64>>,
+{
+    let first_block =
+        first_block_at_or_after(0, head + 1, time_range.start_timestamp, timestamp_of).await?;
+    let after_last_block = first_block_at_or_after(
+        first_block,
+        head + 1,
+        time_range.end_timestamp.saturating_add(1),
+        timestamp_of,
+    )
+    .await?;
+    if first_block == after_last_block {
+        bail!(
+            "No block in the time range from {} to {}",
+            time_range.start_timestamp,
+            time_range.end_timestamp
+        );
+    }
+    let last_block = after_last_block - 1;
+    Ok(ResolvedTimeRange {
+        start_timestamp: time_range.start_timestamp,
+        end_timestamp: time_range.end_timestamp,
+        first_block,
+        first_block_timestamp: timestamp_of(first_block).await?,
+        last_block,
+        last_block_timestamp: timestamp_of(last_block).await?,
+    })
+}
+
+/// First block of `low..high` with a timestamp of at least `timestamp`, `high` if there is none
+async fn first_block_at_or_after<F, Fut>(
+    mut low: u64,
+    mut high: u64,
+    timestamp: u64,
+    timestamp_of: &F,
+) -> Result<u64>
+where
+    F: Fn(u64) -> Fut,
+    Fut: Future<Output = Result<u64>>,
+{
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if timestamp_of(middle).await? >= timestamp {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    Ok(low)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use alloy_primitives::{hex, keccak256};
+    use hdp_primitives::{
+        block::header::RlpBlockHeader,
+        mmr::{leaf_index_to_element_index, Mmr},
+    };
 
     fn rlp_string_to_block_hash(rlp_string: &str) -> String {
         keccak256(hex::decode(rlp_string).unwrap()).to_string()
     }
 
+    fn indexer_mmr(
+        mmr_id: u64,
+        block_numbers: &[u64],
+    ) -> (MMRMetaFromNewIndexer, HashMap<u64, MMRProofFromNewIndexer>) {
+        let meta = MMRMetaFromNewIndexer {
+            mmr_id,
+            mmr_peaks: vec![],
+            mmr_root: format!("0x{}", mmr_id),
+            mmr_size: 0,
+        };
+        let proofs = block_numbers
+            .iter()
+            .map(|block_number| {
+                let proof = MMRProofFromNewIndexer {
+                    block_number: *block_number,
+                    element_hash: "0x0".to_string(),
+                    element_index: *block_number,
+                    rlp_block_header: RlpBlockHeader {
+                        value: "".to_string(),
+                    },
+                    siblings_hashes: vec![],
+                };
+                (*block_number, proof)
+            })
+            .collect();
+        (meta, proofs)
+    }
+
+    #[test]
+    fn test_merge_mmrs_from_indexer() {
+        let mmrs = vec![
+            indexer_mmr(7, &[12, 13]),
+            indexer_mmr(3, &[10, 11, 12]),
+            // every block is already proven by another MMR
+            indexer_mmr(9, &[10, 13]),
+        ];
+        let (headers, mmr_metas) = merge_mmrs_from_indexer(10, 13, mmrs).unwrap();
+        assert_eq!(headers.len(), 4);
+        assert_eq!(headers.get(&10).unwrap().3, 3);
+        assert_eq!(headers.get(&12).unwrap().3, 7);
+        assert_eq!(
+            mmr_metas.iter().map(|meta| meta.id).collect::<Vec<_>>(),
+            vec![3, 7]
+        );
+
+        let error = merge_mmrs_from_indexer(10, 14, vec![indexer_mmr(3, &[10, 11, 12, 13])])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("No MMR proof found for block 14"));
+    }
+
+    #[test]
+    fn test_verify_header_proofs() {
+        for hashing_function in [HashingFunction::Keccak, HashingFunction::Poseidon] {
+            let mut mmr = Mmr::new(hashing_function);
+            let rlps = (0..5u8).map(|i| hex::encode([i; 40])).collect::<Vec<_>>();
+            for rlp in &rlps {
+                mmr.append(hashing_function.hash_header(&hex::decode(rlp).unwrap()))
+                    .unwrap();
+            }
+            let mut headers: StoredHeaders = rlps
+                .iter()
+                .enumerate()
+                .map(|(block_number, rlp)| {
+                    let element_index = leaf_index_to_element_index(block_number as u64);
+                    let mmr_path = mmr
+                        .proof(element_index)
+                        .unwrap()
+                        .iter()
+                        .map(|sibling| sibling.to_string())
+                        .collect();
+                    (
+                        block_number as u64,
+                        (rlp.clone(), mmr_path, element_index, 4),
+                    )
+                })
+                .collect();
+            let mut mmr_meta = MMRMeta {
+                id: 4,
+                root: mmr.root().unwrap().to_string(),
+                size: mmr.size(),
+                peaks: mmr.peaks().iter().map(|peak| peak.to_string()).collect(),
+            };
+            verify_header_proofs(hashing_function, &headers, &[mmr_meta.clone()]).unwrap();
+
+            // header of another block
+            headers.get_mut(&2).unwrap().0 = rlps[3].clone();
+            let error = verify_header_proofs(hashing_function, &headers, &[mmr_meta.clone()])
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains("header of block 2"));
+            headers.get_mut(&2).unwrap().0 = rlps[2].clone();
+
+            mmr_meta.size += 1;
+            let error = verify_header_proofs(hashing_function, &headers, &[mmr_meta.clone()])
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains("Root"));
+            assert!(verify_header_proofs(hashing_function, &headers, &[]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_verify_header_proofs_of_example() {
+        let output: serde_json::Value = serde_json::from_str(include_str!(
+            "../../../../example/header/sum_number/output.json"
+        ))
+        .unwrap();
+        let mmr_metas: Vec<MMRMeta> = serde_json::from_value(output["mmr_metas"].clone()).unwrap();
+        let headers: StoredHeaders = output["headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(i, header)| {
+                let rlp = header["rlp"].as_str().unwrap().to_string();
+                let mmr_path = serde_json::from_value(header["proof"]["mmr_path"].clone()).unwrap();
+                let leaf_idx = header["proof"]["leaf_idx"].as_u64().unwrap();
+                let mmr_id = header["proof"]["mmr_id"].as_u64().unwrap();
+                (i as u64, (rlp, mmr_path, leaf_idx, mmr_id))
+            })
+            .collect();
+        verify_header_proofs(HashingFunction::Poseidon, &headers, &mmr_metas).unwrap();
+    }
+
+    #[test]
+    fn test_verify_trie_proofs() {
+        // trie of a single transaction at index 0, whose key is rlp(0) = 0x80
+        let tx = vec![0xab; 40];
+        let mut leaf_items = vec![];
+        alloy_rlp::Encodable::encode(&[0x20u8, 0x80].as_slice(), &mut leaf_items);
+        alloy_rlp::Encodable::encode(&tx.as_slice(), &mut leaf_items);
+        let mut leaf = vec![];
+        alloy_rlp::Header {
+            list: true,
+            payload_length: leaf_items.len(),
+        }
+        .encode(&mut leaf);
+        leaf.extend(leaf_items);
+        let root = keccak256(&leaf);
+
+        let elements = vec![(
+            10,
+            0,
+            Bytes::from(tx.clone()).to_string(),
+            vec![Bytes::from(leaf).to_string()],
+        )];
+        verify_trie_proofs("transactions", 10, root, root, &elements).unwrap();
+
+        let error = verify_trie_proofs("transactions", 10, B256::ZERO, root, &elements)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("header commits to"));
+
+        let mut tampered = elements.clone();
+        tampered[0].2 = Bytes::from(vec![0xac; 40]).to_string();
+        assert!(verify_trie_proofs("transactions", 10, root, root, &tampered).is_err());
+        let mut other_index = elements;
+        other_index[0].1 = 1;
+        assert!(verify_trie_proofs("transactions", 10, root, root, &other_index).is_err());
+    }
+
+    #[test]
+    fn test_indexer_pages() {
+        assert_eq!(
+            indexer_pages(10, 34, 10),
+            vec![(10, 19), (20, 29), (30, 34)]
+        );
+        assert_eq!(indexer_pages(10, 19, 10), vec![(10, 19)]);
+        assert_eq!(indexer_pages(5, 5, 1000), vec![(5, 5)]);
+        assert_eq!(indexer_pages(0, 100_000, 1000).len(), 101);
+    }
+
     // Non-paid personal alchemy endpoint
     const SEPOLIA_RPC_URL: &str =
         "https://eth-sepolia.g.alchemy.com/v2/xar76cftwEtqTBWdF4ZFy9n8FLHAETDv";
 
     #[tokio::test]
     async fn test_provider_get_rlp_header() {
-        let mut provider = AbstractProvider::new(SEPOLIA_RPC_URL, 11155111);
-        let rlp_header = provider.get_rlp_header(0).await;
+        let provider = AbstractProvider::new(SEPOLIA_RPC_URL.parse().unwrap(), 11155111).unwrap();
+        let rlp_header = provider.get_rlp_header(0).await.unwrap();
         let block_hash = rlp_string_to_block_hash(&rlp_header);
         assert_eq!(
             block_hash,
             "0x25a5cc106eea7138acab33231d7160d69cb777ee0c2c553fcddf5138993e6dd9"
         );
-        let rlp_header = provider.get_rlp_header(5521772).await;
+        let rlp_header = provider.get_rlp_header(5521772).await.unwrap();
         let block_hash = rlp_string_to_block_hash(&rlp_header);
         assert_eq!(
             block_hash,
             "0xe72515bc74912f67912a64a458e6f2cd2742f8dfe0666e985749483dab0b7b9a"
         );
-        let rlp_header = provider.get_rlp_header(487680).await;
+        let rlp_header = provider.get_rlp_header(487680).await.unwrap();
         let block_hash = rlp_string_to_block_hash(&rlp_header);
         assert_eq!(
             block_hash,
@@ -632,9 +1397,17 @@ mod tests {
 
     #[tokio::test]
     async fn get_block_range_from_nonce_range_non_constant() {
-        let provider = AbstractProvider::new(SEPOLIA_RPC_URL, 11155111);
+        let provider = AbstractProvider::new(SEPOLIA_RPC_URL.parse().unwrap(), 11155111).unwrap();
+        let transactions_root = Header::from(
+            &provider
+                .rpc_provider
+                .get_block_by_number(5530433)
+                .await
+                .unwrap(),
+        )
+        .transactions_root;
         let block_range = provider
-            .get_tx_with_proof_from_block(5530433, 1)
+            .get_tx_with_proof_from_block(5530433, 1, transactions_root)
             .await
             .unwrap();
 
@@ -642,10 +1415,42 @@ mod tests {
         assert_eq!(block_range[0].2,"0xf874830199258504a817c8008304ce78943d803617b9607357009fedadf646ad341e246adc88016345785d8a0000808401546d72a088de6c88f53048817fd31c44683fd796a2f529ced61950c610904a809b6342e4a0578d92174df2e1dba4fe88d4a33e868e89392210fad9bc02104b40fcb37792ec");
 
         let block_range = provider
-            .get_tx_with_proof_from_block(5530433, 3)
+            .get_tx_with_proof_from_block(5530433, 3, transactions_root)
             .await
             .unwrap();
 
         assert_eq!(block_range.len(), 40);
     }
+
+    #[tokio::test]
+    async fn test_resolve_blocks_in_time_range() {
+        // blocks every 12 seconds, with a missed slot after block 4
+        let timestamps = [100, 112, 124, 136, 148, 172, 184, 196];
+        let timestamp_of = |block_number: u64| async move { Ok(timestamps[block_number as usize]) };
+        let resolve = |start, end| async move {
+            resolve_blocks_in_time_range(TimeRange::new(start, end).unwrap(), 7, &timestamp_of)
+                .await
+        };
+
+        let resolved = resolve(110, 172).await.unwrap();
+        assert_eq!(
+            resolved,
+            ResolvedTimeRange {
+                start_timestamp: 110,
+                end_timestamp: 172,
+                first_block: 1,
+                first_block_timestamp: 112,
+                last_block: 5,
+                last_block_timestamp: 172,
+            }
+        );
+        let resolved = resolve(0, 1000).await.unwrap();
+        assert_eq!((resolved.first_block, resolved.last_block), (0, 7));
+        let resolved = resolve(148, 148).await.unwrap();
+        assert_eq!((resolved.first_block, resolved.last_block), (4, 4));
+
+        assert!(resolve(150, 170).await.is_err());
+        assert!(resolve(0, 99).await.is_err());
+        assert!(resolve(200, 300).await.is_err());
+    }
 }
@@ -0,0 +1,397 @@
+//! [`MultiRpcProvider`] spreads RPC requests over several endpoints.
+//!
+//! With a quorum of 1, endpoints are tried in order and the first successful response wins.
+//! With a quorum of N, every endpoint is queried and at least N of them have to agree
+//! on the hash of the header (or on the proof and trie roots) before the data is accepted.
+
+use std::{fmt::Display, future::Future, str::FromStr};
+
+use alloy_primitives::{keccak256, Bytes};
+use anyhow::{anyhow, bail, Result};
+use futures::future::join_all;
+use tracing::warn;
+
+use hdp_primitives::{
+    block::{
+        account::AccountFromRpc,
+        header::{BlockHeaderFromRpc, Header},
+    },
+    mpt::Trie,
+};
+
+use super::{
+    config::{Endpoint, ProviderConfig},
+    rpc::RpcProvider,
+};
+
+#[derive(Debug, Clone)]
+pub struct MultiRpcProvider {
+    endpoints: Vec<RpcProvider>,
+    /// Number of endpoints that have to agree on a response
+    quorum: usize,
+}
+
+impl MultiRpcProvider {
+    pub fn new(endpoints: Vec<Endpoint>, chain_id: u64, config: &ProviderConfig) -> Result<Self> {
+        Ok(Self {
+            endpoints: endpoints
+                .into_iter()
+                .map(|endpoint| RpcProvider::new_with_config(endpoint, chain_id, config))
+                .collect::<Result<_>>()?,
+            quorum: config.quorum.max(1),
+        })
+    }
+
+    pub async fn get_block_by_number(&self, block_number: u64) -> Result<BlockHeaderFromRpc> {
+        self.request(
+            &format!("eth_getBlockByNumber({})", block_number),
+            |endpoint| async move { endpoint.get_block_by_number(block_number).await },
+            header_fingerprint,
+        )
+        .await
+    }
+
+    /// Number of the block at a block tag, e.g. `latest` or `finalized`.
+    ///
+    /// Endpoints can be a few blocks apart at the head of the chain, so they are not
+    /// cross-checked: with a quorum, the least advanced endpoint is used once at least
+    /// `quorum` endpoints answered.
+    pub async fn get_block_number_by_tag(&self, block_tag: &str) -> Result<u64> {
+        if self.quorum == 1 {
+            let header = self
+                .request(
+                    &format!("eth_getBlockByNumber({})", block_tag),
+                    |endpoint| async move { endpoint.get_block_by_tag(block_tag).await },
+                    header_fingerprint,
+                )
+                .await?;
+            return block_number(&header);
+        }
+
+        let responses = join_all(self.endpoints.iter().map(|endpoint| async move {
+            (
+                endpoint.url.as_str(),
+                endpoint.get_block_by_tag(block_tag).await,
+            )
+        }))
+        .await;
+        let mut block_numbers = vec![];
+        let mut errors = vec![];
+        for (url, response) in responses {
+            match response {
+                Ok(header) => block_numbers.push(block_number(&header)?),
+                Err(e) => errors.push(format!("{}: {}", url, e)),
+            }
+        }
+        if block_numbers.len() < self.quorum {
+            bail!(
+                "eth_getBlockByNumber({}) succeeded on {} endpoints, quorum is {}: [{}]",
+                block_tag,
+                block_numbers.len(),
+                self.quorum,
+                errors.join(", ")
+            );
+        }
+        block_numbers.sort_unstable();
+        Ok(block_numbers[0])
+    }
+
+    pub async fn get_proof(
+        &self,
+        block_number: u64,
+        address: &str,
+        storage_keys: Option<Vec<String>>,
+    ) -> Result<AccountFromRpc> {
+        self.request(
+            &format!("eth_getProof({}, {})", address, block_number),
+            |endpoint| {
+                let storage_keys = storage_keys.clone();
+                async move {
+                    endpoint
+                        .get_proof(block_number, address, storage_keys)
+                        .await
+                }
+            },
+            proof_fingerprint,
+        )
+        .await
+    }
+
+    /// Transactions of a block, encoded as in its transactions trie
+    pub async fn get_block_transactions(&self, block_number: u64) -> Result<Vec<Vec<u8>>> {
+        self.request(
+            &format!("eth_getBlockByNumber({}, true)", block_number),
+            |endpoint| async move { endpoint.get_block_transactions(block_number).await },
+            |values| trie_fingerprint(values),
+        )
+        .await
+    }
+
+    /// Receipts of the transactions of a block, encoded as in its receipts trie
+    pub async fn get_block_receipts(&self, block_number: u64) -> Result<Vec<Vec<u8>>> {
+        self.request(
+            &format!("eth_getBlockReceipts({})", block_number),
+            |endpoint| async move { endpoint.get_block_receipts(block_number).await },
+            |values| trie_fingerprint(values),
+        )
+        .await
+    }
+
+    /// Send the request to the endpoints, either failing over or cross-checking
+    /// the responses depending on the quorum.
+    async fn request<T, K, F, Fut>(
+        &self,
+        request_name: &str,
+        send: F,
+        fingerprint: impl Fn(&T) -> K,
+    ) -> Result<T>
+    where
+        K: PartialEq + Display,
+        F: Fn(RpcProvider) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if self.endpoints.is_empty() {
+            bail!("No RPC endpoint configured");
+        }
+        if self.quorum > self.endpoints.len() {
+            bail!(
+                "Quorum of {} can't be reached with {} RPC endpoints",
+                self.quorum,
+                self.endpoints.len()
+            );
+        }
+
+        if self.quorum == 1 {
+            let mut errors = vec![];
+            for endpoint in &self.endpoints {
+                let url = &endpoint.url;
+                match send(endpoint.clone()).await {
+                    Ok(response) => return Ok(response),
+                    Err(e) => {
+                        if self.endpoints.len() > 1 {
+                            warn!("{} failed on {}: {}", request_name, url, e);
+                        }
+                        errors.push(format!("{}: {}", url, e));
+                    }
+                }
+            }
+            bail!(
+                "{} failed on every endpoint: [{}]",
+                request_name,
+                errors.join(", ")
+            );
+        }
+
+        let responses = join_all(self.endpoints.iter().map(|endpoint| {
+            let url = endpoint.url.as_str();
+            let response = send(endpoint.clone());
+            async move { (url, response.await) }
+        }))
+        .await;
+
+        select_quorum(request_name, responses, self.quorum, fingerprint)
+    }
+}
+
+fn block_number(header: &BlockHeaderFromRpc) -> Result<u64> {
+    u64::from_str_radix(header.number.trim_start_matches("0x"), 16)
+        .map_err(|e| anyhow!("Invalid block number {}: {}", header.number, e))
+}
+
+/// Group the responses by fingerprint and return the one at least `quorum` endpoints agree on.
+///
+/// Disagreeing endpoints are reported with their url and response, as a warning when the
+/// quorum is still reached and as an error otherwise.
+fn select_quorum<T, K: PartialEq + Display>(
+    request_name: &str,
+    responses: Vec<(&str, Result<T>)>,
+    quorum: usize,
+    fingerprint: impl Fn(&T) -> K,
+) -> Result<T> {
+    // (fingerprint, agreeing endpoints, response)
+    let mut groups: Vec<(K, Vec<&str>, T)> = vec![];
+    let mut errors = vec![];
+
+    for (url, response) in responses {
+        match response {
+            Ok(response) => {
+                let key = fingerprint(&response);
+                match groups
+                    .iter_mut()
+                    .find(|(group_key, _, _)| *group_key == key)
+                {
+                    Some((_, urls, _)) => urls.push(url),
+                    None => groups.push((key, vec![url], response)),
+                }
+            }
+            Err(e) => errors.push(format!("{}: {}", url, e)),
+        }
+    }
+
+    let report = groups
+        .iter()
+        .map(|(key, urls, _)| format!("{} from [{}]", key, urls.join(", ")))
+        .chain(errors.iter().cloned())
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    // the first largest group wins, so ties are broken by endpoint order
+    let best_group = groups
+        .iter()
+        .enumerate()
+        .fold(
+            None,
+            |best: Option<(usize, usize)>, (index, (_, urls, _))| match best {
+                Some((_, best_len)) if best_len >= urls.len() => best,
+                _ => Some((index, urls.len())),
+            },
+        )
+        .map(|(index, _)| index);
+
+    match best_group {
+        Some(index) if groups[index].1.len() >= quorum => {
+            if groups.len() > 1 || !errors.is_empty() {
+                warn!(
+                    "{}: endpoints disagree, quorum of {} reached: {}",
+                    request_name, quorum, report
+                );
+            }
+            Ok(groups.swap_remove(index).2)
+        }
+        _ => bail!(
+            "{}: quorum of {} not reached: {}",
+            request_name,
+            quorum,
+            report
+        ),
+    }
+}
+
+/// Identify a block header by the hash of its fields, rather than by the hash the endpoint
+/// reports next to them
+fn header_fingerprint(header: &BlockHeaderFromRpc) -> String {
+    format!("header hash {}", Header::from(header).hash_slow())
+}
+
+/// Identify the transactions or receipts of a block by the root of their trie
+fn trie_fingerprint(values: &[Vec<u8>]) -> String {
+    format!(
+        "trie root {}",
+        Trie::from_indexed_values(values.to_vec()).root()
+    )
+}
+
+/// Identify a `eth_getProof` response by the state root its account proof is anchored to,
+/// the storage root of the account and the proven storage values.
+fn proof_fingerprint(account: &AccountFromRpc) -> String {
+    let state_root = account
+        .account_proof
+        .first()
+        .and_then(|node| Bytes::from_str(node).ok())
+        .map(|node| keccak256(node).to_string())
+        .unwrap_or_default();
+    let storage_values = account
+        .storage_proof
+        .iter()
+        .map(|storage| format!("{}={}", storage.key, storage.value))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(
+        "state root {}, storage root {}, storage [{}]",
+        state_root, account.storage_hash, storage_values
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::rpc::check_block_hash;
+    use anyhow::anyhow;
+
+    fn identity(value: &u64) -> u64 {
+        *value
+    }
+
+    #[test]
+    fn test_select_quorum_reached() {
+        let responses = vec![
+            ("http://a", Ok(1)),
+            ("http://b", Ok(2)),
+            ("http://c", Ok(2)),
+            ("http://d", Err(anyhow!("timeout"))),
+        ];
+        let result = select_quorum("test", responses, 2, identity).unwrap();
+        assert_eq!(result, 2);
+    }
+
+    #[test]
+    fn test_select_quorum_not_reached() {
+        let responses = vec![
+            ("http://a", Ok(1)),
+            ("http://b", Ok(2)),
+            ("http://c", Err(anyhow!("timeout"))),
+        ];
+        let error = select_quorum("test", responses, 2, identity)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("quorum of 2 not reached"));
+        assert!(error.contains("1 from [http://a]"));
+        assert!(error.contains("2 from [http://b]"));
+        assert!(error.contains("http://c: timeout"));
+    }
+
+    #[test]
+    fn test_select_quorum_of_headers() {
+        let header: BlockHeaderFromRpc = serde_json::from_value(serde_json::json!({
+            "difficulty": "0x0",
+            "extraData": "0x",
+            "gasLimit": "0x1c9c380",
+            "gasUsed": "0x6f9a8e",
+            "hash": "0x0",
+            "logsBloom": format!("0x{}", "00".repeat(256)),
+            "miner": "0xb636a68f834b4d75af9edc5fb0138bb4758ed293",
+            "mixHash": format!("0x{}", "11".repeat(32)),
+            "nonce": "0x0",
+            "number": "0x9eb0f6",
+            "parentHash": format!("0x{}", "22".repeat(32)),
+            "receiptsRoot": format!("0x{}", "33".repeat(32)),
+            "sha3Uncles": format!("0x{}", "44".repeat(32)),
+            "size": "0x0",
+            "stateRoot": format!("0x{}", "55".repeat(32)),
+            "timestamp": "0x65aa8780",
+            "totalDifficulty": "0x0",
+            "transactionsRoot": format!("0x{}", "66".repeat(32)),
+        }))
+        .unwrap();
+        let header = BlockHeaderFromRpc {
+            hash: Header::from(&header).get_block_hash(),
+            ..header
+        };
+        assert!(check_block_hash(&header).is_ok());
+        // a faulty endpoint reports the right hash next to a tampered field
+        let tampered = BlockHeaderFromRpc {
+            gas_used: "0x1".to_string(),
+            ..header.clone()
+        };
+        assert!(check_block_hash(&tampered).is_err());
+
+        let responses = vec![("http://a", Ok(tampered)), ("http://b", Ok(header.clone()))];
+        let error = select_quorum("test", responses, 2, header_fingerprint)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("quorum of 2 not reached"));
+        let responses = vec![
+            ("http://a", Ok(header.clone())),
+            ("http://b", Ok(header.clone())),
+        ];
+        let result = select_quorum("test", responses, 2, header_fingerprint).unwrap();
+        assert_eq!(result.gas_used, header.gas_used);
+    }
+
+    #[test]
+    fn test_select_quorum_tie_prefers_first_endpoint() {
+        let responses = vec![("http://a", Ok(1)), ("http://b", Ok(2))];
+        let result = select_quorum("test", responses, 1, identity).unwrap();
+        assert_eq!(result, 1);
+    }
+}
@@ -0,0 +1,15 @@
+//! Progress of the fetches of a provider, reported to an observer.
+
+use std::sync::Arc;
+
+/// Progress of the fetch of a block range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchProgress {
+    /// Headers fetched with their MMR proofs, out of the headers of the range
+    Headers { fetched: u64, total: u64 },
+    /// Blocks of the range whose account or storage proofs are fetched
+    Proofs { fetched: u64, total: u64 },
+}
+
+/// Called with the progress of every fetch of the provider
+pub type FetchObserver = Arc<dyn Fn(FetchProgress) + Send + Sync>;
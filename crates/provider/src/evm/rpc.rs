@@ -1,72 +1,243 @@
-use std::{collections::HashMap, vec};
+use std::{collections::HashMap, fs, str::FromStr, sync::Arc, time::Duration, vec};
 
-use anyhow::{anyhow, bail, Result};
-use reqwest::{header, Client};
+use alloy_primitives::{keccak256, B256};
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Certificate, Client, RequestBuilder, Url,
+};
 use serde_json::{from_value, json, Value};
+use tracing::warn;
+
+use hdp_primitives::{
+    block::{
+        account::AccountFromRpc,
+        header::{
+            BlockHeaderFromRpc, Header, MMRFromNewIndexer, MMRMetaFromNewIndexer,
+            MMRProofFromNewIndexer,
+        },
+        tx::{TransactionFromRpc, TransactionReceiptFromRpc},
+    },
+    mmr::HashingFunction,
+};
 
-use hdp_primitives::block::{
-    account::AccountFromRpc,
-    header::{
-        BlockHeaderFromRpc, MMRFromNewIndexer, MMRMetaFromNewIndexer, MMRProofFromNewIndexer,
+use super::{
+    config::{Endpoint, ProviderConfig, TlsConfig},
+    transport::{
+        is_retryable_error, is_retryable_rpc_error, is_retryable_status, RateLimiter, RetryPolicy,
     },
 };
 
 #[derive(Debug, Clone)]
 pub struct RpcProvider {
+    /// Sends the headers of the endpoint with every request
     client: Client,
-    pub url: &'static str,
+    pub url: Url,
     chain_id: u64,
+    retry_policy: RetryPolicy,
+    /// Shared between clones, so that the limit applies to the endpoint
+    rate_limiter: Option<Arc<RateLimiter>>,
+    request_timeout: Duration,
 }
 
 impl RpcProvider {
-    pub fn new(rpc_url: &'static str, chain_id: u64) -> Self {
-        Self {
-            client: Client::new(),
-            url: rpc_url,
+    pub fn new_with_config(
+        endpoint: Endpoint,
+        chain_id: u64,
+        config: &ProviderConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            client: build_client(&endpoint, &config.tls)
+                .with_context(|| format!("Invalid endpoint {}", endpoint.url))?,
+            url: endpoint.url,
             chain_id,
+            retry_policy: RetryPolicy::from(config),
+            rate_limiter: config
+                .max_requests_per_second
+                .map(|limit| Arc::new(RateLimiter::new(limit))),
+            request_timeout: config.request_timeout,
+        })
+    }
+
+    /// Send the request built by `build_request`, retrying transient failures
+    /// with exponential backoff, and parse the response body as JSON.
+    ///
+    /// A JSON-RPC `error` member in a successful response is a failure as well,
+    /// retried when it reports rate limiting or a timeout.
+    ///
+    /// `request_name` is only used in error messages.
+    async fn send_with_retry<F>(&self, request_name: &str, build_request: F) -> Result<Value>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut attempt = 0;
+        loop {
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.acquire().await;
+            }
+
+            let error = match build_request().timeout(self.request_timeout).send().await {
+                Ok(response) if response.status().is_success() => {
+                    // Parse the response body as JSON
+                    let body: Value = response
+                        .json()
+                        .await
+                        .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+                    let Some(rpc_error) = body.get("error").filter(|error| !error.is_null()) else {
+                        return Ok(body);
+                    };
+                    let code = rpc_error["code"].as_i64().unwrap_or_default();
+                    let message = rpc_error["message"]
+                        .as_str()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| rpc_error.to_string());
+                    if !is_retryable_rpc_error(code, &message) {
+                        bail!("{} failed with error {}: {}", request_name, code, message);
+                    }
+                    anyhow!("{} failed with error {}: {}", request_name, code, message)
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    if !is_retryable_status(status) {
+                        bail!("{} failed with status: {}", request_name, status);
+                    }
+                    anyhow!("{} failed with status: {}", request_name, status)
+                }
+                Err(e) => {
+                    if !is_retryable_error(&e) {
+                        bail!("Failed to send request: {}", e);
+                    }
+                    anyhow!("Failed to send request: {}", e)
+                }
+            };
+
+            if attempt >= self.retry_policy.max_retries {
+                bail!("{} (gave up after {} retries)", error, attempt);
+            }
+            let backoff = self.retry_policy.backoff(attempt);
+            warn!(
+                "{} to {}: {}, retrying in {:?} ({}/{})",
+                request_name,
+                self.url,
+                error,
+                backoff,
+                attempt + 1,
+                self.retry_policy.max_retries
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
         }
     }
 }
 
 impl RpcProvider {
     pub async fn get_block_by_number(&self, block_number: u64) -> Result<BlockHeaderFromRpc> {
+        self.get_block_by_tag(&format!("0x{:x}", block_number))
+            .await
+    }
+
+    /// Fetch the header of the block at a block tag, e.g. `latest` or `finalized`,
+    /// or at a hex encoded block number.
+    pub async fn get_block_by_tag(&self, block_tag: &str) -> Result<BlockHeaderFromRpc> {
         let rpc_request: Value = json!({
             "jsonrpc": "2.0",
             "method": "eth_getBlockByNumber",
-            "params": [format!("0x{:x}", block_number), false],
+            "params": [block_tag, false],
             "id": 1,
         });
 
-        let response = self
-            .client
-            .post(self.url)
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&rpc_request)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to send request: {}", e))?;
-
-        // Check if the response status is success
-        if !response.status().is_success() {
-            return Err(anyhow!(
-                "RPC request `eth_getBlockByNumber` failed with status: {}",
-                response.status()
-            ));
-        }
-
-        // Parse the response body as JSON
-        let rpc_response: Value = response
-            .json()
-            .await
-            .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+        let rpc_response = self
+            .send_with_retry("RPC request `eth_getBlockByNumber`", || {
+                self.client
+                    .post(self.url.clone())
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .json(&rpc_request)
+            })
+            .await?;
         let result = &rpc_response["result"];
+        if result.is_null() {
+            bail!("Block {} not found on {}", block_tag, self.url);
+        }
 
         // Deserialize into EvmBlockHeaderFromRpc
         let block_header_from_rpc: BlockHeaderFromRpc = from_value(result.clone())?;
+        check_block_hash(&block_header_from_rpc)
+            .with_context(|| format!("Invalid block {} from {}", block_tag, self.url))?;
 
         Ok(block_header_from_rpc)
     }
 
+    /// Fetch the transactions of a block, encoded as in its transactions trie.
+    ///
+    /// Every encoding is checked against the hash of its transaction.
+    pub async fn get_block_transactions(&self, block_number: u64) -> Result<Vec<Vec<u8>>> {
+        let rpc_request: Value = json!({
+            "jsonrpc": "2.0",
+            "method": "eth_getBlockByNumber",
+            "params": [format!("0x{:x}", block_number), true],
+            "id": 1,
+        });
+
+        let rpc_response = self
+            .send_with_retry("RPC request `eth_getBlockByNumber`", || {
+                self.client
+                    .post(self.url.clone())
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .json(&rpc_request)
+            })
+            .await?;
+        let transactions = &rpc_response["result"]["transactions"];
+        if transactions.is_null() {
+            bail!("Block {} not found on {}", block_number, self.url);
+        }
+
+        let transactions: Vec<TransactionFromRpc> = from_value(transactions.clone())?;
+        transactions
+            .iter()
+            .map(|transaction| {
+                let encoded = transaction.rlp_encode()?;
+                if keccak256(&encoded) != B256::from_str(&transaction.hash)? {
+                    bail!(
+                        "Transaction {} of block {} from {} doesn't encode to its hash",
+                        transaction.hash,
+                        block_number,
+                        self.url
+                    );
+                }
+                Ok(encoded)
+            })
+            .collect()
+    }
+
+    /// Fetch the receipts of the transactions of a block, encoded as in its receipts trie
+    pub async fn get_block_receipts(&self, block_number: u64) -> Result<Vec<Vec<u8>>> {
+        let rpc_request: Value = json!({
+            "jsonrpc": "2.0",
+            "method": "eth_getBlockReceipts",
+            "params": [format!("0x{:x}", block_number)],
+            "id": 1,
+        });
+
+        let rpc_response = self
+            .send_with_retry("RPC request `eth_getBlockReceipts`", || {
+                self.client
+                    .post(self.url.clone())
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .json(&rpc_request)
+            })
+            .await?;
+        let receipts = &rpc_response["result"];
+        if receipts.is_null() {
+            bail!("Block {} not found on {}", block_number, self.url);
+        }
+
+        let receipts: Vec<TransactionReceiptFromRpc> = from_value(receipts.clone())?;
+        receipts
+            .iter()
+            .map(TransactionReceiptFromRpc::rlp_encode)
+            .collect()
+    }
+
     pub async fn get_proof(
         &self,
         block_number: u64,
@@ -92,64 +263,45 @@ impl RpcProvider {
             "id": 1,
         });
 
-        let response = self
-            .client
-            .post(self.url)
-            .header(header::CONTENT_TYPE, "application/json")
-            .json(&rpc_request)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to send request: {}", e))?;
-
-        // Check if the response status is success
-        if !response.status().is_success() {
-            bail!(
-                "RPC request `eth_getProof` failed with status: {}",
-                response.status()
-            );
-        }
-
-        // Parse the response body as JSON
-        let rpc_response: Value = response
-            .json()
-            .await
-            .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+        let rpc_response = self
+            .send_with_retry("RPC request `eth_getProof`", || {
+                self.client
+                    .post(self.url.clone())
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .json(&rpc_request)
+            })
+            .await?;
         let result = &rpc_response["result"];
 
         let account_from_rpc: AccountFromRpc = from_value(result.clone())?;
 
-        // Error handling for empty proof (no account found)
-        if account_from_rpc.account_proof.is_empty() {
-            bail!(
-                "No account found for address {} in blocknumber {}",
-                address,
-                block_number
-            );
-        }
-
-        // For now we only request for one storage key
-        if !storage_key_param.is_empty() && account_from_rpc.storage_proof[0].proof.is_empty() {
+        // Empty proofs are valid exclusion proofs against an empty trie, they are
+        // verified against the header when the datalake is compiled
+        if account_from_rpc.storage_proof.len() != storage_key_param.len() {
             bail!(
-                "No storage proof found for address {} in blocknumber {}",
+                "Expected {} storage proofs for address {} in blocknumber {}, got {}",
+                storage_key_param.len(),
                 address,
-                block_number
+                block_number,
+                account_from_rpc.storage_proof.len()
             );
         }
 
         Ok(account_from_rpc)
     }
 
-    // TODO: result should not chunked
     pub async fn get_sequencial_headers_and_mmr_from_indexer(
         &self,
         from_block: u64,
         to_block: u64,
-    ) -> Result<(MMRMetaFromNewIndexer, HashMap<u64, MMRProofFromNewIndexer>)> {
+        hashing_function: HashingFunction,
+        contract_type: &str,
+    ) -> Result<Vec<(MMRMetaFromNewIndexer, HashMap<u64, MMRProofFromNewIndexer>)>> {
         let query_params = vec![
             ("deployed_on_chain".to_string(), self.chain_id.to_string()),
             ("accumulates_chain".to_string(), self.chain_id.to_string()),
-            ("hashing_function".to_string(), "poseidon".to_string()),
-            ("contract_type".to_string(), "AGGREGATOR".to_string()),
+            ("hashing_function".to_string(), hashing_function.to_string()),
+            ("contract_type".to_string(), contract_type.to_string()),
             (
                 "from_block_number_inclusive".to_string(),
                 from_block.to_string(),
@@ -164,30 +316,16 @@ impl RpcProvider {
             ("is_pure_rlp".to_string(), "true".to_string()),
         ];
 
-        let url = format!("{}/proofs", &self.url);
+        let url = format!("{}/proofs", self.url.as_str().trim_end_matches('/'));
 
-        let response = self
-            .client
-            .get(url)
-            .header(header::CONTENT_TYPE, "application/json")
-            .query(&query_params)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to send request: {}", e))?;
-
-        // Check if the response status is success
-        if !response.status().is_success() {
-            bail!(
-                "rs-indexer request failed with status: {}",
-                response.status()
-            );
-        }
-
-        // Parse the response body as JSON
-        let rpc_response: Value = response
-            .json()
-            .await
-            .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+        let rpc_response = self
+            .send_with_retry("rs-indexer request", || {
+                self.client
+                    .get(&url)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .query(&query_params)
+            })
+            .await?;
 
         let mmr_from_indexer: MMRFromNewIndexer = from_value(rpc_response)?;
 
@@ -197,24 +335,62 @@ impl RpcProvider {
                 from_block,
                 to_block
             );
-        } else if mmr_from_indexer.data.len() > 1 {
-            bail!(
-                "More than one MMR data found for block numbers: {} - {}",
-                from_block,
-                to_block
-            );
-        } else {
-            // As we are requesting for one tree, we expect only one tree to be returned
-            // sort the proofs by block number
-            // TODO: This sorting should be done in the indexer side
-            let mut mmr_from_indexer_map: HashMap<u64, MMRProofFromNewIndexer> = HashMap::new();
-            for proof in &mmr_from_indexer.data[0].proofs {
-                mmr_from_indexer_map.insert(proof.block_number, proof.clone());
-            }
-
-            Ok((mmr_from_indexer.data[0].meta.clone(), mmr_from_indexer_map))
         }
+
+        // A range can span several trees, each tree comes with the proofs of its blocks
+        // TODO: This sorting should be done in the indexer side
+        Ok(mmr_from_indexer
+            .data
+            .into_iter()
+            .map(|mmr_data| {
+                let proofs = mmr_data
+                    .proofs
+                    .into_iter()
+                    .map(|proof| (proof.block_number, proof))
+                    .collect::<HashMap<u64, MMRProofFromNewIndexer>>();
+                (mmr_data.meta, proofs)
+            })
+            .collect())
+    }
+}
+
+/// Check that the reported block hash is the hash of the returned header fields
+pub(crate) fn check_block_hash(header: &BlockHeaderFromRpc) -> Result<()> {
+    let header_hash = Header::from(header).get_block_hash();
+    if !header_hash.eq_ignore_ascii_case(&header.hash) {
+        bail!(
+            "Block hash {} is not the hash of its header {}",
+            header.hash,
+            header_hash
+        );
+    }
+    Ok(())
+}
+
+/// Build the HTTP client of an endpoint, which sends the endpoint headers with every request
+fn build_client(endpoint: &Endpoint, tls: &TlsConfig) -> Result<Client> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &endpoint.headers {
+        let name =
+            HeaderName::from_str(name).with_context(|| format!("Invalid header name {}", name))?;
+        let mut value = HeaderValue::from_str(value)
+            .with_context(|| format!("Invalid value of header {}", name))?;
+        // keep credentials out of debug logs
+        value.set_sensitive(true);
+        headers.append(name, value);
+    }
+
+    let mut builder = Client::builder()
+        .default_headers(headers)
+        .danger_accept_invalid_certs(tls.accept_invalid_certs);
+    for path in &tls.ca_certificates {
+        let pem = fs::read(path)
+            .with_context(|| format!("Failed to read CA certificate {}", path.display()))?;
+        let certificate = Certificate::from_pem(&pem)
+            .with_context(|| format!("Invalid CA certificate {}", path.display()))?;
+        builder = builder.add_root_certificate(certificate);
     }
+    Ok(builder.build()?)
 }
 
 #[cfg(test)]
@@ -222,21 +398,33 @@ mod tests {
     use std::str::FromStr;
 
     use alloy_primitives::{FixedBytes, U256};
-    use hdp_primitives::block::{account::Account, header::Header};
+    use hdp_primitives::block::account::Account;
 
     use super::*;
 
-    const HERODOTUS_RS_INDEXER_URL: &str = "https://rs-indexer.api.herodotus.cloud/accumulators";
+    use crate::evm::config::{DEFAULT_INDEXER_CONTRACT_TYPE, DEFAULT_INDEXER_URL};
 
     #[tokio::test]
     async fn test_get_sepolia_sequencial_headers_and_mmr_from_indexer() {
-        let rpc_provider = RpcProvider::new(HERODOTUS_RS_INDEXER_URL, 11155111);
-
-        let block_header = rpc_provider
-            .get_sequencial_headers_and_mmr_from_indexer(4952200, 4952229)
+        let rpc_provider = RpcProvider::new_with_config(
+            DEFAULT_INDEXER_URL.parse().unwrap(),
+            11155111,
+            &ProviderConfig::default(),
+        )
+        .unwrap();
+
+        let mmrs = rpc_provider
+            .get_sequencial_headers_and_mmr_from_indexer(
+                4952200,
+                4952229,
+                HashingFunction::Poseidon,
+                DEFAULT_INDEXER_CONTRACT_TYPE,
+            )
             .await
             .unwrap();
 
+        assert_eq!(mmrs.len(), 1);
+        let block_header = &mmrs[0];
         let mmr_meta = &block_header.0;
         assert_eq!(mmr_meta.mmr_id, 2);
         let length = block_header.1.len();
@@ -250,13 +438,25 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_mainnet_sequencial_headers_and_mmr_from_indexer() {
-        let rpc_provider = RpcProvider::new(HERODOTUS_RS_INDEXER_URL, 1);
-
-        let block_header = rpc_provider
-            .get_sequencial_headers_and_mmr_from_indexer(4952200, 4952229)
+        let rpc_provider = RpcProvider::new_with_config(
+            DEFAULT_INDEXER_URL.parse().unwrap(),
+            1,
+            &ProviderConfig::default(),
+        )
+        .unwrap();
+
+        let mmrs = rpc_provider
+            .get_sequencial_headers_and_mmr_from_indexer(
+                4952200,
+                4952229,
+                HashingFunction::Poseidon,
+                DEFAULT_INDEXER_CONTRACT_TYPE,
+            )
             .await
             .unwrap();
 
+        assert_eq!(mmrs.len(), 1);
+        let block_header = &mmrs[0];
         let mmr_meta = &block_header.0;
         assert_eq!(mmr_meta.mmr_id, 5);
         let length = block_header.1.len();
@@ -268,6 +468,122 @@ mod tests {
         assert_eq!(block_4952229.block_number, 4952229);
     }
 
+    #[test]
+    fn test_build_client() {
+        let endpoint = Endpoint::from_str("https://rpc.example.com/v1")
+            .unwrap()
+            .with_header("x-api-key", "key")
+            .with_bearer_auth("token");
+        assert_eq!(
+            endpoint.headers[1],
+            ("Authorization".to_string(), "Bearer token".to_string())
+        );
+        let tls = TlsConfig::default();
+        assert!(build_client(&endpoint, &tls).is_ok());
+
+        let invalid_header = endpoint.clone().with_header("x api key", "key");
+        assert!(build_client(&invalid_header, &tls).is_err());
+        let missing_certificate = TlsConfig {
+            ca_certificates: vec!["missing-ca.pem".into()],
+            accept_invalid_certs: false,
+        };
+        assert!(build_client(&endpoint, &missing_certificate).is_err());
+        assert!(Endpoint::from_str("not a url").is_err());
+    }
+
+    /// Serve the given JSON-RPC response bodies over HTTP 200, one per connection
+    async fn serve_responses(bodies: Vec<String>) -> Url {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for body in bodies {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                // the request is read until its headers end, its body is small enough
+                // to arrive with them
+                let mut request = vec![0; 4096];
+                let mut read = 0;
+                while !request[..read].windows(4).any(|w| w == b"\r\n\r\n") {
+                    read += stream.read(&mut request[read..]).await.unwrap();
+                }
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        url.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_json_rpc_errors() {
+        let config = ProviderConfig {
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let send = |rpc_provider: RpcProvider| async move {
+            rpc_provider
+                .send_with_retry("RPC request `eth_blockNumber`", || {
+                    rpc_provider
+                        .client
+                        .post(rpc_provider.url.clone())
+                        .json(&json!({"jsonrpc": "2.0", "method": "eth_blockNumber", "id": 1}))
+                })
+                .await
+        };
+
+        // rate limiting reported in a successful response is retried
+        let url = serve_responses(vec![
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32005,"message":"limit exceeded"}}"#
+                .to_string(),
+            r#"{"jsonrpc":"2.0","id":1,"result":"0x10"}"#.to_string(),
+        ])
+        .await;
+        let rpc_provider = RpcProvider::new_with_config(url.into(), 1, &config).unwrap();
+        assert_eq!(send(rpc_provider).await.unwrap()["result"], "0x10");
+
+        // other errors fail at once, with their code and message
+        let url = serve_responses(vec![
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32602,"message":"invalid argument 0"}}"#
+                .to_string(),
+        ])
+        .await;
+        let rpc_provider = RpcProvider::new_with_config(url.into(), 1, &config).unwrap();
+        let error = send(rpc_provider).await.unwrap_err().to_string();
+        assert!(error.contains("-32602: invalid argument 0"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_get_block_transactions() {
+        // the signed transaction of the EIP-155 example
+        let block = r#"{"jsonrpc":"2.0","id":1,"result":{"transactions":[{
+            "hash":"0x33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788",
+            "nonce":"0x9","gasPrice":"0x4a817c800","gas":"0x5208",
+            "to":"0x3535353535353535353535353535353535353535","value":"0xde0b6b3a7640000",
+            "input":"0x","v":"0x25",
+            "r":"0x28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276",
+            "s":"0x67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"}]}}"#;
+        let url = serve_responses(vec![block.to_string(), block.replace("0x9", "0xa")]).await;
+        let rpc_provider =
+            RpcProvider::new_with_config(url.into(), 1, &ProviderConfig::default()).unwrap();
+
+        let transactions = rpc_provider.get_block_transactions(1).await.unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            keccak256(&transactions[0]).to_string(),
+            "0x33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788"
+        );
+        // a transaction whose fields don't hash to its hash is refused
+        let error = rpc_provider.get_block_transactions(1).await.unwrap_err();
+        assert!(error.to_string().contains("doesn't encode to its hash"));
+    }
+
     // Non-paid personal alchemy endpoint
     const SEPOLIA_RPC_URL: &str =
         "https://eth-sepolia.g.alchemy.com/v2/a-w72ZvoUS0dfMD_LBPAuRzHOlQEhi_m";
@@ -276,7 +592,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_block_by_number() {
-        let rpc_provider = RpcProvider::new(SEPOLIA_RPC_URL, 11155111);
+        let rpc_provider = RpcProvider::new_with_config(
+            SEPOLIA_RPC_URL.parse().unwrap(),
+            11155111,
+            &ProviderConfig::default(),
+        )
+        .unwrap();
 
         let block = rpc_provider.get_block_by_number(0).await.unwrap();
         let block_header = Header::from(&block);
@@ -293,7 +614,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_rpc_get_proof() {
-        let rpc_provider = RpcProvider::new(SEPOLIA_RPC_URL, 11155111);
+        let rpc_provider = RpcProvider::new_with_config(
+            SEPOLIA_RPC_URL.parse().unwrap(),
+            11155111,
+            &ProviderConfig::default(),
+        )
+        .unwrap();
 
         let account_from_rpc = rpc_provider
             .get_proof(4952229, SEPOLIA_TARGET_ADDRESS, None)
@@ -0,0 +1,189 @@
+//! Retry and rate limiting primitives shared by the RPC and indexer clients.
+
+use std::{sync::Mutex, time::Duration};
+
+use rand::Rng;
+use reqwest::StatusCode;
+use tokio::time::Instant;
+
+use super::config::ProviderConfig;
+
+/// [`RetryPolicy`] decides how long to wait before retrying a failed request.
+///
+/// The backoff grows exponentially from `initial_backoff` up to `max_backoff`,
+/// and half of it is randomized so that concurrent requests don't retry in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl From<&ProviderConfig> for RetryPolicy {
+    fn from(config: &ProviderConfig) -> Self {
+        Self {
+            max_retries: config.max_retries,
+            initial_backoff: config.initial_backoff,
+            max_backoff: config.max_backoff,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff of the given attempt (0-based), without jitter
+    fn exponential_backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+
+    /// Backoff of the given attempt (0-based), with jitter applied to half of it
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let half = self.exponential_backoff(attempt) / 2;
+        let jitter_millis = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter_millis)
+    }
+}
+
+/// Whether a response with the given status is worth retrying
+pub(crate) fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+/// Whether a failed request (no response received) is worth retrying
+pub(crate) fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_timeout() || error.is_connect() || error.is_request()
+}
+
+/// Whether a JSON-RPC error, sent in a successful HTTP response, is worth retrying.
+///
+/// Several providers report rate limiting this way, e.g. `-32005` (limit exceeded) or `429`,
+/// and the codes of timeouts vary, so the message is checked as well.
+pub(crate) fn is_retryable_rpc_error(code: i64, message: &str) -> bool {
+    let message = message.to_lowercase();
+    matches!(code, 429 | -32005 | -32007)
+        || ["rate limit", "too many requests", "timeout", "timed out"]
+            .iter()
+            .any(|pattern| message.contains(pattern))
+}
+
+/// [`RateLimiter`] is a token bucket that allows `requests_per_second` requests per second
+/// with bursts up to the same amount.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    requests_per_second: f64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: u32) -> Self {
+        let requests_per_second = f64::from(requests_per_second.max(1));
+        Self {
+            requests_per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: requests_per_second,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take one token, or return how long to wait until one is available
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens =
+            (bucket.tokens + elapsed * self.requests_per_second).min(self.requests_per_second);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.requests_per_second,
+            ))
+        }
+    }
+
+    /// Wait until a request is allowed to be sent
+    pub async fn acquire(&self) {
+        while let Err(wait) = self.try_acquire() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_is_bounded() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        };
+
+        assert_eq!(policy.exponential_backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.exponential_backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.exponential_backoff(3), Duration::from_millis(800));
+        assert_eq!(policy.exponential_backoff(5), Duration::from_secs(2));
+        assert_eq!(policy.exponential_backoff(u32::MAX), Duration::from_secs(2));
+
+        for attempt in 0..10 {
+            let base = policy.exponential_backoff(attempt);
+            let backoff = policy.backoff(attempt);
+            assert!(backoff >= base / 2);
+            assert!(backoff <= base);
+        }
+    }
+
+    #[test]
+    fn test_retryable_status() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST));
+        assert!(!is_retryable_status(StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn test_retryable_rpc_error() {
+        assert!(is_retryable_rpc_error(
+            -32005,
+            "daily request count exceeded"
+        ));
+        assert!(is_retryable_rpc_error(
+            429,
+            "Your app has exceeded its compute units"
+        ));
+        assert!(is_retryable_rpc_error(-32603, "request timed out"));
+        assert!(!is_retryable_rpc_error(-32602, "invalid argument 0"));
+        assert!(!is_retryable_rpc_error(-32000, "header not found"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(10);
+        let start = Instant::now();
+        // the bucket starts full
+        for _ in 0..10 {
+            limiter.acquire().await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        // the next request has to wait for one token to be refilled
+        limiter.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(start.elapsed() < Duration::from_millis(110));
+    }
+}